@@ -0,0 +1,37 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{AccelerateConfiguration, BucketAccelerateStatus};
+use clap::Subcommand;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum AccelerateAction {
+    /// show whether Transfer Acceleration is on
+    Get,
+    /// turn Transfer Acceleration on, so --accelerate can be used with the bucket
+    Enable,
+    /// turn Transfer Acceleration off
+    Suspend,
+}
+
+pub async fn run(client: &Client, bucket_name: &str, action: &AccelerateAction) -> Result<(), Box<dyn Error>> {
+    let status = match action {
+        AccelerateAction::Get => {
+            let result = client.get_bucket_accelerate_configuration().bucket(bucket_name).send().await?;
+            // buckets that have never had it turned on report no status
+            println!("transfer acceleration: {}", result.status().map(|s| s.as_str()).unwrap_or("never enabled"));
+            return Ok(());
+        }
+        AccelerateAction::Enable => BucketAccelerateStatus::Enabled,
+        AccelerateAction::Suspend => BucketAccelerateStatus::Suspended,
+    };
+    if status == BucketAccelerateStatus::Enabled && bucket_name.contains('.') {
+        return Err(format!("{} has a dot in its name, which Transfer Acceleration doesn't support", bucket_name).into());
+    }
+    client.put_bucket_accelerate_configuration()
+        .bucket(bucket_name)
+        .accelerate_configuration(AccelerateConfiguration::builder().status(status.clone()).build())
+        .send()
+        .await?;
+    println!("transfer acceleration of {}: {}", bucket_name, status.as_str());
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{Grantee, ObjectCannedAcl};
+use clap::builder::PossibleValuesParser;
+use crate::payer;
+
+/// what a grant allows. objects have no write permission of their own
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permission {
+    Read,
+    ReadAcp,
+    WriteAcp,
+    FullControl,
+}
+
+/// a --grant: PERMISSION=TYPE=VALUE, e.g. read=id=<canonical id> or read=uri=http://acs.amazonaws.com/groups/global/AllUsers
+#[derive(Clone, Debug)]
+pub struct Grant {
+    pub permission: Permission,
+    /// in the form the x-amz-grant-* headers take, e.g. id="abc"
+    pub grantee: String,
+}
+
+/// accepts the canned ACLs the SDK knows
+pub fn canned_acl_parser() -> PossibleValuesParser {
+    PossibleValuesParser::new(ObjectCannedAcl::values().iter().copied())
+}
+
+pub fn parse_grant(value: &str) -> Result<Grant, String> {
+    let (permission, grantee) = value.split_once('=').ok_or("expected PERMISSION=TYPE=VALUE")?;
+    let permission = match permission {
+        "read" => Permission::Read,
+        "read-acp" => Permission::ReadAcp,
+        "write-acp" => Permission::WriteAcp,
+        "full-control" => Permission::FullControl,
+        _ => return Err(format!("unknown permission {:?}. expected read, read-acp, write-acp or full-control", permission)),
+    };
+    let (kind, id) = grantee.split_once('=').ok_or("expected the grantee as TYPE=VALUE")?;
+    if !["id", "uri", "emailAddress"].contains(&kind) {
+        return Err(format!("unknown grantee type {:?}. expected id, uri or emailAddress", kind));
+    }
+    Ok(Grant { permission, grantee: format!("{}=\"{}\"", kind, id) })
+}
+
+fn describe(grantee: &Grantee) -> String {
+    let name = grantee.id().or(grantee.uri()).or(grantee.email_address()).unwrap_or("<unknown>");
+    match grantee.display_name() {
+        Some(display_name) => format!("{} ({})", name, display_name),
+        None => name.to_string(),
+    }
+}
+
+pub async fn get(client: &Client, bucket_name: &str, key: &str, version_id: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let result = client.get_object_acl()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .set_version_id(version_id.map(str::to_string))
+        .send()
+        .await?;
+    if let Some(owner) = result.owner() {
+        println!("owner: {}", owner.display_name().or(owner.id()).unwrap_or("<unknown>"));
+    }
+    let grants = result.grants().unwrap_or_default();
+    if grants.is_empty() {
+        println!("no grants");
+    }
+    for grant in grants {
+        let permission = grant.permission().map(|p| p.as_str()).unwrap_or("<unknown>");
+        println!("{}: {}", permission, grant.grantee().map(describe).unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// replaces key's ACL with canned_acl, or with exactly grants
+pub async fn put(client: &Client, bucket_name: &str, key: &str, version_id: Option<&str>, canned_acl: Option<&str>, grants: &[Grant]) -> Result<(), Box<dyn Error>> {
+    // each header lists every grantee with that permission
+    let header = |permission: Permission| {
+        let grantees: Vec<&str> = grants.iter()
+            .filter(|grant| grant.permission == permission)
+            .map(|grant| grant.grantee.as_str())
+            .collect();
+        if grantees.is_empty() { None } else { Some(grantees.join(", ")) }
+    };
+    client.put_object_acl()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .set_version_id(version_id.map(str::to_string))
+        .set_acl(canned_acl.map(ObjectCannedAcl::from))
+        .set_grant_read(header(Permission::Read))
+        .set_grant_read_acp(header(Permission::ReadAcp))
+        .set_grant_write_acp(header(Permission::WriteAcp))
+        .set_grant_full_control(header(Permission::FullControl))
+        .send()
+        .await?;
+    println!("updated the ACL of {}", key);
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use std::task::{Context, Poll};
+use aws_credential_types::Credentials;
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use http::header::AUTHORIZATION;
+use http::{Request, Response};
+use hyper::service::Service;
+
+/// S3 operations refuse to run without credentials, so anonymous requests are signed with these
+/// and have the signature removed on the way out
+pub fn placeholder_credentials() -> Credentials {
+    Credentials::from_keys("anonymous", "anonymous", None)
+}
+
+/// strips the signature from every request, for --no-sign-request
+#[derive(Clone)]
+pub struct Unsigned {
+    inner: DynConnector,
+}
+
+impl Unsigned {
+    pub fn wrap(inner: DynConnector) -> DynConnector {
+        DynConnector::new(Unsigned { inner })
+    }
+}
+
+impl Service<Request<SdkBody>> for Unsigned {
+    type Response = Response<SdkBody>;
+    type Error = ConnectorError;
+    type Future = <DynConnector as Service<Request<SdkBody>>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ConnectorError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<SdkBody>) -> Self::Future {
+        request.headers_mut().remove(AUTHORIZATION);
+        request.headers_mut().remove("x-amz-security-token");
+        self.inner.call(request)
+    }
+}
@@ -0,0 +1,204 @@
+use std::error::Error;
+use std::path::Path;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use crate::copy::tagging;
+use crate::download;
+use crate::key;
+use crate::migrate::{PendingUpload, MAX_PARTS, MIN_PART_SIZE};
+use crate::payer;
+use crate::progress::Progress;
+use crate::stop;
+use crate::summary::{self, Outcome};
+
+/// bytes of new data in each uploaded part, held in memory one part at a time
+const PART_SIZE: u64 = 64 * 1024 * 1024;
+
+/// the new data, from a file or stdin
+pub enum Source<'a> {
+    File(&'a Path),
+    Stdin,
+}
+
+async fn read_part(reader: &mut (dyn AsyncRead + Unpin + Send), buffer: &mut Vec<u8>, size: u64) -> Result<(), Box<dyn Error>> {
+    (&mut *reader).take(size).read_to_end(buffer).await?;
+    Ok(())
+}
+
+/// the ranges of an existing object of size bytes to copy as parts, as even as they can be while
+/// each stays under what one copy can take
+fn copy_ranges(size: u64) -> Vec<(u64, u64)> {
+    let count = size.div_ceil(key::MAX_COPY_SIZE as u64).max(1);
+    let part = size.div_ceil(count);
+    (0..size).step_by(part as usize).map(|start| (start, (start + part).min(size) - 1)).collect()
+}
+
+/// the existing object at name, or None if there isn't one
+async fn head(client: &Client, bucket_name: &str, name: &str) -> Result<Option<HeadObjectOutput>, Box<dyn Error>> {
+    match client.head_object().bucket(bucket_name).set_request_payer(payer::get()).key(name).send().await {
+        Ok(head) => Ok(Some(head)),
+        Err(SdkError::ServiceError(err)) if err.err().is_not_found() => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// adds source to the end of name as a multipart upload whose first parts are copies of the
+/// existing object, made by S3 without downloading it. an object smaller than the smallest part
+/// S3 allows can't be copied as one, so it's downloaded and sent with the new data instead. the
+/// object keeps its content headers, metadata, tags and storage class. a new object gets a content
+/// type guessed from name
+///
+/// the copy is of the version there when the append starts. anything written to name while
+/// the append runs is replaced
+pub async fn append(client: &Client, bucket_name: &str, name: &str, source: Source<'_>) -> Result<(), Box<dyn Error>> {
+    key::validate(name)?;
+    let (mut reader, new_size): (Box<dyn AsyncRead + Unpin + Send>, Option<u64>) = match source {
+        Source::File(path) => {
+            let file = tokio::fs::File::open(path).await?;
+            let size = file.metadata().await?.len();
+            (Box::new(file), Some(size))
+        }
+        Source::Stdin => (Box::new(tokio::io::stdin()), None),
+    };
+    let existing = head(client, bucket_name, name).await?;
+    if let Some(head) = &existing {
+        if !download::is_plain(head, false) {
+            return Err(format!("{} is compressed or encrypted, so appending bytes would corrupt it", name).into());
+        }
+    }
+    let existing_size = existing.as_ref().map_or(0, |head| head.content_length().max(0) as u64);
+    let copied = if existing_size >= MIN_PART_SIZE { copy_ranges(existing_size) } else { Vec::new() };
+    let part_size = match new_size {
+        Some(size) => PART_SIZE.max(size.div_ceil(MAX_PARTS - copied.len() as u64)),
+        None => PART_SIZE,
+    };
+
+    // a small existing object goes at the start of the first part
+    let mut first = Vec::with_capacity(part_size as usize);
+    if existing.is_some() && copied.is_empty() && existing_size > 0 {
+        let object = client.get_object()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .key(name)
+            .set_version_id(existing.as_ref().and_then(|head| head.version_id()).map(str::to_string))
+            .send()
+            .await?;
+        first = object.body.collect().await?.into_bytes().to_vec();
+    }
+    let prefix_len = first.len();
+    read_part(&mut reader, &mut first, part_size).await?;
+    if first.len() == prefix_len {
+        println!("nothing to append");
+        summary::report("append", name, Outcome::NoOp);
+        return Ok(());
+    }
+
+    let tags = match &existing {
+        Some(head) => {
+            let result = client.get_object_tagging()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(name)
+                .set_version_id(head.version_id().map(str::to_string))
+                .send()
+                .await?;
+            let tags: Vec<(String, String)> = result.tag_set().unwrap_or_default().iter()
+                .filter_map(|tag| Some((tag.key()?.to_string(), tag.value()?.to_string())))
+                .collect();
+            Some(tagging(&tags)).filter(|_| !tags.is_empty())
+        }
+        None => None,
+    };
+    let created = client.create_multipart_upload()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(name)
+        .set_metadata(existing.as_ref().and_then(|head| head.metadata().cloned()))
+        .content_type(match existing.as_ref().and_then(|head| head.content_type()) {
+            Some(content_type) => content_type.to_string(),
+            None => mime_guess::from_path(name).first_or_octet_stream().to_string(),
+        })
+        .set_content_disposition(existing.as_ref().and_then(|head| head.content_disposition()).map(str::to_string))
+        .set_content_language(existing.as_ref().and_then(|head| head.content_language()).map(str::to_string))
+        .set_cache_control(existing.as_ref().and_then(|head| head.cache_control()).map(str::to_string))
+        .set_expires(existing.as_ref().and_then(|head| head.expires()).cloned())
+        .set_storage_class(existing.as_ref().and_then(|head| head.storage_class()).cloned())
+        .set_tagging(tags)
+        .send()
+        .await?;
+    let mut upload = PendingUpload {
+        client,
+        bucket_name,
+        key: name,
+        upload_id: created.upload_id().ok_or("no upload id in the response")?.to_string(),
+        completed: false,
+    };
+
+    let mut parts = Vec::new();
+    if let Some(head) = existing.as_ref().filter(|_| !copied.is_empty()) {
+        // pinned to the version, or its etag, as download::range_get pins the ranges it reads
+        let copy_source = key::copy_source(bucket_name, name, head.version_id());
+        for (start, end) in &copied {
+            let number = parts.len() as i32 + 1;
+            let result = stop::within(client.upload_part_copy()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(name)
+                .upload_id(&upload.upload_id)
+                .part_number(number)
+                .copy_source(&copy_source)
+                .set_copy_source_if_match(head.e_tag().filter(|_| head.version_id().is_none()).map(str::to_string))
+                .copy_source_range(format!("bytes={}-{}", start, end))
+                .send()).await??;
+            let e_tag = result.copy_part_result().and_then(|part| part.e_tag()).map(str::to_string);
+            parts.push(CompletedPart::builder().part_number(number).set_e_tag(e_tag).build());
+        }
+    }
+
+    let mut progress = match new_size {
+        Some(size) => Progress::bytes("appending", name, size),
+        None => Progress::counter("appending"),
+    };
+    let mut appended = (first.len() - prefix_len) as u64;
+    let mut buffer = first;
+    loop {
+        let number = parts.len() as i32 + 1;
+        let sent = buffer.len() as u64;
+        let result = stop::within(client.upload_part()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .key(name)
+            .upload_id(&upload.upload_id)
+            .part_number(number)
+            .body(ByteStream::from(std::mem::take(&mut buffer)))
+            .send()).await??;
+        parts.push(CompletedPart::builder().part_number(number).set_e_tag(result.e_tag().map(str::to_string)).build());
+        progress.add(sent);
+        read_part(&mut reader, &mut buffer, part_size).await?;
+        if buffer.is_empty() {
+            break;
+        }
+        if parts.len() as u64 == MAX_PARTS {
+            return Err(format!("more than {} parts of {} bytes to append", MAX_PARTS, part_size).into());
+        }
+        appended += buffer.len() as u64;
+    }
+    progress.finish();
+    let result = client.complete_multipart_upload()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(name)
+        .upload_id(&upload.upload_id)
+        .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+        .send()
+        .await?;
+    upload.completed = true;
+    println!("appended {} bytes to {}, now {} bytes", appended, name, existing_size + appended);
+    println!("put version: {}", result.version_id().unwrap_or("null"));
+    summary::report("append", name, Outcome::Changed);
+    Ok(())
+}
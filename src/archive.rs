@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::ChecksumAlgorithm;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use futures_util::TryStreamExt;
+use crate::download::relative_path;
+use crate::key;
+use crate::listing::object_pages;
+use crate::payer;
+
+fn is_gzip(path: &str) -> bool {
+    path.ends_with(".gz") || path.ends_with(".tgz")
+}
+
+/// writes every object under prefix into a tar archive at output, gzipped if output ends in .gz or .tgz.
+/// objects are fetched one at a time and appended straight to the archive
+pub async fn archive(client: &Client, bucket_name: &str, prefix: &str, output: &str) -> Result<(), Box<dyn Error>> {
+    let file = File::create(output)?;
+    let count = if is_gzip(output) {
+        let mut builder = tar::Builder::new(GzEncoder::new(file, flate2::Compression::default()));
+        let count = append_objects(client, bucket_name, prefix, &mut builder).await?;
+        builder.into_inner()?.finish()?;
+        count
+    } else {
+        let mut builder = tar::Builder::new(file);
+        let count = append_objects(client, bucket_name, prefix, &mut builder).await?;
+        builder.into_inner()?.flush()?;
+        count
+    };
+    println!("wrote {} objects to {}", count, output);
+    Ok(())
+}
+
+async fn append_objects<W: Write>(client: &Client, bucket_name: &str, prefix: &str, builder: &mut tar::Builder<W>) -> Result<usize, Box<dyn Error>> {
+    let mut count = 0;
+    // tar entries go in listing order, so objects are fetched one at a time as each page arrives
+    let mut pages = pin!(object_pages(client, bucket_name, prefix));
+    while let Some(page) = pages.try_next().await? {
+        for object in page {
+            let Some(key) = object.key() else { continue };
+            if key.ends_with('/') {
+                continue;
+            }
+            // tar readers extract entries as local paths, so they get the same checks as downloads
+            let path = match key::local_relative(relative_path(prefix, key)) {
+                Ok(path) => path,
+                Err(err) => {
+                    println!("skipping {}: {}", key, err);
+                    continue;
+                }
+            };
+            let result = client.get_object()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(key)
+                .send()
+                .await?;
+            let bytes = result.body.collect().await?.into_bytes();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(bytes.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(object.last_modified().map(|d| d.secs().max(0) as u64).unwrap_or(0));
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_cksum();
+            builder.append_data(&mut header, path, &bytes[..])?;
+            println!("archived: {}", key);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// uploads each regular file in a tar archive (gzipped if it ends in .gz or .tgz) as prefix + its path
+pub async fn unarchive(client: &Client, bucket_name: &str, archive_path: &str, prefix: &str) -> Result<(), Box<dyn Error>> {
+    let file = File::open(archive_path)?;
+    let reader: Box<dyn Read> = if is_gzip(archive_path) {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut archive = tar::Archive::new(reader);
+    let mut count = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().replace('\\', "/");
+        let key = format!("{}{}", prefix, path.trim_start_matches("./"));
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        let result = client.put_object()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .key(&key)
+            .content_type(mime_guess::from_path(&path).first_or_octet_stream().to_string())
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .body(ByteStream::from(bytes))
+            .send()
+            .await?;
+        println!("put {}: {}", key, result.version_id().unwrap_or("null"));
+        count += 1;
+    }
+    println!("uploaded {} objects from {}", count, archive_path);
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::fmt;
+
+/// an S3 Access Point or Outposts access point ARN given in place of a bucket name.
+/// the SDK resolves the endpoint from it, so this is only used to check it and pick the region
+#[derive(Clone, Debug)]
+pub struct BucketArn {
+    pub region: String,
+    pub outpost: Option<String>,
+    pub access_point: String,
+}
+
+impl BucketArn {
+    /// None if bucket isn't an ARN at all
+    pub fn parse(bucket: &str) -> Result<Option<BucketArn>, Box<dyn Error>> {
+        if !bucket.starts_with("arn:") {
+            return Ok(None);
+        }
+        let invalid = |why: &str| -> Box<dyn Error> { format!("invalid bucket ARN {}: {}", bucket, why).into() };
+        let parts: Vec<&str> = bucket.splitn(6, ':').collect();
+        let [_, partition, service, region, account, resource] = parts[..] else {
+            return Err(invalid("expected arn:partition:service:region:account:resource"));
+        };
+        if partition.is_empty() || account.is_empty() {
+            return Err(invalid("missing partition or account"));
+        }
+        let resource: Vec<&str> = resource.split(['/', ':']).collect();
+        let (outpost, access_point) = match (service, &resource[..]) {
+            ("s3", ["accesspoint", _]) if region.is_empty() =>
+                return Err(invalid("multi-region access points need SigV4a signing, which isn't supported")),
+            ("s3", ["accesspoint", name]) => (None, name),
+            ("s3-outposts", ["outpost", outpost, "accesspoint", name]) => (Some(outpost.to_string()), name),
+            ("s3", _) => return Err(invalid("expected an accesspoint/NAME resource")),
+            ("s3-outposts", _) => return Err(invalid("expected an outpost/ID/accesspoint/NAME resource")),
+            _ => return Err(invalid("only s3 access point and s3-outposts ARNs can be used as a bucket")),
+        };
+        if region.is_empty() || access_point.is_empty() {
+            return Err(invalid("missing region or access point name"));
+        }
+        Ok(Some(BucketArn {
+            region: region.to_string(),
+            outpost,
+            access_point: access_point.to_string(),
+        }))
+    }
+}
+
+impl fmt::Display for BucketArn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outpost {
+            Some(outpost) => write!(f, "access point {} on outpost {} in {}", self.access_point, outpost, self.region),
+            None => write!(f, "access point {} in {}", self.access_point, self.region),
+        }
+    }
+}
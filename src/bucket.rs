@@ -0,0 +1,335 @@
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::config::Builder;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Error;
+use aws_sdk_s3::primitives::DateTimeFormat;
+use aws_sdk_s3::types::{Bucket, BucketCannedAcl, BucketLocationConstraint, BucketVersioningStatus, CreateBucketConfiguration, ObjectOwnership,
+                        ServerSideEncryption, ServerSideEncryptionByDefault, ServerSideEncryptionConfiguration,
+                        ServerSideEncryptionRule, VersioningConfiguration};
+use clap::{Args, ValueEnum};
+use clap::builder::PossibleValuesParser;
+use futures_util::{stream, StreamExt};
+use crate::delete::{self, RmOptions};
+use crate::payer;
+use crate::progress::Progress;
+use crate::region;
+use crate::report::format_bytes;
+use crate::summary::{self, Outcome};
+
+/// exit status when the bucket exists but can't be accessed with these credentials
+pub const EXIT_FORBIDDEN: i32 = 3;
+/// exit status when there's no bucket by that name
+pub const EXIT_NOT_FOUND: i32 = 4;
+
+/// what a HeadBucket found
+pub enum Probe {
+    Accessible,
+    /// in another region than the configured one, which is still accessible
+    Elsewhere(String),
+    Forbidden,
+    NotFound,
+}
+
+impl Probe {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Probe::Accessible | Probe::Elsewhere(_) => 0,
+            Probe::Forbidden => EXIT_FORBIDDEN,
+            Probe::NotFound => EXIT_NOT_FOUND,
+        }
+    }
+}
+
+/// whether bucket_name exists and can be used. HeadBucket responses have no body, so the
+/// status code is all there is to go on
+pub async fn probe(client: &Client, bucket_name: &str) -> Result<Probe, Box<dyn Error>> {
+    let err = match client.head_bucket().bucket(bucket_name).send().await {
+        Ok(_) => return Ok(Probe::Accessible),
+        Err(err) => err,
+    };
+    if let Some(region) = region::redirect_hint(&err, client.conf().region()) {
+        return Ok(Probe::Elsewhere(region.to_string()));
+    }
+    match &err {
+        SdkError::ServiceError(service) => match service.raw().http().status().as_u16() {
+            403 => Ok(Probe::Forbidden),
+            404 => Ok(Probe::NotFound),
+            _ => Err(err.into()),
+        },
+        _ => Err(err.into()),
+    }
+}
+
+/// prints what probe found and returns the exit status to report it with
+pub async fn exists(client: &Client, bucket_name: &str) -> Result<i32, Box<dyn Error>> {
+    let probe = probe(client, bucket_name).await?;
+    match &probe {
+        Probe::Accessible => println!("{} exists and is accessible", bucket_name),
+        Probe::Elsewhere(region) => println!("{} exists and is accessible in {}", bucket_name, region),
+        Probe::Forbidden => println!("{} exists, but access is denied", bucket_name),
+        Probe::NotFound => println!("{} doesn't exist", bucket_name),
+    }
+    Ok(probe.exit_code())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum DefaultEncryption {
+    /// SSE-S3, with keys S3 manages
+    SseS3,
+    /// SSE-KMS, with --kms-key-id or the account's aws/s3 key
+    SseKms,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CreateArgs {
+    #[arg(long, help = "enable Object Lock, which also enables versioning. it can't be turned off")]
+    object_lock: bool,
+    #[arg(long, help = "enable versioning, which the other commands need")]
+    versioning: bool,
+    #[arg(long, value_parser = PossibleValuesParser::new(BucketCannedAcl::values().iter().copied()), help = "canned ACL for the bucket. ACLs other than private also enable ACLs on it")]
+    acl: Option<String>,
+    #[arg(long, value_enum, help = "default encryption for new objects")]
+    encryption: Option<DefaultEncryption>,
+    #[arg(long, value_name = "KEY", requires = "encryption", help = "KMS key id or ARN for sse-kms")]
+    kms_key_id: Option<String>,
+    #[arg(long, help = "succeed if the bucket already exists and is ours, applying --versioning and --encryption to it")]
+    if_not_exists: bool,
+}
+
+/// the configuration that creates a bucket in region. us-east-1 is the one region that mustn't be named
+fn location(region: &str) -> Option<CreateBucketConfiguration> {
+    (region != "us-east-1").then(|| CreateBucketConfiguration::builder()
+        .location_constraint(BucketLocationConstraint::from(region))
+        .build())
+}
+
+/// creates a bucket with nothing but a region
+pub async fn create_in(client: &Client, bucket_name: &str, region: &str) -> Result<(), Box<dyn Error>> {
+    client.create_bucket()
+        .bucket(bucket_name)
+        .set_create_bucket_configuration(location(region))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// creates bucket_name in the client's region, then applies the versioning and encryption
+/// settings, so a repeated run with if_not_exists leaves the bucket the same way. versioning
+/// that's already enabled is left alone, so a rerun that changes nothing reports a no-op
+pub async fn create(client: &Client, bucket_name: &str, args: &CreateArgs) -> Result<(), Box<dyn Error>> {
+    if args.kms_key_id.is_some() && args.encryption != Some(DefaultEncryption::SseKms) {
+        return Err("--kms-key-id needs --encryption sse-kms".into());
+    }
+    let region = client.conf().region().map(|r| r.to_string()).ok_or("no region is set")?;
+    let exists = if args.if_not_exists {
+        match probe(client, bucket_name).await? {
+            Probe::Accessible => true,
+            Probe::Elsewhere(other) => return Err(format!("{} already exists in {}, not {}", bucket_name, other, region).into()),
+            Probe::Forbidden => return Err(format!("{} already exists and belongs to someone else", bucket_name).into()),
+            Probe::NotFound => false,
+        }
+    } else {
+        false
+    };
+    let mut changed = !exists;
+    if exists {
+        println!("{} already exists", bucket_name);
+        if args.object_lock || args.acl.is_some() {
+            eprintln!("note: --object-lock and --acl only apply when the bucket is created");
+        }
+    } else {
+        // new buckets have ACLs disabled, which rejects any ACL but private
+        let ownership = args.acl.as_deref()
+            .filter(|acl| *acl != "private")
+            .map(|_| ObjectOwnership::BucketOwnerPreferred);
+        let result = client.create_bucket()
+            .bucket(bucket_name)
+            .set_create_bucket_configuration(location(&region))
+            .set_object_lock_enabled_for_bucket(args.object_lock.then_some(true))
+            .set_acl(args.acl.as_deref().map(BucketCannedAcl::from))
+            .set_object_ownership(ownership)
+            .send()
+            .await;
+        match result {
+            Ok(_) => println!("created {} in {}", bucket_name, region),
+            // created by an earlier run that lost the race with this one's probe
+            Err(SdkError::ServiceError(err)) if args.if_not_exists && err.err().code() == Some("BucketAlreadyOwnedByYou") => {
+                println!("{} already exists", bucket_name);
+                changed = false;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let enabled = args.versioning && !changed
+        && client.get_bucket_versioning().bucket(bucket_name).send().await?.status() == Some(&BucketVersioningStatus::Enabled);
+    if args.versioning && !enabled {
+        changed = true;
+        client.put_bucket_versioning()
+            .bucket(bucket_name)
+            .versioning_configuration(VersioningConfiguration::builder().status(BucketVersioningStatus::Enabled).build())
+            .send()
+            .await?;
+        println!("enabled versioning");
+    }
+    if let Some(encryption) = args.encryption {
+        changed = true;
+        let algorithm = match encryption {
+            DefaultEncryption::SseS3 => ServerSideEncryption::Aes256,
+            DefaultEncryption::SseKms => ServerSideEncryption::AwsKms,
+        };
+        let default = ServerSideEncryptionByDefault::builder()
+            .sse_algorithm(algorithm.clone())
+            .set_kms_master_key_id(args.kms_key_id.clone())
+            .build();
+        let configuration = ServerSideEncryptionConfiguration::builder()
+            .rules(ServerSideEncryptionRule::builder().apply_server_side_encryption_by_default(default).build())
+            .build();
+        client.put_bucket_encryption()
+            .bucket(bucket_name)
+            .server_side_encryption_configuration(configuration)
+            .send()
+            .await?;
+        println!("set default encryption to {}", algorithm.as_str());
+    }
+    summary::report("create-bucket", bucket_name, if changed { Outcome::Changed } else { Outcome::NoOp });
+    Ok(())
+}
+
+/// aborts every incomplete multipart upload in the bucket, returning how many there were
+async fn abort_uploads(client: &Client, bucket_name: &str) -> Result<usize, Box<dyn Error>> {
+    let mut progress = Progress::counter("aborting uploads");
+    let mut key_marker = None;
+    let mut upload_id_marker = None;
+    let mut aborted = 0;
+    loop {
+        let result = client.list_multipart_uploads()
+            .bucket(bucket_name)
+            .set_key_marker(key_marker)
+            .set_upload_id_marker(upload_id_marker)
+            .send()
+            .await?;
+        for upload in result.uploads().unwrap_or_default() {
+            let (Some(key), Some(upload_id)) = (upload.key(), upload.upload_id()) else { continue };
+            client.abort_multipart_upload()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(key)
+                .upload_id(upload_id)
+                .send()
+                .await?;
+            aborted += 1;
+            progress.add(1);
+        }
+        key_marker = result.next_key_marker().map(str::to_string);
+        upload_id_marker = result.next_upload_id_marker().map(str::to_string);
+        if !result.is_truncated() || key_marker.is_none() {
+            break;
+        }
+    }
+    progress.finish();
+    Ok(aborted)
+}
+
+/// permanently deletes everything in bucket_name, then the bucket. without yes, the bucket's
+/// name has to be typed to confirm
+pub async fn nuke(client: &Client, bucket_name: &str, yes: bool) -> Result<(), Box<dyn Error>> {
+    if !yes {
+        print!("this permanently deletes every version in {} and then the bucket. type its name to confirm: ", bucket_name);
+        io::stdout().flush()?;
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line)?;
+        if line.trim() != bucket_name {
+            return Err("not confirmed, nothing was deleted".into());
+        }
+    }
+    let options = RmOptions { all_versions: true, concurrency: 4, failures_out: None, ignore_missing: false };
+    delete::rm(client, bucket_name, "", &options).await?;
+    let aborted = abort_uploads(client, bucket_name).await?;
+    println!("aborted {} incomplete uploads", aborted);
+    client.delete_bucket().bucket(bucket_name).send().await?;
+    println!("deleted {}", bucket_name);
+    Ok(())
+}
+
+/// the number and bytes of the current objects in bucket_name, with the error typed so a
+/// redirect to the bucket's region can be spotted
+async fn usage(client: &Client, bucket_name: &str) -> Result<(usize, i64), SdkError<ListObjectsV2Error>> {
+    let mut count = 0;
+    let mut bytes = 0;
+    let mut token = None;
+    loop {
+        let result = client.list_objects_v2()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .set_continuation_token(token)
+            .send()
+            .await?;
+        for object in result.contents().unwrap_or_default() {
+            count += 1;
+            bytes += object.size();
+        }
+        token = result.next_continuation_token;
+        if !result.is_truncated || token.is_none() {
+            return Ok((count, bytes));
+        }
+    }
+}
+
+/// usage for a bucket that may be in another region than config's, which ListBuckets doesn't say
+async fn usage_anywhere(client: &Client, config: &Builder, bucket_name: &str) -> Result<(usize, i64), Box<dyn Error>> {
+    match usage(client, bucket_name).await {
+        Ok(usage) => Ok(usage),
+        Err(err) => {
+            let Some(region) = region::redirect_hint(&err, client.conf().region()) else { return Err(err.into()) };
+            let regional = Client::from_conf(config.clone().region(region).build());
+            Ok(usage(&regional, bucket_name).await?)
+        }
+    }
+}
+
+/// prints the buckets the credentials own whose names start with prefix, with when they were
+/// created. with_usage also lists every bucket, concurrency at a time, to count its objects
+pub async fn list(client: &Client, config: &Builder, prefix: &str, with_usage: bool, concurrency: usize) -> Result<(), Box<dyn Error>> {
+    let result = client.list_buckets().send().await?;
+    let buckets: Vec<&Bucket> = result.buckets().unwrap_or_default().iter()
+        .filter(|bucket| bucket.name().unwrap_or_default().starts_with(prefix))
+        .collect();
+    if buckets.is_empty() {
+        println!("no buckets");
+        return Ok(());
+    }
+    let created = |bucket: &Bucket| bucket.creation_date()
+        .and_then(|date| date.fmt(DateTimeFormat::DateTime).ok())
+        .unwrap_or_default();
+    if !with_usage {
+        for bucket in &buckets {
+            println!("{}  {}", bucket.name().unwrap_or_default(), created(bucket));
+        }
+        return Ok(());
+    }
+    // listed in order, so the output stays sorted by name
+    let mut results = stream::iter(&buckets)
+        .map(|bucket| async move {
+            let name = bucket.name().unwrap_or_default();
+            (*bucket, usage_anywhere(client, config, name).await)
+        })
+        .buffered(concurrency);
+    let mut total_count = 0;
+    let mut total_bytes = 0;
+    while let Some((bucket, result)) = results.next().await {
+        let name = bucket.name().unwrap_or_default();
+        match result {
+            Ok((count, bytes)) => {
+                println!("{}  {}  {} objects ({})", name, created(bucket), count, format_bytes(bytes));
+                total_count += count;
+                total_bytes += bytes;
+            }
+            // one bucket the credentials can't list shouldn't hide the rest
+            Err(err) => println!("{}  {}  can't count objects: {}", name, created(bucket), err),
+        }
+    }
+    println!("total: {} buckets, {} objects ({})", buckets.len(), total_count, format_bytes(total_bytes));
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::types::{Tag, Tagging};
+use clap::Subcommand;
+use crate::copy::parse_pair;
+use crate::errors::Exit;
+
+/// the most tags a bucket can have
+const MAX_TAGS: usize = 50;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum TagsAction {
+    /// list the bucket's tags
+    Get,
+    /// replace the bucket's tags with these
+    Set {
+        #[arg(value_name = "KEY=VALUE", value_parser = parse_pair, required = true)]
+        tags: Vec<(String, String)>,
+    },
+    /// remove every tag from the bucket
+    Delete,
+}
+
+/// why S3 would refuse tags, checked first so the message names the tag
+fn validate(tags: &[(String, String)]) -> Result<(), String> {
+    if tags.len() > MAX_TAGS {
+        return Err(format!("a bucket can have at most {} tags", MAX_TAGS));
+    }
+    for (i, (key, value)) in tags.iter().enumerate() {
+        if key.chars().count() > 128 {
+            return Err(format!("tag key {} is over 128 characters", key));
+        }
+        if value.chars().count() > 256 {
+            return Err(format!("the value of tag {} is over 256 characters", key));
+        }
+        if key.starts_with("aws:") {
+            return Err(format!("tag key {} uses the aws: prefix, which is reserved", key));
+        }
+        if tags[..i].iter().any(|(earlier, _)| earlier == key) {
+            return Err(format!("tag key {} is given more than once", key));
+        }
+    }
+    Ok(())
+}
+
+pub async fn run(client: &Client, bucket_name: &str, action: &TagsAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        TagsAction::Get => {
+            let result = match client.get_bucket_tagging().bucket(bucket_name).send().await {
+                Ok(result) => result,
+                Err(SdkError::ServiceError(err)) if err.err().code() == Some("NoSuchTagSet") => {
+                    println!("{} has no tags", bucket_name);
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            };
+            for tag in result.tag_set().unwrap_or_default() {
+                println!("{}={}", tag.key().unwrap_or_default(), tag.value().unwrap_or_default());
+            }
+        }
+        TagsAction::Set { tags } => {
+            if let Err(message) = validate(tags) {
+                println!("{}", message);
+                return Err(Exit(1).into());
+            }
+            let tag_set = tags.iter().map(|(key, value)| Tag::builder().key(key).value(value).build()).collect();
+            client.put_bucket_tagging()
+                .bucket(bucket_name)
+                .tagging(Tagging::builder().set_tag_set(Some(tag_set)).build())
+                .send()
+                .await?;
+            println!("set {} tags on {}", tags.len(), bucket_name);
+        }
+        TagsAction::Delete => {
+            client.delete_bucket_tagging().bucket(bucket_name).send().await?;
+            println!("removed the tags from {}", bucket_name);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::ByteStream;
+use http::HeaderValue;
+use crate::checksum::Sha256;
+use crate::clock;
+use crate::dedup;
+use crate::delete::{self, ObjectRef};
+use crate::key;
+use crate::listing::list_all_objects;
+use crate::mapped;
+use crate::payer;
+use crate::summary::{self, Outcome};
+use crate::upload::join_key;
+
+/// the object under the prefix that says which logical keys link to which blobs
+const REFS_NAME: &str = ".refs";
+const REFS_HEADER: &str = "# s3test cas refs";
+
+/// tries at updating the refs before giving up on other runs changing them at the same time
+const UPDATE_ATTEMPTS: usize = 5;
+
+/// whether name is the lowercase hex SHA-256 a blob is stored under
+pub fn is_hash(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// uploads file_path to prefix/<hex SHA-256 of its contents>, unless the object there already has
+/// the same contents, and prints the key either way. an object at that key with other contents
+/// is replaced, so a damaged entry is fixed by storing its file again
+pub async fn put(client: &Client, bucket_name: &str, prefix: &str, file_path: &Path, content_type: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let bytes = mapped::read(file_path).await?;
+    let checksum = Sha256::of(&bytes);
+    let key = join_key(prefix, &checksum.hex());
+    key::validate(&key)?;
+    if dedup::find_duplicate(client, bucket_name, &key, &bytes, &checksum, false).await?.is_some() {
+        println!("already stored: {}", key);
+        summary::report("put-cas", &key, Outcome::NoOp);
+        return Ok(());
+    }
+    let content_type = match content_type {
+        Some(content_type) => content_type.to_string(),
+        None => mime_guess::from_path(file_path).first_or_octet_stream().to_string(),
+    };
+    let request = client.put_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(&key)
+        .content_type(content_type)
+        .checksum_sha256(checksum.base64())
+        .body(bytes.body(0..bytes.len()).await?);
+    checksum.sign_with(request.customize().await?).send().await?;
+    println!("stored: {}", key);
+    summary::report("put-cas", &key, Outcome::Changed);
+    Ok(())
+}
+
+/// logical keys and the hashes they link to, with the ETag of the refs object they were read
+/// from, or None if there wasn't one
+struct Refs {
+    links: BTreeMap<String, String>,
+    e_tag: Option<String>,
+}
+
+async fn load_refs(client: &Client, bucket_name: &str, refs_key: &str) -> Result<Refs, Box<dyn Error>> {
+    let result = client.get_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(refs_key)
+        .send()
+        .await;
+    let object = match result {
+        Ok(object) => object,
+        Err(SdkError::ServiceError(err)) if err.err().is_no_such_key() => return Ok(Refs { links: BTreeMap::new(), e_tag: None }),
+        Err(err) => return Err(err.into()),
+    };
+    let e_tag = object.e_tag().map(str::to_string);
+    let text = String::from_utf8(object.body.collect().await?.into_bytes().to_vec())?;
+    let mut lines = text.lines();
+    if lines.next() != Some(REFS_HEADER) {
+        return Err(format!("{} isn't a refs object written by Link", refs_key).into());
+    }
+    let mut links = BTreeMap::new();
+    for (idx, line) in lines.enumerate() {
+        let Some((logical, hash)) = line.split_once('\t').filter(|(_, hash)| is_hash(hash)) else {
+            return Err(format!("{}:{}: malformed link", refs_key, idx + 2).into());
+        };
+        links.insert(urlencoding::decode(logical)?.into_owned(), hash.to_string());
+    }
+    Ok(Refs { links, e_tag })
+}
+
+/// writes refs back only if the object hasn't changed since they were read, or still doesn't
+/// exist. false if another run got there first
+async fn save_refs(client: &Client, bucket_name: &str, refs_key: &str, refs: &Refs) -> Result<bool, Box<dyn Error>> {
+    let mut text = format!("{}\n", REFS_HEADER);
+    for (logical, hash) in &refs.links {
+        text.push_str(&format!("{}\t{}\n", urlencoding::encode(logical), hash));
+    }
+    let condition = match &refs.e_tag {
+        Some(e_tag) => ("if-match", HeaderValue::from_str(e_tag)?),
+        None => ("if-none-match", HeaderValue::from_static("*")),
+    };
+    let request = client.put_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(refs_key)
+        .content_type("text/plain")
+        .body(ByteStream::from(text.into_bytes()))
+        .customize()
+        .await?
+        .mutate_request(move |request| {
+            request.headers_mut().insert(condition.0, condition.1.clone());
+        });
+    match request.send().await {
+        Ok(_) => Ok(true),
+        Err(SdkError::ServiceError(err)) if err.raw().http().status().as_u16() == 412 => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// applies change to the links and saves them, reading them again and reapplying it when another
+/// run saved in between. returns the links as saved, or None if change left them alone
+async fn update_refs<F>(client: &Client, bucket_name: &str, refs_key: &str, mut change: F) -> Result<Option<BTreeMap<String, String>>, Box<dyn Error>>
+    where F: FnMut(&mut BTreeMap<String, String>) -> bool {
+    for _ in 0..UPDATE_ATTEMPTS {
+        let mut refs = load_refs(client, bucket_name, refs_key).await?;
+        if !change(&mut refs.links) {
+            return Ok(None);
+        }
+        if save_refs(client, bucket_name, refs_key, &refs).await? {
+            return Ok(Some(refs.links));
+        }
+    }
+    Err(format!("{} kept changing while updating it. try again", refs_key).into())
+}
+
+fn references(links: &BTreeMap<String, String>, hash: &str) -> usize {
+    links.values().filter(|linked| *linked == hash).count()
+}
+
+/// links logical_key to the blob stored under hash, replacing whatever it linked to before. the
+/// blob has to exist, so a link never points at nothing
+pub async fn link(client: &Client, bucket_name: &str, prefix: &str, hash: &str, logical_key: &str) -> Result<(), Box<dyn Error>> {
+    if !is_hash(hash) {
+        return Err(format!("{} isn't a lowercase hex SHA-256, as PutCas prints", hash).into());
+    }
+    let blob = join_key(prefix, hash);
+    let result = client.head_object().bucket(bucket_name).set_request_payer(payer::get()).key(&blob).send().await;
+    match result {
+        Ok(_) => {}
+        Err(SdkError::ServiceError(err)) if err.err().is_not_found() => return Err(format!("{} isn't stored. store it with PutCas first", blob).into()),
+        Err(err) => return Err(err.into()),
+    }
+    let refs_key = join_key(prefix, REFS_NAME);
+    let links = update_refs(client, bucket_name, &refs_key, |links| {
+        links.insert(logical_key.to_string(), hash.to_string()).as_deref() != Some(hash)
+    }).await?;
+    match links {
+        Some(links) => {
+            println!("linked {} to {}, which has {} references", logical_key, blob, references(&links, hash));
+            summary::report("link", logical_key, Outcome::Changed);
+        }
+        None => {
+            println!("{} already links to {}", logical_key, blob);
+            summary::report("link", logical_key, Outcome::NoOp);
+        }
+    }
+    Ok(())
+}
+
+/// removes logical_key's link. a blob left with no references stays until CasGc removes it
+pub async fn unlink(client: &Client, bucket_name: &str, prefix: &str, logical_key: &str) -> Result<(), Box<dyn Error>> {
+    let refs_key = join_key(prefix, REFS_NAME);
+    let mut unlinked = None;
+    let links = update_refs(client, bucket_name, &refs_key, |links| {
+        unlinked = links.remove(logical_key);
+        unlinked.is_some()
+    }).await?;
+    match (links, unlinked) {
+        (Some(links), Some(hash)) => {
+            let count = references(&links, &hash);
+            println!("unlinked {} from {}, which has {} references left", logical_key, join_key(prefix, &hash), count);
+            summary::report("unlink", logical_key, Outcome::Changed);
+        }
+        _ => {
+            println!("{} isn't linked", logical_key);
+            summary::report("unlink", logical_key, Outcome::NoOp);
+        }
+    }
+    Ok(())
+}
+
+/// deletes the blobs under prefix that no logical key links to. blobs newer than min_age are
+/// kept, since PutCas stores a blob before it's linked. Link and Unlink can run alongside, but
+/// a blob linked while this runs may be deleted, so give both the same --lock to keep them apart
+pub async fn gc(client: &Client, bucket_name: &str, prefix: &str, min_age: Duration, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let refs = load_refs(client, bucket_name, &join_key(prefix, REFS_NAME)).await?;
+    let referenced: HashSet<&str> = refs.links.values().map(|hash| hash.as_str()).collect();
+    let base = join_key(prefix, "");
+    let cutoff = clock::signing_time() - min_age.as_secs() as i64;
+    let mut unreferenced = Vec::new();
+    let (mut kept, mut recent) = (0, 0);
+    for object in list_all_objects(client, bucket_name, prefix).await? {
+        let Some(key) = object.key else { continue };
+        let Some(hash) = key.strip_prefix(&base).filter(|name| is_hash(name)) else { continue };
+        if referenced.contains(hash) {
+            kept += 1;
+        } else if object.last_modified.is_some_and(|modified| modified.secs() > cutoff) {
+            recent += 1;
+        } else {
+            unreferenced.push(ObjectRef { key, version_id: None });
+        }
+    }
+    if dry_run {
+        for object in &unreferenced {
+            println!("would remove {}", object.key);
+        }
+        println!("would remove {} unreferenced blobs. keeping {} referenced and {} newer than --min-age", unreferenced.len(), kept, recent);
+        return Ok(());
+    }
+    let removed = delete::delete_versions(client, bucket_name, &unreferenced).await?;
+    println!("removed {} unreferenced blobs. kept {} referenced and {} newer than --min-age", removed, kept, recent);
+    if removed < unreferenced.len() {
+        return Err(format!("{} blobs couldn't be removed", unreferenced.len() - removed).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use std::convert::Infallible;
+use aws_sdk_s3::client::customize::CustomizableOperation;
+use aws_sigv4::http_request::SignableBody;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use ring::digest;
+
+/// bodies at least this big are hashed after handing the worker thread's other tasks to another
+/// thread, since it takes a while
+const BLOCKING_THRESHOLD: usize = 8 * 1024 * 1024;
+
+/// a SHA-256 digest of an upload body. ring uses the CPU's SHA instructions where it has them
+pub struct Sha256(digest::Digest);
+
+impl Sha256 {
+    /// must be called from the multi-threaded runtime
+    pub fn of(data: &[u8]) -> Sha256 {
+        let hash = || Sha256(digest::digest(&digest::SHA256, data));
+        if data.len() >= BLOCKING_THRESHOLD {
+            tokio::task::block_in_place(hash)
+        } else {
+            hash()
+        }
+    }
+
+    /// the form x-amz-content-sha256 uses
+    pub fn hex(&self) -> String {
+        base16ct::lower::encode_string(self.0.as_ref())
+    }
+
+    /// the form x-amz-checksum-sha256 uses
+    pub fn base64(&self) -> String {
+        STANDARD.encode(self.0.as_ref())
+    }
+
+    /// has the signer use this digest as the payload hash instead of hashing the body again
+    pub fn sign_with<O, R>(&self, operation: CustomizableOperation<O, R>) -> CustomizableOperation<O, R> {
+        let hex = self.hex();
+        let result = operation.map_operation(|mut operation| {
+            operation.properties_mut().insert(SignableBody::Precomputed(hex));
+            Ok::<_, Infallible>(operation)
+        });
+        match result {
+            Ok(operation) => operation,
+            Err(never) => match never {},
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::SeekFrom;
+use std::path::Path;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::ByteStream;
+use futures_util::{stream, StreamExt};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use crate::cas::is_hash;
+use crate::checksum::Sha256;
+use crate::key;
+use crate::listing::list_all_objects;
+use crate::mapped::{self, Mapped};
+use crate::payer;
+use crate::progress::Progress;
+use crate::report::format_bytes;
+use crate::stop;
+use crate::summary::{self, Outcome};
+use crate::upload::join_key;
+
+const MANIFEST_HEADER: &str = "# s3test chunked";
+
+/// one piece of the file, stored under its SHA-256 so unchanged pieces keep their key
+#[derive(Clone, Debug, PartialEq)]
+struct Chunk {
+    offset: u64,
+    length: u64,
+    hash: String,
+}
+
+/// the object at name: the file's size, then a line per chunk in file order
+#[derive(Debug, PartialEq)]
+struct Manifest {
+    size: u64,
+    chunks: Vec<Chunk>,
+}
+
+impl Manifest {
+    fn to_text(&self) -> String {
+        let mut text = format!("{}\t{}\n", MANIFEST_HEADER, self.size);
+        for chunk in &self.chunks {
+            text.push_str(&format!("{}\t{}\t{}\n", chunk.offset, chunk.length, chunk.hash));
+        }
+        text
+    }
+
+    /// checks the chunks cover the file end to end, so reassembling can't leave holes
+    fn parse(name: &str, text: &str) -> Result<Manifest, Box<dyn Error>> {
+        let mut lines = text.lines();
+        let size = lines.next()
+            .and_then(|header| header.strip_prefix(MANIFEST_HEADER)?.strip_prefix('\t')?.parse().ok())
+            .ok_or_else(|| format!("{} isn't a manifest written by ChunkedPut", name))?;
+        let mut chunks: Vec<Chunk> = Vec::new();
+        for (idx, line) in lines.enumerate() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let chunk = match fields[..] {
+                [offset, length, hash] if is_hash(hash) => offset.parse().ok()
+                    .zip(length.parse().ok())
+                    .map(|(offset, length)| Chunk { offset, length, hash: hash.to_string() }),
+                _ => None,
+            };
+            let expected = chunks.last().map_or(0, |last| last.offset + last.length);
+            match chunk {
+                Some(chunk) if chunk.offset == expected => chunks.push(chunk),
+                _ => return Err(format!("{}:{}: malformed chunk", name, idx + 2).into()),
+            }
+        }
+        if chunks.last().map_or(0, |last| last.offset + last.length) != size {
+            return Err(format!("{}: the chunks don't add up to {} bytes", name, size).into());
+        }
+        Ok(Manifest { size, chunks })
+    }
+}
+
+/// where the chunks of name are stored
+fn chunk_dir(name: &str) -> String {
+    format!("{}.chunks", name)
+}
+
+async fn load_manifest(client: &Client, bucket_name: &str, name: &str, version_id: Option<&str>) -> Result<Option<Manifest>, Box<dyn Error>> {
+    let result = client.get_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(name)
+        .set_version_id(version_id.map(str::to_string))
+        .send()
+        .await;
+    let object = match result {
+        Ok(object) => object,
+        Err(SdkError::ServiceError(err)) if err.err().is_no_such_key() => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let text = String::from_utf8(object.body.collect().await?.into_bytes().to_vec())?;
+    Ok(Some(Manifest::parse(name, &text)?))
+}
+
+/// a gear table for the rolling hash, a fixed pseudo-random u64 per byte value so every run
+/// cuts the same file in the same places
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x5333_7465_7374_4344;
+    let mut idx = 0;
+    while idx < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[idx] = z ^ (z >> 31);
+        idx += 1;
+    }
+    table
+}
+
+/// how a file is cut into chunks
+#[derive(Clone, Copy, Debug)]
+pub enum Chunking {
+    /// chunks of this many bytes. an insertion shifts every chunk after it, so they all change
+    Fixed(u64),
+    /// chunks of about this many bytes on average, cut where a rolling hash of the last 64 bytes
+    /// hits a pattern, so an insertion or deletion only changes the chunks around it
+    ContentDefined(u64),
+}
+
+impl Chunking {
+    fn lengths(self, bytes: &[u8]) -> Vec<usize> {
+        match self {
+            Chunking::Fixed(size) => bytes.chunks(size.max(1) as usize).map(<[u8]>::len).collect(),
+            Chunking::ContentDefined(average) => content_defined(bytes, average.max(64) as usize),
+        }
+    }
+
+    /// splits bytes into chunks and hashes each one
+    fn chunks(self, bytes: &[u8]) -> Vec<Chunk> {
+        let mut offset = 0;
+        self.lengths(bytes).into_iter()
+            .map(|length| {
+                let chunk = Chunk { offset: offset as u64, length: length as u64, hash: Sha256::of(&bytes[offset..offset + length]).hex() };
+                offset += length;
+                chunk
+            })
+            .collect()
+    }
+}
+
+/// gear hash chunking: a cut after any byte where the top bits of the hash are zero, with as many
+/// bits as make that happen once in average bytes. chunks stay between a quarter and eight times
+/// the average, so a run of identical bytes still gets cut
+fn content_defined(bytes: &[u8], average: usize) -> Vec<usize> {
+    let (min, max) = (average / 4, average * 8);
+    let bits = average.next_power_of_two().trailing_zeros();
+    let mask = !(u64::MAX >> bits);
+    let mut lengths = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = (start + max).min(bytes.len());
+        let mut cut = end;
+        let mut hash: u64 = 0;
+        for (idx, byte) in bytes[start..end].iter().enumerate() {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if idx + 1 >= min && hash & mask == 0 {
+                cut = start + idx + 1;
+                break;
+            }
+        }
+        lengths.push(cut - start);
+        start = cut;
+    }
+    lengths
+}
+
+/// what storing a file as chunks did
+pub struct Stored {
+    pub uploaded_chunks: usize,
+    pub total_chunks: usize,
+    pub uploaded_bytes: u64,
+    /// false if the manifest already at name was the same, and was left alone
+    pub changed: bool,
+    pub version_id: Option<String>,
+    pub e_tag: Option<String>,
+}
+
+async fn put_chunk(client: &Client, bucket_name: &str, key: &str, contents: &Mapped, chunk: &Chunk) -> Result<u64, Box<dyn Error>> {
+    let range = chunk.offset as usize..(chunk.offset + chunk.length) as usize;
+    let checksum = Sha256::of(&contents[range.clone()]);
+    let length = chunk.length;
+    let request = client.put_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .content_type("application/octet-stream")
+        .checksum_sha256(checksum.base64())
+        .body(contents.body(range).await?);
+    checksum.sign_with(request.customize().await?).send().await
+        .map_err(|err| format!("chunk {}: {}", key, err))?;
+    Ok(length)
+}
+
+/// stores file_path as chunks under name.chunks/<SHA-256> and a manifest at name listing them.
+/// chunks already stored there, by an earlier put of the file or one that was stopped, aren't
+/// uploaded again, so a changed file only sends the chunks that changed. chunks the new manifest
+/// leaves out are kept, since earlier versions of the manifest still use them
+pub async fn put(client: &Client, bucket_name: &str, file_path: &Path, name: &str, chunking: Chunking, concurrency: usize) -> Result<(), Box<dyn Error>> {
+    let stored = store(client, bucket_name, file_path, name, chunking, concurrency).await?;
+    println!("uploaded {} of {} chunks ({}), {} already stored", stored.uploaded_chunks, stored.total_chunks,
+             format_bytes(stored.uploaded_bytes as i64), stored.total_chunks - stored.uploaded_chunks);
+    if !stored.changed {
+        println!("{} is unchanged", name);
+        summary::report("chunked-put", name, Outcome::NoOp);
+        return Ok(());
+    }
+    println!("put version: {}", stored.version_id.as_deref().unwrap_or("null"));
+    summary::report("chunked-put", name, Outcome::Changed);
+    Ok(())
+}
+
+/// cuts file_path into chunks and uploads the ones that aren't stored yet, then the manifest
+/// unless it's the same as the one already at name
+pub async fn store(client: &Client, bucket_name: &str, file_path: &Path, name: &str, chunking: Chunking, concurrency: usize) -> Result<Stored, Box<dyn Error>> {
+    key::validate(name)?;
+    let bytes = mapped::read(file_path).await?;
+    let manifest = Manifest { size: bytes.len() as u64, chunks: chunking.chunks(&bytes) };
+    let dir = chunk_dir(name);
+    let stored: HashSet<String> = list_all_objects(client, bucket_name, &join_key(&dir, "")).await?
+        .into_iter()
+        .filter_map(|object| object.key)
+        .collect();
+    let mut queued = HashSet::new();
+    let missing: Vec<&Chunk> = manifest.chunks.iter()
+        .filter(|chunk| !stored.contains(&join_key(&dir, &chunk.hash)) && queued.insert(&chunk.hash))
+        .collect();
+    let missing_bytes = missing.iter().map(|chunk| chunk.length).sum();
+
+    let mut progress = Progress::bytes("uploading", name, missing_bytes);
+    let mut results = stream::iter(&missing)
+        .map(|chunk| {
+            let key = join_key(&dir, &chunk.hash);
+            let bytes = &bytes;
+            async move { put_chunk(client, bucket_name, &key, bytes, chunk).await }
+        })
+        .buffer_unordered(concurrency.max(1));
+    while let Some(result) = stop::within(results.next()).await? {
+        progress.add(result?);
+    }
+    progress.finish();
+    let mut stored = Stored {
+        uploaded_chunks: missing.len(),
+        total_chunks: manifest.chunks.len(),
+        uploaded_bytes: missing_bytes,
+        changed: false,
+        version_id: None,
+        e_tag: None,
+    };
+
+    // an unreadable manifest is replaced like a changed one
+    if load_manifest(client, bucket_name, name, None).await.ok().flatten().as_ref() == Some(&manifest) {
+        return Ok(stored);
+    }
+    let result = client.put_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(name)
+        .content_type("text/plain")
+        .body(ByteStream::from(manifest.to_text().into_bytes()))
+        .send()
+        .await?;
+    stored.changed = true;
+    stored.version_id = result.version_id().map(str::to_string);
+    stored.e_tag = result.e_tag().map(str::to_string);
+    Ok(stored)
+}
+
+/// fetches a chunk and writes it at its offset in path, checking it against its hash first
+async fn get_chunk(client: &Client, bucket_name: &str, dir: &str, chunk: &Chunk, path: &Path) -> Result<u64, Box<dyn Error>> {
+    let key = join_key(dir, &chunk.hash);
+    let result = client.get_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(&key)
+        .send()
+        .await
+        .map_err(|err| format!("chunk {}: {}", key, err))?;
+    let piece = result.body.collect().await?.into_bytes();
+    if piece.len() as u64 != chunk.length || Sha256::of(&piece).hex() != chunk.hash {
+        return Err(format!("chunk {} doesn't match its hash", key).into());
+    }
+    let mut file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+    file.seek(SeekFrom::Start(chunk.offset)).await?;
+    file.write_all(&piece).await?;
+    file.flush().await?;
+    Ok(chunk.length)
+}
+
+/// reassembles the file ChunkedPut stored at name (at version_id, if given) into file_path,
+/// fetching concurrency chunks at once
+pub async fn get(client: &Client, bucket_name: &str, name: &str, version_id: Option<&str>, file_path: &Path, concurrency: usize) -> Result<(), Box<dyn Error>> {
+    key::validate(name)?;
+    let manifest = load_manifest(client, bucket_name, name, version_id).await?
+        .ok_or_else(|| format!("{} doesn't exist", name))?;
+    if let Some(parent) = file_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::File::create(file_path).await?.set_len(manifest.size).await?;
+
+    let dir = chunk_dir(name);
+    let mut progress = Progress::bytes("downloading", name, manifest.size);
+    let mut results = stream::iter(&manifest.chunks)
+        .map(|chunk| get_chunk(client, bucket_name, &dir, chunk, file_path))
+        .buffer_unordered(concurrency.max(1));
+    while let Some(result) = stop::within(results.next()).await? {
+        progress.add(result?);
+    }
+    progress.finish();
+    println!("got {} chunks ({} bytes)", manifest.chunks.len(), manifest.size);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(offset: u64, length: u64, fill: char) -> Chunk {
+        Chunk { offset, length, hash: fill.to_string().repeat(64) }
+    }
+
+    #[test]
+    fn manifest_round_trips() {
+        let manifest = Manifest { size: 12, chunks: vec![chunk(0, 5, 'a'), chunk(5, 7, 'b')] };
+        let text = manifest.to_text();
+        assert_eq!(text, format!("# s3test chunked\t12\n0\t5\t{}\n5\t7\t{}\n", "a".repeat(64), "b".repeat(64)));
+        assert_eq!(Manifest::parse("m", &text).unwrap(), manifest);
+        let empty = Manifest { size: 0, chunks: Vec::new() };
+        assert_eq!(Manifest::parse("m", &empty.to_text()).unwrap(), empty);
+    }
+
+    #[test]
+    fn manifest_rejects_holes_and_bad_lines() {
+        let hash = "a".repeat(64);
+        let parse = |text: String| Manifest::parse("m", &text).map_err(|err| err.to_string());
+        assert!(parse("not a manifest\n".to_string()).unwrap_err().contains("isn't a manifest"));
+        assert!(parse(format!("# s3test chunked\t10\n0\t5\t{}\n6\t4\t{}\n", hash, hash)).unwrap_err().contains("m:3"));
+        assert!(parse(format!("# s3test chunked\t10\n0\t5\t{}\n", hash)).unwrap_err().contains("don't add up"));
+        assert!(parse("# s3test chunked\t5\n0\t5\tnot-a-hash\n".to_string()).unwrap_err().contains("m:2"));
+        assert!(parse(format!("# s3test chunked\t5\n0\t5\n{}", hash)).is_err());
+    }
+
+    /// deterministic bytes that look random to the rolling hash
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        }).collect()
+    }
+
+    #[test]
+    fn content_defined_chunks_stay_within_bounds() {
+        let average = 4096;
+        for bytes in [noise(1 << 20, 1), vec![0u8; 1 << 20]] {
+            let lengths = content_defined(&bytes, average);
+            assert_eq!(lengths.iter().sum::<usize>(), bytes.len());
+            assert!(lengths.iter().all(|len| *len <= average * 8));
+            assert!(lengths[..lengths.len() - 1].iter().all(|len| *len >= average / 4));
+        }
+        // random data averages out near the target
+        let count = content_defined(&noise(1 << 20, 2), average).len();
+        assert!(((1 << 20) / (average * 2)..(1 << 20) * 2 / average).contains(&count), "{}", count);
+        // a run of identical bytes is cut at the maximum
+        assert!(content_defined(&vec![7u8; average * 20], average).iter().any(|len| *len == average * 8));
+        assert!(content_defined(&[], average).is_empty());
+        assert_eq!(content_defined(&[1, 2, 3], average), [3]);
+    }
+
+    #[test]
+    fn content_defined_cuts_survive_an_insertion() {
+        let original = noise(1 << 20, 3);
+        let mut edited = original.clone();
+        edited.splice(500_000..500_000, b"inserted".iter().copied());
+        let hashes = |bytes: &[u8]| Chunking::ContentDefined(4096).chunks(bytes).into_iter().map(|c| c.hash).collect::<Vec<_>>();
+        let (before, after) = (hashes(&original), hashes(&edited));
+        assert_eq!(before, hashes(&original));
+        let unchanged = after.iter().filter(|hash| before.contains(hash)).count();
+        assert!(unchanged + 3 >= before.len(), "{} of {} chunks unchanged", unchanged, before.len());
+    }
+
+    #[test]
+    fn fixed_chunks_cover_the_file() {
+        let bytes: Vec<u8> = (0..10).collect();
+        let chunks = Chunking::Fixed(4).chunks(&bytes);
+        assert_eq!(chunks.iter().map(|c| (c.offset, c.length)).collect::<Vec<_>>(), [(0, 4), (4, 4), (8, 2)]);
+        assert_eq!(chunks[2].hash, Sha256::of(&bytes[8..]).hex());
+    }
+}
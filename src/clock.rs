@@ -0,0 +1,142 @@
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use aws_credential_types::provider::SharedCredentialsProvider;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use http::{HeaderMap, Request, Response, StatusCode};
+use hyper::service::Service;
+use crate::http::try_clone;
+use crate::prometheus;
+use crate::sigv4;
+
+/// S3 rejects requests signed further than this from its own clock, in seconds
+pub const MAX_SKEW: i64 = 15 * 60;
+/// differences up to this are left alone, since Date has one second resolution and includes latency
+pub const TOLERANCE: i64 = 60;
+
+/// seconds added to the local clock when re-signing requests
+static OFFSET: AtomicI64 = AtomicI64::new(0);
+/// server time minus local time from the most recent response
+static MEASURED: Mutex<Option<i64>> = Mutex::new(None);
+
+/// the latest measured server time minus local time, in seconds, if any response had a Date header
+pub fn measured() -> Option<i64> {
+    *MEASURED.lock().unwrap()
+}
+
+/// e.g. "local clock is 90s behind the server's"
+pub fn describe(skew: i64) -> String {
+    let direction = if skew > 0 { "behind" } else { "ahead of" };
+    format!("local clock is {}s {} the server's", skew.abs(), direction)
+}
+
+fn local_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
+
+/// the time to sign requests with, corrected for any skew seen so far
+pub fn signing_time() -> i64 {
+    local_now() + OFFSET.load(Ordering::Relaxed)
+}
+
+/// records the skew shown by a response's Date header, and starts correcting for it once it's large enough
+fn observe(headers: &HeaderMap) -> Option<i64> {
+    let date = headers.get("date")?.to_str().ok()?;
+    let server = DateTime::from_str(date, DateTimeFormat::HttpDate).ok()?.secs();
+    let skew = server - local_now();
+    *MEASURED.lock().unwrap() = Some(skew);
+    let offset = if skew.abs() > TOLERANCE { skew } else { 0 };
+    let previous = OFFSET.swap(offset, Ordering::Relaxed);
+    if (previous - offset).abs() > TOLERANCE {
+        eprintln!("note: {}, adjusting request times", describe(skew));
+    }
+    Some(skew)
+}
+
+/// re-signs requests using the server's clock once a response shows the local one is off.
+/// a request rejected for being signed too far from the server's time is sent once more, re-signed
+#[derive(Clone)]
+pub struct SkewCorrection {
+    inner: DynConnector,
+    credentials: SharedCredentialsProvider,
+}
+
+impl SkewCorrection {
+    pub fn wrap(inner: DynConnector, credentials: SharedCredentialsProvider) -> DynConnector {
+        DynConnector::new(SkewCorrection { inner, credentials })
+    }
+}
+
+impl Service<Request<SdkBody>> for SkewCorrection {
+    type Response = Response<SdkBody>;
+    type Error = ConnectorError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<SdkBody>, ConnectorError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ConnectorError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<SdkBody>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let credentials = self.credentials.clone();
+        Box::pin(async move {
+            let signed_offset = OFFSET.load(Ordering::Relaxed);
+            if signed_offset != 0 {
+                sigv4::resign_request(&credentials, &mut request, local_now() + signed_offset).await?;
+            }
+            let retry = try_clone(&request);
+            poll_fn(|cx| inner.poll_ready(cx)).await?;
+            let response = inner.call(request).await?;
+            let skew = observe(response.headers());
+            match (retry, skew) {
+                (Some(mut retry), Some(skew)) if response.status() == StatusCode::FORBIDDEN && (skew - signed_offset).abs() > MAX_SKEW => {
+                    sigv4::resign_request(&credentials, &mut retry, signing_time()).await?;
+                    prometheus::count_retry();
+                    poll_fn(|cx| inner.poll_ready(cx)).await?;
+                    inner.call(retry).await
+                }
+                _ => Ok(response),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::HeaderValue;
+
+    fn date_header(secs: i64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let date = DateTime::from_secs(secs).fmt(DateTimeFormat::HttpDate).unwrap();
+        headers.insert("date", HeaderValue::from_str(&date).unwrap());
+        headers
+    }
+
+    #[test]
+    fn describes_skew() {
+        assert_eq!(describe(90), "local clock is 90s behind the server's");
+        assert_eq!(describe(-5), "local clock is 5s ahead of the server's");
+    }
+
+    #[test]
+    fn corrects_only_large_skew() {
+        // one test, since the offset is shared
+        assert_eq!(observe(&HeaderMap::new()), None);
+        let skew = observe(&date_header(local_now() + 10)).unwrap();
+        assert!((9..=11).contains(&skew));
+        assert_eq!(OFFSET.load(Ordering::Relaxed), 0);
+        let skew = observe(&date_header(local_now() - 3600)).unwrap();
+        assert_eq!(measured(), Some(skew));
+        assert_eq!(OFFSET.load(Ordering::Relaxed), skew);
+        assert!((signing_time() - (local_now() - 3600)).abs() <= 2);
+        observe(&date_header(local_now()));
+        assert_eq!(OFFSET.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::primitives::DateTime;
+use aws_sdk_s3::types::{BucketLifecycleConfiguration, BucketVersioningStatus, CorsConfiguration, MetadataDirective, Tagging,
+                        TaggingDirective, VersioningConfiguration};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use crate::bucket::{self, Probe};
+use crate::listing::{list_all_versions, object_pages};
+use crate::key;
+use crate::payer;
+use crate::progress::Progress;
+use crate::stop;
+
+/// copies at once
+const CONCURRENCY: usize = 4;
+
+/// one step in a key's history: a version to copy or a delete marker to recreate
+pub struct Event {
+    pub version_id: Option<String>,
+    pub modified: Option<DateTime>,
+    pub size: i64,
+    pub deleted: bool,
+}
+
+/// whether err is the error code S3 uses for a configuration the bucket doesn't have
+fn is_missing<E: ProvideErrorMetadata>(err: &SdkError<E>, code: &str) -> bool {
+    matches!(err, SdkError::ServiceError(service) if service.err().code() == Some(code))
+}
+
+/// copies versioning, lifecycle rules, CORS rules and tags from source to dest
+async fn copy_configuration(client: &Client, source: &str, dest: &str) -> Result<(), Box<dyn Error>> {
+    let versioning = client.get_bucket_versioning().bucket(source).send().await?;
+    if let Some(status) = versioning.status() {
+        // a bucket that has never been versioned can't be suspended
+        if *status == BucketVersioningStatus::Enabled {
+            client.put_bucket_versioning()
+                .bucket(dest)
+                .versioning_configuration(VersioningConfiguration::builder().status(status.clone()).build())
+                .send()
+                .await?;
+            println!("copied versioning: {}", status.as_str());
+        }
+    }
+    match client.get_bucket_lifecycle_configuration().bucket(source).send().await {
+        Ok(lifecycle) => {
+            let rules = lifecycle.rules().unwrap_or_default().to_vec();
+            let count = rules.len();
+            client.put_bucket_lifecycle_configuration()
+                .bucket(dest)
+                .lifecycle_configuration(BucketLifecycleConfiguration::builder().set_rules(Some(rules)).build())
+                .send()
+                .await?;
+            println!("copied {} lifecycle rules", count);
+        }
+        Err(err) if is_missing(&err, "NoSuchLifecycleConfiguration") => {}
+        Err(err) => return Err(err.into()),
+    }
+    match client.get_bucket_cors().bucket(source).send().await {
+        Ok(cors) => {
+            let rules = cors.cors_rules().unwrap_or_default().to_vec();
+            let count = rules.len();
+            client.put_bucket_cors()
+                .bucket(dest)
+                .cors_configuration(CorsConfiguration::builder().set_cors_rules(Some(rules)).build())
+                .send()
+                .await?;
+            println!("copied {} CORS rules", count);
+        }
+        Err(err) if is_missing(&err, "NoSuchCORSConfiguration") => {}
+        Err(err) => return Err(err.into()),
+    }
+    match client.get_bucket_tagging().bucket(source).send().await {
+        Ok(tagging) => {
+            let tags = tagging.tag_set().unwrap_or_default().to_vec();
+            let count = tags.len();
+            client.put_bucket_tagging()
+                .bucket(dest)
+                .tagging(Tagging::builder().set_tag_set(Some(tags)).build())
+                .send()
+                .await?;
+            println!("copied {} tags", count);
+        }
+        Err(err) if is_missing(&err, "NoSuchTagSet") => {}
+        Err(err) => return Err(err.into()),
+    }
+    Ok(())
+}
+
+/// server-side copies one object or version, keeping its metadata and tags
+async fn copy_one(client: &Client, source: &str, dest: &str, key: &str, version_id: Option<&str>, size: i64) -> Result<(), Box<dyn Error>> {
+    if size > key::MAX_COPY_SIZE {
+        return Err(format!("{} is over 5 GiB, which needs a multipart copy", key).into());
+    }
+    client.copy_object()
+        .bucket(dest)
+        .set_request_payer(payer::get())
+        .copy_source(key::copy_source(source, key, version_id))
+        .key(key)
+        .metadata_directive(MetadataDirective::Copy)
+        .tagging_directive(TaggingDirective::Copy)
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// replays a key's history into dest, oldest first, so the versions keep their order and a
+/// delete marker on top leaves the key deleted
+async fn copy_history(client: &Client, source: &str, dest: &str, key: &str, events: &[Event]) -> Result<usize, Box<dyn Error>> {
+    for event in events {
+        if event.deleted {
+            client.delete_object().bucket(dest).set_request_payer(payer::get()).key(key).send().await?;
+        } else {
+            copy_one(client, source, dest, key, event.version_id.as_deref(), event.size).await?;
+        }
+    }
+    Ok(events.len())
+}
+
+/// the versions and delete markers of every key under prefix, each key's oldest first
+pub async fn histories(client: &Client, bucket_name: &str, prefix: &str) -> Result<BTreeMap<String, Vec<Event>>, Box<dyn Error>> {
+    let (versions, markers) = list_all_versions(client, bucket_name, prefix).await?;
+    let mut histories: BTreeMap<String, Vec<Event>> = BTreeMap::new();
+    for version in versions {
+        let Some(key) = version.key.clone() else { continue };
+        histories.entry(key).or_default().push(Event {
+            version_id: version.version_id,
+            modified: version.last_modified,
+            size: version.size,
+            deleted: false,
+        });
+    }
+    for marker in markers {
+        let Some(key) = marker.key.clone() else { continue };
+        histories.entry(key).or_default().push(Event {
+            version_id: marker.version_id,
+            modified: marker.last_modified,
+            size: 0,
+            deleted: true,
+        });
+    }
+    for events in histories.values_mut() {
+        events.sort_by_key(|event| event.modified.map(|d| (d.secs(), d.subsec_nanos())));
+    }
+    Ok(histories)
+}
+
+/// creates dest if it doesn't exist, copies source's configuration to it, then copies every
+/// object, or with include_versions every version and delete marker
+pub async fn run(client: &Client, source: &str, dest: &str, include_versions: bool) -> Result<(), Box<dyn Error>> {
+    match bucket::probe(client, dest).await? {
+        Probe::Accessible | Probe::Elsewhere(_) => println!("{} already exists", dest),
+        Probe::Forbidden => return Err(format!("{} already exists and belongs to someone else", dest).into()),
+        Probe::NotFound => {
+            let region = client.conf().region().map(|r| r.to_string()).ok_or("no region is set")?;
+            bucket::create_in(client, dest, &region).await?;
+            println!("created {} in {}", dest, region);
+        }
+    }
+    copy_configuration(client, source, dest).await?;
+
+    let mut progress = Progress::counter("copying");
+    let mut copied = 0;
+    let mut failed = 0;
+    if include_versions {
+        let histories = histories(client, source, "").await?;
+        let mut results = stream::iter(&histories)
+            .map(|(key, events)| async move { (key, copy_history(client, source, dest, key, events).await) })
+            .buffer_unordered(CONCURRENCY);
+        while let Some((key, result)) = stop::within(results.next()).await? {
+            match result {
+                Ok(count) => {
+                    copied += count;
+                    progress.add(count as u64);
+                }
+                Err(err) => {
+                    // the versions before the failure have been copied, so the rest of the history is out of order
+                    println!("failed to copy the history of {}: {}", key, err);
+                    failed += 1;
+                }
+            }
+        }
+    } else {
+        let objects = object_pages(client, source, "")
+            .map_ok(|page| stream::iter(page.into_iter().map(Ok::<_, Box<dyn Error>>)))
+            .try_flatten();
+        let mut results = pin!(objects
+            .map_ok(|object| async move {
+                let key = object.key.clone().unwrap_or_default();
+                let result = copy_one(client, source, dest, &key, None, object.size).await;
+                Ok((key, result))
+            })
+            .try_buffer_unordered(CONCURRENCY));
+        while let Some((key, result)) = stop::within(results.try_next()).await?? {
+            match result {
+                Ok(()) => {
+                    copied += 1;
+                    progress.add(1);
+                }
+                Err(err) => {
+                    println!("failed to copy {}: {}", key, err);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    progress.finish();
+    println!("copied {}, failed {}", copied, failed);
+    if failed > 0 {
+        return Err(format!("{} copies failed", failed).into());
+    }
+    Ok(())
+}
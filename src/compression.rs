@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+/// the metadata recording how an encrypted object's plaintext was compressed. Content-Encoding
+/// would have HTTP clients try to decompress the ciphertext
+pub const METADATA_KEY: &str = "s3test-compression";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// the Content-Encoding value stored with objects compressed this way
+    pub fn content_encoding(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    pub fn from_content_encoding(encoding: &str) -> Option<Compression> {
+        match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Compression::Gzip),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// how an object was compressed before it was stored, from METADATA_KEY or its Content-Encoding
+    pub fn of_object(content_encoding: Option<&str>, metadata: &HashMap<String, String>) -> Option<Compression> {
+        metadata.get(METADATA_KEY).map(String::as_str).or(content_encoding).and_then(Compression::from_content_encoding)
+    }
+
+    pub fn compress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            Compression::Zstd => zstd::encode_all(bytes, 0),
+        }
+    }
+
+    pub fn decompress(&self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::Gzip => {
+                let mut out = Vec::new();
+                GzDecoder::new(bytes).read_to_end(&mut out)?;
+                Ok(out)
+            }
+            Compression::Zstd => zstd::decode_all(bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let text = b"hello hello hello hello".repeat(10);
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let compressed = compression.compress(&text).unwrap();
+            assert_eq!(compression.decompress(&compressed).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn metadata_wins_over_content_encoding() {
+        let mut metadata = HashMap::new();
+        assert_eq!(Compression::of_object(Some("x-gzip"), &metadata), Some(Compression::Gzip));
+        assert_eq!(Compression::of_object(Some("br"), &metadata), None);
+        assert_eq!(Compression::of_object(None, &metadata), None);
+        metadata.insert(METADATA_KEY.to_string(), "zstd".to_string());
+        assert_eq!(Compression::of_object(None, &metadata), Some(Compression::Zstd));
+        assert_eq!(Compression::of_object(Some("gzip"), &metadata), Some(Compression::Zstd));
+    }
+}
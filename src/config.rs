@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// one `[profile.NAME]` table from the config file
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    /// the first takes every request. reads fail over to the rest, in order
+    pub endpoints: Vec<String>,
+    pub region: Option<String>,
+    pub bucket: Option<String>,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+}
+
+/// one `[alias.NAME]` table, a short name usable wherever a bucket is
+#[derive(Clone, Debug)]
+pub struct Alias {
+    pub bucket: String,
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+}
+
+/// the config file, TOML like
+///
+/// ```toml
+/// [profile.default]
+/// endpoints = ["https://minio-east:9000", "https://minio-west:9000"]
+/// region = "us-east-1"
+/// bucket = "backups"
+///
+/// [alias.staging]
+/// bucket = "backups-staging-2291"
+/// endpoint = "https://minio-staging:9000"
+/// region = "us-west-2"
+/// ```
+///
+/// `endpoint = "..."` is accepted for a single endpoint
+#[derive(Debug, Default)]
+pub struct Settings {
+    pub profiles: BTreeMap<String, Profile>,
+    pub aliases: BTreeMap<String, Alias>,
+}
+
+/// table.key as a string, if present
+fn string(table: &toml::Table, name: &str, key: &str) -> Result<Option<String>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value.as_str().map(|s| Some(s.to_string()))
+            .ok_or_else(|| format!("{}: {} must be a string", name, key)),
+    }
+}
+
+/// $S3TEST_CONFIG, or ~/.config/s3test/config.toml
+pub fn path() -> Option<PathBuf> {
+    if let Ok(path) = env::var("S3TEST_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    env::var("HOME").ok().map(|home| PathBuf::from(home).join(".config/s3test/config.toml"))
+}
+
+impl Settings {
+    pub fn parse(text: &str) -> Result<Settings, Box<dyn Error>> {
+        let table: toml::Table = text.parse()?;
+        let mut settings = Settings::default();
+        let empty = toml::Table::new();
+        let profiles = match table.get("profile") {
+            Some(profiles) => profiles.as_table().ok_or("profile must be a table of [profile.NAME] tables")?,
+            None => &empty,
+        };
+        for (name, profile) in profiles {
+            let profile = profile.as_table().ok_or_else(|| format!("profile.{} is not a table", name))?;
+            let label = format!("profile.{}", name);
+            let string = |key: &str| string(profile, &label, key);
+            let mut endpoints = Vec::new();
+            if let Some(list) = profile.get("endpoints") {
+                let list = list.as_array().ok_or_else(|| format!("profile.{}: endpoints must be an array", name))?;
+                for endpoint in list {
+                    let endpoint = endpoint.as_str().ok_or_else(|| format!("profile.{}: endpoints must be strings", name))?;
+                    endpoints.push(endpoint.to_string());
+                }
+            }
+            if let Some(endpoint) = string("endpoint")? {
+                if !endpoints.is_empty() {
+                    return Err(format!("profile.{}: set endpoint or endpoints, not both", name).into());
+                }
+                endpoints.push(endpoint);
+            }
+            let parsed = Profile {
+                endpoints,
+                region: string("region")?,
+                bucket: string("bucket")?,
+                access_key: string("access_key")?,
+                secret_key: string("secret_key")?,
+            };
+            settings.profiles.insert(name.to_string(), parsed);
+        }
+
+        let aliases = match table.get("alias") {
+            Some(aliases) => aliases.as_table().ok_or("alias must be a table of [alias.NAME] tables")?,
+            None => &empty,
+        };
+        for (name, alias) in aliases {
+            let alias = alias.as_table().ok_or_else(|| format!("alias.{} is not a table", name))?;
+            let label = format!("alias.{}", name);
+            let bucket = string(alias, &label, "bucket")?.ok_or_else(|| format!("{}: bucket is required", label))?;
+            let parsed = Alias { bucket, endpoint: string(alias, &label, "endpoint")?, region: string(alias, &label, "region")? };
+            settings.aliases.insert(name.to_string(), parsed);
+        }
+        Ok(settings)
+    }
+
+    /// reads the config file. a missing file is the same as an empty one
+    pub fn load() -> Result<Settings, Box<dyn Error>> {
+        let Some(path) = path() else { return Ok(Settings::default()) };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Settings::parse(&text).map_err(|err| format!("{}: {}", path.display(), err).into()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Settings::default()),
+            Err(err) => Err(format!("{}: {}", path.display(), err).into()),
+        }
+    }
+
+    /// replaces the named profile in the config file, leaving the rest of it alone, and makes the
+    /// file readable only by its owner since it can hold credentials
+    pub fn save_profile(name: &str, profile: &Profile) -> Result<PathBuf, Box<dyn Error>> {
+        let path = path().ok_or("no config file location. set S3TEST_CONFIG or HOME")?;
+        let mut table: toml::Table = match fs::read_to_string(&path) {
+            Ok(text) => text.parse().map_err(|err| format!("{}: {}", path.display(), err))?,
+            Err(err) if err.kind() == ErrorKind::NotFound => toml::Table::new(),
+            Err(err) => return Err(format!("{}: {}", path.display(), err).into()),
+        };
+        let mut entry = toml::Table::new();
+        if !profile.endpoints.is_empty() {
+            entry.insert("endpoints".to_string(), profile.endpoints.iter().map(|e| toml::Value::from(e.as_str())).collect::<Vec<_>>().into());
+        }
+        let fields = [("region", &profile.region), ("bucket", &profile.bucket),
+                      ("access_key", &profile.access_key), ("secret_key", &profile.secret_key)];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                entry.insert(key.to_string(), value.as_str().into());
+            }
+        }
+        let profiles = table.entry("profile").or_insert_with(|| toml::Table::new().into());
+        profiles.as_table_mut().ok_or("profile must be a table of [profile.NAME] tables")?.insert(name.to_string(), entry.into());
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = fs::File::options();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // mode only applies to new files
+            if path.exists() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+        options.open(&path)?.write_all(table.to_string().as_bytes())?;
+        Ok(path)
+    }
+
+    /// the bucket an alias stands for, or name itself if it isn't one
+    pub fn bucket<'a>(&'a self, name: &'a str) -> &'a str {
+        self.aliases.get(name).map(|alias| alias.bucket.as_str()).unwrap_or(name)
+    }
+
+    /// the named profile. only the default profile may be missing
+    pub fn profile(&self, name: &str) -> Result<Profile, Box<dyn Error>> {
+        match self.profiles.get(name) {
+            Some(profile) => Ok(profile.clone()),
+            None if name == DEFAULT_PROFILE => Ok(Profile::default()),
+            None => Err(format!("no profile named {}", name).into()),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use aws_credential_types::provider::SharedCredentialsProvider;
+use aws_sdk_config::config::Credentials;
+use aws_sdk_s3::{Client, Config};
+use aws_sdk_s3::config::Region;
+use crate::config::{self, Profile};
+use crate::errors::ErrorDetails;
+use crate::http::{self, HttpArgs};
+use crate::secrets;
+
+/// asks for a line on stdin, showing current as the default kept by an empty answer. - clears it
+fn prompt(label: &str, current: Option<&str>) -> Result<Option<String>, Box<dyn Error>> {
+    prompt_showing(label, current, current)
+}
+
+/// prompt, showing shown in place of the current value
+fn prompt_showing(label: &str, shown: Option<&str>, current: Option<&str>) -> Result<Option<String>, Box<dyn Error>> {
+    match shown {
+        Some(shown) => print!("{} [{}]: ", label, shown),
+        None => print!("{}: ", label),
+    }
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Err("no answer on stdin".into());
+    }
+    match line.trim() {
+        "" => Ok(current.map(|c| c.to_string())),
+        "-" => Ok(None),
+        answer => Ok(Some(answer.to_string())),
+    }
+}
+
+/// the secret key is only shown by its last four characters
+fn masked(secret: &str) -> String {
+    let tail: String = secret.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+    format!("****{}", tail)
+}
+
+/// a HeadBucket of the default bucket, or a ListBuckets without one
+async fn validate(profile: &Profile, http_args: &HttpArgs) -> Result<(), Box<dyn Error>> {
+    let (Some(access_key), Some(secret_key), Some(region)) = (&profile.access_key, &profile.secret_key, &profile.region) else {
+        return Err("access key, secret key and region are needed to connect".into());
+    };
+    let credentials = Credentials::from_keys(access_key, secret_key, None);
+    let mut builder = Config::builder()
+        .credentials_provider(SharedCredentialsProvider::new(credentials))
+        .region(Region::new(region.clone()))
+        .http_connector(http::connector(http_args)?);
+    builder.set_endpoint_url(profile.endpoints.first().cloned());
+    let client = Client::from_conf(builder.build());
+    match &profile.bucket {
+        Some(bucket) => {
+            client.head_bucket().bucket(bucket).send().await?;
+        }
+        None => {
+            client.list_buckets().send().await?;
+        }
+    }
+    Ok(())
+}
+
+/// prompts for a profile's settings, checks they work and writes them to the config file.
+/// with store_keyring the keys go to the OS keyring instead, and are removed from the file
+pub async fn run(name: &str, http_args: &HttpArgs, store_keyring: bool) -> Result<(), Box<dyn Error>> {
+    let mut existing = config::Settings::load()?.profiles.get(name).cloned().unwrap_or_default();
+    let in_keyring = match secrets::load(name) {
+        Some((access_key, secret_key)) => {
+            existing.access_key = Some(access_key);
+            existing.secret_key = Some(secret_key);
+            true
+        }
+        None => false,
+    };
+    println!("configuring profile {}. press enter to keep the value in brackets, or - to clear it", name);
+
+    let endpoint = prompt("endpoint URL, - for AWS", existing.endpoints.first().map(|e| e.as_str()))?;
+    // a single answer can't describe several endpoints, so they're kept unless replaced
+    let endpoints = match endpoint {
+        Some(endpoint) if existing.endpoints.first() != Some(&endpoint) => vec![endpoint],
+        Some(_) => existing.endpoints.clone(),
+        None => Vec::new(),
+    };
+    let region = prompt("region", existing.region.as_deref().or(Some("us-east-1")))?;
+    let access_key = prompt("access key", existing.access_key.as_deref())?;
+    let masked_secret = existing.secret_key.as_deref().map(masked);
+    let secret_key = prompt_showing("secret key", masked_secret.as_deref(), existing.secret_key.as_deref())?;
+    let bucket = prompt("default bucket", existing.bucket.as_deref())?;
+    let mut profile = Profile { endpoints, region, bucket, access_key, secret_key };
+
+    match validate(&profile, http_args).await {
+        Ok(()) => println!("ok    connected"),
+        Err(err) => {
+            println!("FAIL  {}", ErrorDetails::from_error(err.as_ref()));
+            let save = prompt("save anyway? [y/N]", None)?;
+            if !matches!(save.as_deref(), Some("y" | "Y" | "yes")) {
+                return Err("profile not saved".into());
+            }
+        }
+    }
+    if store_keyring {
+        let (Some(access_key), Some(secret_key)) = (profile.access_key.take(), profile.secret_key.take()) else {
+            return Err("--store-keyring needs an access key and secret key".into());
+        };
+        secrets::store(name, &access_key, &secret_key)?;
+        println!("stored the keys for {} in the keyring", name);
+    } else if in_keyring && profile.secret_key.is_none() {
+        // otherwise the cleared secret would still be found there
+        secrets::delete(name)?;
+        println!("removed the keys for {} from the keyring", name);
+    }
+    let path = config::Settings::save_profile(name, &profile)?;
+    println!("wrote profile {} to {}", name, path.display());
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{MetadataDirective, TaggingDirective};
+use clap::{Args, ValueEnum};
+use crate::key;
+use crate::payer;
+
+/// whether a copy keeps what the source has or takes what's given on the command line
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Directive {
+    Copy,
+    Replace,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct CopyArgs {
+    #[arg(long, value_enum, default_value = "copy", help = "keep the source's metadata, or replace it with --metadata and --content-type")]
+    metadata_directive: Directive,
+    #[arg(long, value_enum, default_value = "copy", help = "keep the source's tags, or replace them with --tag")]
+    tagging_directive: Directive,
+    #[arg(long = "metadata", value_name = "KEY=VALUE", value_parser = parse_pair, help = "metadata for the copy, with --metadata-directive replace. may be repeated")]
+    metadata: Vec<(String, String)>,
+    #[arg(long, help = "content type for the copy, with --metadata-directive replace")]
+    content_type: Option<String>,
+    #[arg(long = "tag", value_name = "KEY=VALUE", value_parser = parse_pair, help = "tag for the copy, with --tagging-directive replace. may be repeated")]
+    tags: Vec<(String, String)>,
+}
+
+pub fn parse_pair(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value.split_once('=').ok_or("expected KEY=VALUE")?;
+    if key.is_empty() {
+        return Err("the key can't be empty".to_string());
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// the x-amz-tagging form of tags, a URL-encoded query string
+pub fn tagging(tags: &[(String, String)]) -> String {
+    tags.iter()
+        .map(|(key, value)| format!("{}={}", urlencoding::encode(key), urlencoding::encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// copies source_bucket/source to bucket/dest. both directives are always sent, so what happens to
+/// metadata and tags doesn't depend on the server's defaults
+pub async fn run(client: &Client, source_bucket: &str, source: &str, bucket_name: &str, dest: &str, args: &CopyArgs) -> Result<(), Box<dyn Error>> {
+    if args.metadata_directive == Directive::Copy && (!args.metadata.is_empty() || args.content_type.is_some()) {
+        return Err("--metadata and --content-type need --metadata-directive replace".into());
+    }
+    if args.tagging_directive == Directive::Copy && !args.tags.is_empty() {
+        return Err("--tag needs --tagging-directive replace".into());
+    }
+    let mut request = client.copy_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .copy_source(key::copy_source(source_bucket, source, None))
+        .key(dest);
+    request = match args.metadata_directive {
+        Directive::Copy => request.metadata_directive(MetadataDirective::Copy),
+        Directive::Replace => {
+            for (key, value) in &args.metadata {
+                request = request.metadata(key, value);
+            }
+            request.metadata_directive(MetadataDirective::Replace).set_content_type(args.content_type.clone())
+        }
+    };
+    request = match args.tagging_directive {
+        Directive::Copy => request.tagging_directive(TaggingDirective::Copy),
+        Directive::Replace => request.tagging_directive(TaggingDirective::Replace).tagging(tagging(&args.tags)),
+    };
+    let result = request.send().await?;
+    println!("copy result: {:?}", result);
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use aws_sdk_s3::Client;
+use clap::ValueEnum;
+use crate::listing::list_all_versions;
+use crate::report::format_bytes;
+
+const GIB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum Provider {
+    Aws,
+    Wasabi,
+    B2,
+}
+
+/// monthly price per GiB, by storage class
+pub struct PriceTable {
+    classes: HashMap<String, f64>,
+    /// used for storage classes not listed in classes
+    default: Option<f64>,
+}
+
+impl PriceTable {
+    /// list prices as of writing. AWS uses us-east-1; Wasabi and B2 charge a flat rate for everything
+    pub fn builtin(provider: Provider) -> PriceTable {
+        match provider {
+            Provider::Aws => PriceTable {
+                classes: [
+                    ("STANDARD", 0.023),
+                    ("REDUCED_REDUNDANCY", 0.024),
+                    ("STANDARD_IA", 0.0125),
+                    ("ONEZONE_IA", 0.01),
+                    ("INTELLIGENT_TIERING", 0.023),
+                    ("GLACIER_IR", 0.004),
+                    ("GLACIER", 0.0036),
+                    ("DEEP_ARCHIVE", 0.00099),
+                ].iter().map(|(class, price)| (class.to_string(), *price)).collect(),
+                default: None,
+            },
+            Provider::Wasabi => PriceTable { classes: HashMap::new(), default: Some(0.0068) },
+            Provider::B2 => PriceTable { classes: HashMap::new(), default: Some(0.006) },
+        }
+    }
+
+    /// parses lines of `STORAGE_CLASS price`, where `*` sets the price for unlisted classes.
+    /// blank lines and lines starting with '#' are ignored
+    pub fn parse(text: &str) -> Result<PriceTable, Box<dyn Error>> {
+        let mut table = PriceTable { classes: HashMap::new(), default: None };
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (Some(class), Some(price), None) = (parts.next(), parts.next(), parts.next()) else {
+                return Err(format!("line {}: expected '<storage class> <price per GiB>'", idx + 1).into());
+            };
+            let price: f64 = price.parse().map_err(|_| format!("line {}: invalid price '{}'", idx + 1, price))?;
+            if class == "*" {
+                table.default = Some(price);
+            } else {
+                table.classes.insert(class.to_ascii_uppercase(), price);
+            }
+        }
+        Ok(table)
+    }
+
+    fn price(&self, class: &str) -> Option<f64> {
+        self.classes.get(class).copied().or(self.default)
+    }
+}
+
+#[derive(Default)]
+struct ClassUsage {
+    current_bytes: i64,
+    noncurrent_bytes: i64,
+    versions: usize,
+}
+
+/// prints the estimated monthly storage cost of everything under prefix, including noncurrent versions
+pub async fn run(client: &Client, bucket_name: &str, prefix: &str, prices: &PriceTable) -> Result<(), Box<dyn Error>> {
+    let (versions, _) = list_all_versions(client, bucket_name, prefix).await?;
+    let mut usage: BTreeMap<String, ClassUsage> = BTreeMap::new();
+    let mut keys = HashSet::new();
+    for version in &versions {
+        let class = version.storage_class().map(|c| c.as_str()).unwrap_or("STANDARD");
+        let entry = usage.entry(class.to_string()).or_default();
+        if version.is_latest() {
+            entry.current_bytes += version.size();
+        } else {
+            entry.noncurrent_bytes += version.size();
+        }
+        entry.versions += 1;
+        keys.insert(version.key().unwrap_or_default());
+    }
+
+    let mut total = 0.0;
+    println!("{:<20} {:>12} {:>12} {:>10}", "storage class", "current", "noncurrent", "monthly");
+    for (class, class_usage) in &usage {
+        let bytes = class_usage.current_bytes + class_usage.noncurrent_bytes;
+        let cost = match prices.price(class) {
+            Some(price) => {
+                let cost = bytes as f64 / GIB * price;
+                total += cost;
+                format!("${:.2}", cost)
+            }
+            None => "no price".to_string(),
+        };
+        println!("{:<20} {:>12} {:>12} {:>10}", class, format_bytes(class_usage.current_bytes),
+                 format_bytes(class_usage.noncurrent_bytes), cost);
+    }
+    let version_count: usize = usage.values().map(|u| u.versions).sum();
+    println!("estimated total: ${:.2}/month ({} versions of {} keys)", total, version_count, keys.len());
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::time::Duration;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::presigning::{PresignedRequest, PresigningConfig};
+use crate::payer;
+use crate::upload::HeaderArgs;
+
+/// how long the URL in an emitted command works for
+const EXPIRES: Duration = Duration::from_secs(60 * 60);
+
+/// an operation to print a curl command for instead of doing it
+pub enum Operation<'a> {
+    Get { key: &'a str, version_id: Option<&'a str>, file_path: &'a str },
+    Put { key: &'a str, file_path: &'a str, content_type: &'a str, headers: &'a HeaderArgs },
+    Delete { key: &'a str, version_id: &'a str },
+}
+
+/// single quotes s for a POSIX shell
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// prints a curl command that does operation with a presigned URL, so it can be run without
+/// this tool or its credentials
+pub async fn emit(client: &Client, bucket_name: &str, operation: Operation<'_>) -> Result<(), Box<dyn Error>> {
+    let config = PresigningConfig::expires_in(EXPIRES)?;
+    let (presigned, args): (PresignedRequest, Vec<String>) = match operation {
+        Operation::Get { key, version_id, file_path } => {
+            let presigned = client.get_object()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(key)
+                .set_version_id(version_id.map(str::to_string))
+                .presigned(config)
+                .await?;
+            (presigned, vec!["-o".to_string(), quote(file_path)])
+        }
+        Operation::Put { key, file_path, content_type, headers } => {
+            let request = client.put_object()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(key)
+                .content_type(content_type);
+            let presigned = headers.apply(request)
+                .presigned(config)
+                .await?;
+            (presigned, vec!["-T".to_string(), quote(file_path)])
+        }
+        Operation::Delete { key, version_id } => {
+            let presigned = client.delete_object()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(key)
+                .version_id(version_id)
+                .presigned(config)
+                .await?;
+            (presigned, vec!["-X".to_string(), "DELETE".to_string()])
+        }
+    };
+    let mut command = vec!["curl".to_string(), "-fsS".to_string()];
+    command.extend(args);
+    // signed headers the request has to be sent with
+    for (name, value) in presigned.headers() {
+        command.push("-H".to_string());
+        command.push(quote(&format!("{}: {}", name, value.to_str()?)));
+    }
+    command.push(quote(&presigned.uri().to_string()));
+    println!("{}", command.join(" "));
+    eprintln!("note: the URL expires in {} minutes", EXPIRES.as_secs() / 60);
+    Ok(())
+}
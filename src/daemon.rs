@@ -0,0 +1,289 @@
+use std::env;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, Interest};
+use tokio::net::{UnixListener, UnixStream};
+use crate::config;
+
+/// how long a client that has connected gets to send its command
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// the longest command line a client may send
+const MAX_REQUEST: u64 = 1024 * 1024;
+
+/// stdin, stdout and stderr, which the client passes so the command reads and writes its own
+const STDIO: [RawFd; 3] = [0, 1, 2];
+
+/// where the daemon listens unless --daemon-socket says otherwise: next to the config file
+pub fn default_socket() -> Option<PathBuf> {
+    config::path().and_then(|config| Some(config.parent()?.join("daemon.sock")))
+}
+
+fn socket_path(socket: Option<&Path>) -> Result<PathBuf, Box<dyn Error>> {
+    match socket {
+        Some(socket) => Ok(socket.to_path_buf()),
+        None => default_socket().ok_or_else(|| "there's no config directory for the daemon's socket. use --daemon-socket".into()),
+    }
+}
+
+/// sends data along with fds, which the receiver gets as fds of its own
+fn send_with_fds(socket: &StdUnixStream, data: &[u8], fds: &[RawFd]) -> io::Result<()> {
+    let fds_len = mem::size_of_val(fds) as u32;
+    let mut iov = libc::iovec { iov_base: data.as_ptr() as *mut _, iov_len: data.len() };
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(fds_len) } as usize];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = control.len() as _;
+    // the control buffer has room for exactly this one header and its fds
+    let sent = unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+        ptr::copy_nonoverlapping(fds.as_ptr(), libc::CMSG_DATA(cmsg).cast::<RawFd>(), fds.len());
+        libc::sendmsg(socket.as_raw_fd(), &msg, 0)
+    };
+    match sent {
+        n if n < 0 => Err(io::Error::last_os_error()),
+        n if n as usize != data.len() => Err(io::Error::new(ErrorKind::WriteZero, "the daemon took part of the request")),
+        _ => Ok(()),
+    }
+}
+
+/// reads into data, returning how much was read and the fds that came with it
+fn recv_with_fds(socket: RawFd, data: &mut [u8]) -> io::Result<(usize, Vec<OwnedFd>)> {
+    let mut iov = libc::iovec { iov_base: data.as_mut_ptr().cast(), iov_len: data.len() };
+    let mut control = vec![0u8; unsafe { libc::CMSG_SPACE(mem::size_of_val(&STDIO) as u32) } as usize];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = control.len() as _;
+    let read = unsafe { libc::recvmsg(socket, &mut msg, 0) };
+    if read < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut fds = Vec::new();
+    // recvmsg filled in the headers, and each fd it passed is now ours to close
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg).cast::<RawFd>();
+                let count = ((*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize) / mem::size_of::<RawFd>();
+                for idx in 0..count {
+                    let fd = ptr::read_unaligned(data.add(idx));
+                    // hooks the command runs shouldn't inherit the client's terminal
+                    libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                    fds.push(OwnedFd::from_raw_fd(fd));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(io::Error::new(ErrorKind::InvalidData, "the client sent more fds than stdin, stdout and stderr"));
+    }
+    Ok((read as usize, fds))
+}
+
+/// runs this command line in the daemon at socket, handing it this process's stdin, stdout and
+/// stderr, and returns its exit status. None means no daemon is listening there
+pub fn forward(socket: Option<&Path>) -> Result<Option<i32>, Box<dyn Error>> {
+    let path = socket_path(socket)?;
+    let mut stream = match StdUnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(err) if matches!(err.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+            eprintln!("note: no daemon is listening on {}, running here", path.display());
+            return Ok(None);
+        }
+        Err(err) => return Err(format!("can't reach the daemon on {}: {}", path.display(), err).into()),
+    };
+    // the working directory and then the arguments, each ending in a NUL
+    let mut request = Vec::new();
+    for part in std::iter::once(env::current_dir()?.into_os_string()).chain(env::args_os()) {
+        request.extend_from_slice(part.as_bytes());
+        request.push(0);
+    }
+    send_with_fds(&stream, &(request.len() as u64).to_be_bytes(), &STDIO)?;
+    stream.write_all(&request)?;
+    let mut status = [0; 4];
+    match stream.read_exact(&mut status) {
+        Ok(()) => Ok(Some(i32::from_be_bytes(status))),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Err("the daemon hung up before the command finished".into()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// the socket the daemon accepts commands on. it's removed when the daemon stops
+pub struct Listener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Listener {
+    /// listens on socket, which only this user may connect to. a socket left by a daemon that
+    /// died is replaced, but one that's still answering isn't
+    pub fn bind(socket: Option<&Path>) -> Result<Listener, Box<dyn Error>> {
+        let path = socket_path(socket)?;
+        if path.exists() {
+            if StdUnixStream::connect(&path).is_ok() {
+                return Err(format!("a daemon is already listening on {}", path.display()).into());
+            }
+            fs::remove_file(&path)?;
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let listener = UnixListener::bind(&path).map_err(|err| format!("can't listen on {}: {}", path.display(), err))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        Ok(Listener { listener, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// waits for the next command. clients run by other users, and ones that don't send a whole
+    /// command in time, are reported and dropped
+    pub async fn accept(&self) -> Result<Request, Box<dyn Error>> {
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let uid = unsafe { libc::getuid() };
+            if stream.peer_cred().map(|cred| cred.uid()).ok() != Some(uid) {
+                eprintln!("daemon: refused a connection from another user");
+                continue;
+            }
+            match tokio::time::timeout(REQUEST_TIMEOUT, Request::read(stream)).await {
+                Ok(Ok(request)) => return Ok(request),
+                Ok(Err(err)) => eprintln!("daemon: bad request: {}", err),
+                Err(_) => eprintln!("daemon: no request after {}s", REQUEST_TIMEOUT.as_secs()),
+            }
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// a command line a client sent, with its working directory and stdio
+pub struct Request {
+    stream: UnixStream,
+    cwd: PathBuf,
+    args: Vec<OsString>,
+    stdio: Vec<OwnedFd>,
+}
+
+impl Request {
+    async fn read(mut stream: UnixStream) -> Result<Request, Box<dyn Error>> {
+        let mut len = [0; 8];
+        let (mut read, stdio) = stream.async_io(Interest::READABLE, || recv_with_fds(stream.as_raw_fd(), &mut len)).await?;
+        if stdio.len() != STDIO.len() {
+            return Err("the client didn't pass stdin, stdout and stderr".into());
+        }
+        while read < len.len() {
+            match stream.read(&mut len[read..]).await? {
+                0 => return Err("the client hung up".into()),
+                n => read += n,
+            }
+        }
+        let len = u64::from_be_bytes(len);
+        if len > MAX_REQUEST {
+            return Err(format!("a {} byte command line is too long", len).into());
+        }
+        let mut request = vec![0; len as usize];
+        stream.read_exact(&mut request).await?;
+        let mut parts = request.split(|b| *b == 0).map(|part| OsStr::from_bytes(part).to_os_string());
+        let cwd = PathBuf::from(parts.next().ok_or("the request is empty")?);
+        let mut args: Vec<OsString> = parts.collect();
+        // what follows the last NUL
+        args.pop();
+        Ok(Request { stream, cwd, args, stdio })
+    }
+
+    /// the command line, starting with the program name
+    pub fn args(&self) -> &[OsString] {
+        &self.args
+    }
+
+    /// points this process's stdin, stdout and stderr at the client's and moves to its working
+    /// directory, until the guard is dropped
+    pub fn attach(&self) -> io::Result<Attached> {
+        let cwd = env::current_dir()?;
+        let mut saved = Vec::new();
+        for fd in STDIO {
+            let copy = unsafe { libc::dup(fd) };
+            if copy < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            saved.push(unsafe { OwnedFd::from_raw_fd(copy) });
+        }
+        env::set_current_dir(&self.cwd)?;
+        let attached = Attached { saved, cwd };
+        flush();
+        for (fd, client) in STDIO.iter().zip(&self.stdio) {
+            if unsafe { libc::dup2(client.as_raw_fd(), *fd) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(attached)
+    }
+
+    /// returns once the client has gone away, as when it's interrupted
+    pub async fn hung_up(&self) {
+        let mut buf = [0; 64];
+        loop {
+            if self.stream.readable().await.is_err() {
+                return;
+            }
+            match self.stream.try_read(&mut buf) {
+                Ok(0) => return,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => return,
+                // the client has nothing more to say, so anything it does is ignored
+                Ok(_) => {}
+            }
+        }
+    }
+
+    /// tells the client how the command ended
+    pub async fn finish(mut self, status: i32) {
+        let _ = self.stream.write_all(&status.to_be_bytes()).await;
+    }
+}
+
+fn flush() {
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+}
+
+/// puts back the daemon's own stdio and working directory
+pub struct Attached {
+    saved: Vec<OwnedFd>,
+    cwd: PathBuf,
+}
+
+impl Drop for Attached {
+    fn drop(&mut self) {
+        flush();
+        for (fd, saved) in STDIO.iter().zip(&self.saved) {
+            unsafe { libc::dup2(saved.as_raw_fd(), *fd) };
+        }
+        let _ = env::set_current_dir(&self.cwd);
+    }
+}
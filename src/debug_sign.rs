@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use aws_credential_types::cache::ProvideCachedCredentials;
+use aws_sdk_s3::{config, Client};
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use clap::ValueEnum;
+use http::request::Parts;
+use http::{Request, Response};
+use hyper::service::Service;
+use crate::payer;
+use crate::sigv4::{self, Authorization};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Method {
+    Get,
+    Head,
+    Put,
+    Delete,
+}
+
+/// a connector that keeps the first request it's given instead of sending it
+#[derive(Clone, Default)]
+struct Capture {
+    request: Arc<Mutex<Option<Parts>>>,
+}
+
+impl Service<Request<SdkBody>> for Capture {
+    type Response = Response<SdkBody>;
+    type Error = ConnectorError;
+    type Future = std::future::Ready<Result<Response<SdkBody>, ConnectorError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), ConnectorError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<SdkBody>) -> Self::Future {
+        self.request.lock().unwrap().get_or_insert(request.into_parts().0);
+        // a user error, so the SDK doesn't retry
+        std::future::ready(Err(ConnectorError::user("captured by DebugSign".into())))
+    }
+}
+
+/// builds the request the SDK would send for method on key, without sending it, and prints how it was signed
+pub async fn run(builder: config::Builder, bucket_name: &str, method: Method, key: &str) -> Result<(), Box<dyn Error>> {
+    let capture = Capture::default();
+    let config = builder.http_connector(DynConnector::new(capture.clone())).build();
+    let credentials = config.credentials_cache().provide_cached_credentials().await?;
+    let client = Client::from_conf(config);
+    // each of these fails once the request reaches Capture
+    match method {
+        Method::Get => {
+            let _ = client.get_object().bucket(bucket_name).set_request_payer(payer::get()).key(key).send().await;
+        }
+        Method::Head => {
+            let _ = client.head_object().bucket(bucket_name).set_request_payer(payer::get()).key(key).send().await;
+        }
+        Method::Put => {
+            let _ = client.put_object().bucket(bucket_name).set_request_payer(payer::get()).key(key).send().await;
+        }
+        Method::Delete => {
+            let _ = client.delete_object().bucket(bucket_name).set_request_payer(payer::get()).key(key).send().await;
+        }
+    }
+    let request = capture.request.lock().unwrap().take().ok_or("the SDK didn't build a request")?;
+
+    let header = request.headers.get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .ok_or("the request wasn't signed")?
+        .to_string();
+    let authorization = Authorization::parse(&header).ok_or("the Authorization header isn't SigV4")?;
+    let amz_date = request.headers.get("x-amz-date").and_then(|v| v.to_str().ok()).unwrap_or_default();
+
+    let canonical = sigv4::canonical_request(&request.method, &request.uri, &request.headers, authorization.signed_headers);
+    let string_to_sign = sigv4::string_to_sign(amz_date, authorization.scope, &canonical);
+    let recomputed = sigv4::signature(credentials.secret_access_key(), authorization.scope, &string_to_sign)?;
+
+    println!("{} {}\n", request.method, request.uri);
+    println!("canonical request:\n{}\n", canonical);
+    println!("string to sign:\n{}\n", string_to_sign);
+    println!("authorization:\n{}", header);
+    if recomputed != authorization.signature {
+        println!("\nwarning: recomputing the signature gave {}, so the canonical request above may differ from the SDK's", recomputed);
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::types::ChecksumMode;
+use md5::{Digest, Md5};
+use crate::checksum::Sha256;
+use crate::payer;
+
+/// the most versions an all-versions scan looks at before giving up
+pub const SCAN_LIMIT: usize = 10_000;
+
+/// the contents being uploaded, with their MD5 computed only if an object without a stored SHA-256 needs it
+struct Candidate<'a> {
+    bytes: &'a [u8],
+    checksum: &'a Sha256,
+    md5: Option<String>,
+}
+
+impl Candidate<'_> {
+    /// compares by the stored SHA-256, or by ETag for objects uploaded without one
+    fn matches(&mut self, checksum_sha256: Option<&str>, e_tag: Option<&str>) -> bool {
+        match checksum_sha256 {
+            Some(stored) => stored == self.checksum.base64(),
+            None => {
+                let bytes = self.bytes;
+                let md5 = self.md5.get_or_insert_with(|| format!("{:x}", Md5::digest(bytes)));
+                e_tag.unwrap_or_default().trim_matches('"').eq_ignore_ascii_case(md5)
+            }
+        }
+    }
+}
+
+/// the version id of the key's current version if it has the same contents
+async fn check_latest(client: &Client, bucket_name: &str, key: &str, candidate: &mut Candidate<'_>) -> Result<Option<String>, Box<dyn Error>> {
+    let result = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .checksum_mode(ChecksumMode::Enabled)
+        .send()
+        .await;
+    let head = match result {
+        Ok(head) => head,
+        // no object, or a delete marker on top
+        Err(SdkError::ServiceError(err)) if err.err().is_not_found() => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    if head.content_length() != candidate.bytes.len() as i64 || !candidate.matches(head.checksum_sha256(), head.e_tag()) {
+        return Ok(None);
+    }
+    Ok(Some(head.version_id().unwrap_or("null").to_string()))
+}
+
+/// the id of any version of key with the same contents, looking at up to SCAN_LIMIT versions
+async fn scan_versions(client: &Client, bucket_name: &str, key: &str, candidate: &mut Candidate<'_>) -> Result<Option<String>, Box<dyn Error>> {
+    let mut key_marker: Option<String> = None;
+    let mut version_marker: Option<String> = None;
+    let mut scanned = 0;
+    loop {
+        let result = client.list_object_versions()
+            .bucket(bucket_name)
+            .prefix(key)
+            .set_key_marker(key_marker)
+            .set_version_id_marker(version_marker)
+            .send()
+            .await?;
+        for version in result.versions().unwrap_or_default() {
+            // versions are listed in key order, so once past key there's nothing left to find
+            match version.key() {
+                Some(listed) if listed > key => return Ok(None),
+                Some(listed) if listed == key => {}
+                _ => continue,
+            }
+            scanned += 1;
+            let Some(version_id) = version.version_id() else { continue };
+            // only versions of the same size can match, which saves most of the HEADs
+            if version.size() != candidate.bytes.len() as i64 {
+                continue;
+            }
+            let head = client.head_object()
+                .bucket(bucket_name)
+                .key(key)
+                .version_id(version_id)
+                .checksum_mode(ChecksumMode::Enabled)
+                .send()
+                .await?;
+            if candidate.matches(head.checksum_sha256(), version.e_tag()) {
+                return Ok(Some(version_id.to_string()));
+            }
+        }
+        if scanned >= SCAN_LIMIT {
+            eprintln!("note: stopped looking for duplicates after {} versions", scanned);
+            return Ok(None);
+        }
+        key_marker = result.next_key_marker;
+        version_marker = result.next_version_id_marker;
+        if !result.is_truncated || key_marker.is_none() {
+            return Ok(None);
+        }
+    }
+}
+
+/// the id of a version of key whose contents are bytes, if one exists. only the current version
+/// is checked unless all_versions is set
+pub async fn find_duplicate(client: &Client, bucket_name: &str, key: &str, bytes: &[u8], checksum: &Sha256, all_versions: bool) -> Result<Option<String>, Box<dyn Error>> {
+    let mut candidate = Candidate { bytes, checksum, md5: None };
+    if let Some(version_id) = check_latest(client, bucket_name, key, &mut candidate).await? {
+        return Ok(Some(version_id));
+    }
+    if !all_versions {
+        return Ok(None);
+    }
+    scan_versions(client, bucket_name, key, &mut candidate).await
+}
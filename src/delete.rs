@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::operation::{RequestId, RequestIdExt};
+use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use crate::errors::ErrorDetails;
+use crate::failures::{FailedOp, FailureLog, Operation};
+use crate::listing::{object_pages, version_pages};
+use crate::payer;
+use crate::progress::Progress;
+use crate::stop;
+use crate::summary::Summary;
+
+/// the most keys a single delete_objects request accepts
+pub const MAX_BATCH: usize = 1000;
+
+/// a key to delete. without a version id, deleting a key in a versioned bucket adds a delete marker
+#[derive(Clone, Debug)]
+pub struct ObjectRef {
+    pub key: String,
+    pub version_id: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DeleteFailure {
+    pub object: ObjectRef,
+    pub error: ErrorDetails,
+}
+
+/// deletes up to MAX_BATCH objects in one request, returning the ones that failed.
+/// if the request itself fails, every object in the batch is reported with that error
+pub async fn delete_batch(client: &Client, bucket_name: &str, batch: &[ObjectRef]) -> Vec<DeleteFailure> {
+    let objects = batch.iter()
+        .map(|o| ObjectIdentifier::builder().key(&o.key).set_version_id(o.version_id.clone()).build())
+        .collect();
+    let result = client.delete_objects()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .delete(Delete::builder().set_objects(Some(objects)).quiet(true).build())
+        .send()
+        .await;
+    match result {
+        Ok(output) => output.errors().unwrap_or_default().iter()
+            .map(|error| DeleteFailure {
+                object: ObjectRef {
+                    key: error.key().unwrap_or_default().to_string(),
+                    version_id: error.version_id().map(|v| v.to_string()),
+                },
+                error: ErrorDetails {
+                    message: error.message().unwrap_or_default().to_string(),
+                    code: error.code().map(|c| c.to_string()),
+                    request_id: output.request_id().map(|id| id.to_string()),
+                    extended_request_id: output.extended_request_id().map(|id| id.to_string()),
+                },
+            })
+            .collect(),
+        Err(err) => batch.iter()
+            .map(|object| DeleteFailure { object: object.clone(), error: ErrorDetails::from_error(&err) })
+            .collect(),
+    }
+}
+
+/// deletes objects one batch at a time, printing failures, and returns how many were deleted
+pub async fn delete_versions(client: &Client, bucket_name: &str, objects: &[ObjectRef]) -> Result<usize, Box<dyn Error>> {
+    let mut deleted = 0;
+    for batch in objects.chunks(MAX_BATCH) {
+        let failures = delete_batch(client, bucket_name, batch).await;
+        for failure in &failures {
+            println!("failed to delete {} ({}): {}", failure.object.key,
+                     failure.object.version_id.as_deref().unwrap_or("null"), failure.error);
+        }
+        deleted += batch.len() - failures.len();
+    }
+    Ok(deleted)
+}
+
+pub struct RmOptions {
+    /// delete every version and delete marker instead of adding delete markers
+    pub all_versions: bool,
+    /// delete_objects requests in flight at once
+    pub concurrency: usize,
+    /// JSON lines file recording objects that couldn't be deleted, for Retry
+    pub failures_out: Option<PathBuf>,
+    /// count keys and versions that are already gone as skipped, so a rerun after an
+    /// interrupted one doesn't fail on them
+    pub ignore_missing: bool,
+}
+
+/// the error codes for a key or version that isn't there to delete
+pub fn is_missing(code: Option<&str>) -> bool {
+    matches!(code, Some("NoSuchKey") | Some("NoSuchVersion"))
+}
+
+/// deletes everything under prefix using concurrent batches, starting on each page of the
+/// listing as it arrives
+pub async fn rm(client: &Client, bucket_name: &str, prefix: &str, options: &RmOptions) -> Result<(), Box<dyn Error>> {
+    // each target with its size, for the summary
+    let pages = if options.all_versions {
+        version_pages(client, bucket_name, prefix)
+            .map_ok(|(versions, markers)| versions.into_iter().map(|v| (v.key, v.version_id, v.size))
+                .chain(markers.into_iter().map(|m| (m.key, m.version_id, 0)))
+                .filter_map(|(key, version_id, size)| Some((ObjectRef { key: key?, version_id }, size)))
+                .collect::<Vec<_>>())
+            .left_stream()
+    } else {
+        object_pages(client, bucket_name, prefix)
+            .map_ok(|page| page.into_iter()
+                .filter_map(|o| Some((ObjectRef { key: o.key?, version_id: None }, o.size)))
+                .collect::<Vec<_>>())
+            .right_stream()
+    };
+    // a version page can hold more than a delete request takes
+    let batches = pages
+        .map_ok(|targets| stream::iter(targets.chunks(MAX_BATCH).map(|batch| Ok::<_, Box<dyn Error>>(batch.to_vec())).collect::<Vec<_>>()))
+        .try_flatten();
+
+    let mut failures = FailureLog::new(options.failures_out.as_deref())?;
+    let mut progress = Progress::counter("deleting");
+    let mut summary = Summary::start("rm", "deleted");
+    let mut results = pin!(batches
+        .map_ok(|batch| async move {
+            let (targets, sizes): (Vec<ObjectRef>, Vec<i64>) = batch.into_iter().unzip();
+            let batch_failures = delete_batch(client, bucket_name, &targets).await;
+            let failed_bytes: i64 = batch_failures.iter()
+                .filter_map(|failure| targets.iter().position(|t| t.key == failure.object.key && t.version_id == failure.object.version_id))
+                .map(|idx| sizes[idx])
+                .sum();
+            let bytes = sizes.iter().sum::<i64>() - failed_bytes;
+            Ok((targets.len(), bytes, batch_failures))
+        })
+        .try_buffer_unordered(options.concurrency.max(1)));
+    while let Some((count, bytes, batch_failures)) = stop::within(results.try_next()).await?? {
+        summary.transferred += count.saturating_sub(batch_failures.len());
+        summary.bytes += bytes.max(0) as u64;
+        for failure in batch_failures {
+            if options.ignore_missing && is_missing(failure.error.code.as_deref()) {
+                summary.skipped += 1;
+                continue;
+            }
+            failures.record(FailedOp {
+                operation: Operation::Delete,
+                bucket: bucket_name.to_string(),
+                key: failure.object.key,
+                version_id: failure.object.version_id,
+                local_path: None,
+                symlink_target: None,
+                put_options: None,
+                error: failure.error,
+            })?;
+        }
+        progress.add(count as u64);
+    }
+    progress.finish();
+
+    summary.failed = failures.count();
+    summary.print();
+    failures.finish(summary.transferred + summary.failed)
+}
@@ -0,0 +1,69 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::BucketVersioningStatus;
+use crate::arn::BucketArn;
+use crate::clock;
+use crate::errors::ErrorDetails;
+use crate::payer;
+use crate::region;
+
+/// checks the endpoint, credentials, clock and bucket settings, printing a line for each
+pub async fn run(client: &Client, bucket_name: &str) -> Result<(), Box<dyn Error>> {
+    let mut failed = 0;
+
+    let listing = client.list_objects_v2()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .max_keys(1)
+        .send()
+        .await;
+    match listing {
+        Ok(_) => println!("ok    list {}", bucket_name),
+        Err(err) => {
+            match region::redirect_hint(&err, client.conf().region()) {
+                Some(region) => println!("FAIL  list {}: the bucket is in {}", bucket_name, region),
+                None => println!("FAIL  list {}: {}", bucket_name, ErrorDetails::from_error(&err)),
+            }
+            failed += 1;
+        }
+    }
+
+    match clock::measured() {
+        Some(skew) if skew.abs() > clock::MAX_SKEW => {
+            println!("FAIL  {}. requests are re-signed to match, but the clock should be fixed", clock::describe(skew));
+            failed += 1;
+        }
+        Some(skew) if skew.abs() > clock::TOLERANCE => println!("warn  {}", clock::describe(skew)),
+        Some(skew) => println!("ok    {}", clock::describe(skew)),
+        None => println!("skip  clock: the server didn't send a Date header"),
+    }
+
+    if let Ok(Some(arn)) = BucketArn::parse(bucket_name) {
+        println!("skip  versioning: not available through {}", arn);
+        return summary(failed);
+    }
+    let versioning = client.get_bucket_versioning()
+        .bucket(bucket_name)
+        .send()
+        .await;
+    match versioning {
+        Ok(result) if result.status() == Some(&BucketVersioningStatus::Enabled) => println!("ok    versioning enabled"),
+        Ok(_) => {
+            println!("FAIL  versioning not enabled");
+            failed += 1;
+        }
+        Err(err) => {
+            println!("FAIL  versioning: {}", ErrorDetails::from_error(&err));
+            failed += 1;
+        }
+    }
+
+    summary(failed)
+}
+
+fn summary(failed: usize) -> Result<(), Box<dyn Error>> {
+    if failed > 0 {
+        return Err(format!("{} checks failed", failed).into());
+    }
+    Ok(())
+}
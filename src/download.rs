@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::io::SeekFrom;
+use std::path::Path;
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::operation::get_object::builders::GetObjectFluentBuilder;
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use crate::compression::Compression;
+use crate::encryption;
+use crate::errors::ErrorDetails;
+use crate::failures::{FailedOp, FailureLog, Operation};
+use crate::key;
+use crate::listing::object_pages_from;
+use crate::payer;
+use crate::progress::Progress;
+use crate::resume::ResumeFile;
+use crate::stop;
+use crate::summary::Summary;
+
+/// the part of key below prefix, or the key's last segment if nothing is left
+pub fn relative_path<'a>(prefix: &str, key: &'a str) -> &'a str {
+    match key.strip_prefix(prefix).map(|rest| rest.trim_start_matches('/')) {
+        Some(rest) if !rest.is_empty() => rest,
+        _ => key.rsplit('/').next().unwrap_or(key),
+    }
+}
+
+/// streams key (at version_id, if given) into path, creating parent directories. returns the bytes written
+pub async fn download_to(client: &Client, bucket_name: &str, key: &str, version_id: Option<&str>, path: &Path) -> Result<u64, Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let result = client.get_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .set_version_id(version_id.map(|v| v.to_string()))
+        .send()
+        .await?;
+    let mut file = tokio::fs::File::create(path).await?;
+    Ok(tokio::io::copy(&mut result.body.into_async_read(), &mut file).await?)
+}
+
+pub struct RangedOptions {
+    /// bytes fetched by each ranged GET
+    pub part_size: u64,
+    /// ranged GETs in flight at once
+    pub concurrency: usize,
+}
+
+/// whether the stored bytes are the file, so they can be fetched in pieces. encrypted objects, and
+/// compressed ones unless decompression is skipped, have to be processed whole
+pub fn is_plain(head: &HeadObjectOutput, no_decompress: bool) -> bool {
+    let encrypted = head.metadata().is_some_and(|m| m.contains_key(encryption::ALGORITHM_KEY));
+    let compressed = head.content_encoding().and_then(Compression::from_content_encoding).is_some();
+    !encrypted && (no_decompress || !compressed)
+}
+
+/// a GET of the inclusive byte range of the version head describes. the version id, or failing
+/// that the etag, keeps every range from the same object
+pub fn range_get(client: &Client, bucket_name: &str, key: &str, head: &HeadObjectOutput, (start, end): (u64, u64)) -> GetObjectFluentBuilder {
+    client.get_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .set_version_id(head.version_id().map(str::to_string))
+        .set_if_match(head.e_tag().filter(|_| head.version_id().is_none()).map(str::to_string))
+        .range(format!("bytes={}-{}", start, end))
+}
+
+/// fetches the inclusive byte range of the version head describes and writes it at the same offset in path
+async fn download_range(client: &Client, bucket_name: &str, key: &str, head: &HeadObjectOutput, (start, end): (u64, u64), path: &Path) -> Result<u64, Box<dyn Error>> {
+    let result = range_get(client, bucket_name, key, head, (start, end)).send().await?;
+    let mut file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    let written = tokio::io::copy(&mut result.body.into_async_read(), &mut file).await?;
+    file.flush().await?;
+    if written != end - start + 1 {
+        return Err(format!("bytes {}-{} of {}: got {} bytes", start, end, key, written).into());
+    }
+    Ok(written)
+}
+
+/// downloads the object head describes as concurrent ranged GETs, each written at its offset in a
+/// file preallocated to the object's size. returns the bytes written
+pub async fn download_ranged(client: &Client, bucket_name: &str, key: &str, head: &HeadObjectOutput, path: &Path, options: &RangedOptions) -> Result<u64, Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let size = head.content_length().max(0) as u64;
+    tokio::fs::File::create(path).await?.set_len(size).await?;
+
+    let part_size = options.part_size.max(1);
+    let ranges: Vec<(u64, u64)> = (0..size).step_by(part_size as usize)
+        .map(|start| (start, (start + part_size).min(size) - 1))
+        .collect();
+    let mut progress = Progress::bytes("downloading", key, size);
+    let mut results = stream::iter(ranges)
+        .map(|range| download_range(client, bucket_name, key, head, range, path))
+        .buffer_unordered(options.concurrency.max(1));
+    while let Some(result) = results.next().await {
+        progress.add(result?);
+    }
+    progress.finish();
+    Ok(size)
+}
+
+/// downloads every object under prefix into local_dir, continuing past objects that fail.
+/// downloads start as soon as the first page of the listing arrives, and each page finishes before
+/// the next starts. with resume set, the token for the next page is saved there after each one, so
+/// a stopped run can pick up where it left off
+pub async fn download_prefix(client: &Client, bucket_name: &str, prefix: &str, local_dir: &str, concurrency: usize, failures_out: Option<&Path>, resume: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let resume = resume.map(|path| ResumeFile::new(path, bucket_name, prefix));
+    let start = match &resume {
+        Some(resume) => resume.load()?,
+        None => None,
+    };
+    let mut failures = FailureLog::new(failures_out)?;
+    let mut progress = Progress::objects("downloading", None);
+    let mut summary = Summary::start("download", "downloaded");
+    let mut pages = pin!(object_pages_from(client, bucket_name, prefix, start));
+    while let Some((page, next)) = stop::within(pages.try_next()).await?? {
+        let (markers, keys): (Vec<String>, Vec<String>) = page.into_iter()
+            .filter_map(|object| object.key)
+            .partition(|key| key.ends_with('/'));
+        // folder markers have nothing to download
+        summary.skipped += markers.len();
+        let mut results = stream::iter(keys)
+            .map(|key| async move {
+                let path = key::local_relative(relative_path(prefix, &key)).map(|relative| Path::new(local_dir).join(relative));
+                let result = match &path {
+                    Ok(path) => download_to(client, bucket_name, &key, None, path).await,
+                    Err(err) => Err(err.to_string().into()),
+                };
+                (key, path.ok(), result)
+            })
+            .buffer_unordered(concurrency.max(1));
+        while let Some((key, path, result)) = stop::within(results.next()).await? {
+            match result {
+                Ok(bytes) => {
+                    println!("got {} ({} bytes)", key, bytes);
+                    summary.transferred += 1;
+                    summary.bytes += bytes;
+                    progress.add_key(&key, 1);
+                }
+                Err(err) => failures.record(FailedOp {
+                    operation: Operation::Get,
+                    bucket: bucket_name.to_string(),
+                    key,
+                    version_id: None,
+                    local_path: path,
+                    symlink_target: None,
+                    put_options: None,
+                    error: ErrorDetails::from_error(err.as_ref()),
+                })?,
+            }
+        }
+        // failed downloads are in the failures log, so the page counts as done either way
+        if let Some(resume) = &resume {
+            resume.save(next.as_deref())?;
+        }
+    }
+    progress.finish();
+    summary.failed = failures.count();
+    summary.print();
+    failures.finish(summary.transferred + summary.failed)
+}
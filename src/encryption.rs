@@ -0,0 +1,43 @@
+use std::error::Error;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// user metadata key recording the client-side encryption algorithm
+pub const ALGORITHM_KEY: &str = "s3test-encryption";
+/// user metadata key holding the hex encoded nonce
+pub const NONCE_KEY: &str = "s3test-nonce";
+pub const ALGORITHM: &str = "aes-256-gcm";
+
+/// reads a 256 bit key from path, either as 32 raw bytes or 64 hex characters
+pub fn read_key(path: &str) -> Result<LessSafeKey, Box<dyn Error>> {
+    let contents = std::fs::read(path)?;
+    let key_bytes = match String::from_utf8(contents.clone()) {
+        Ok(text) if text.trim().len() == 64 => base16ct::mixed::decode_vec(text.trim())
+            .map_err(|_| format!("{} is not a valid hex key", path))?,
+        _ => contents,
+    };
+    let key = UnboundKey::new(&AES_256_GCM, &key_bytes)
+        .map_err(|_| format!("{} must contain a 32 byte key", path))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// encrypts bytes with a fresh random nonce, returning the ciphertext (with tag appended) and the hex nonce.
+/// since the nonce changes every time, encrypted uploads never match an existing version's ETag
+pub fn encrypt(key: &LessSafeKey, mut bytes: Vec<u8>) -> Result<(Vec<u8>, String), Box<dyn Error>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(|_| "failed to generate nonce")?;
+    key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut bytes)
+        .map_err(|_| "encryption failed")?;
+    Ok((bytes, base16ct::lower::encode_string(&nonce)))
+}
+
+/// decrypts bytes produced by encrypt
+pub fn decrypt(key: &LessSafeKey, mut bytes: Vec<u8>, nonce_hex: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let nonce = base16ct::mixed::decode_vec(nonce_hex).map_err(|_| "invalid nonce in object metadata")?;
+    let nonce = Nonce::try_assume_unique_for_key(&nonce).map_err(|_| "invalid nonce in object metadata")?;
+    let plain_len = key.open_in_place(nonce, Aad::empty(), &mut bytes)
+        .map_err(|_| "decryption failed: wrong key or corrupted object")?
+        .len();
+    bytes.truncate(plain_len);
+    Ok(bytes)
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{ObjectVersion, StorageClass};
+use crate::delete::{delete_versions, ObjectRef};
+use crate::key;
+use crate::listing::list_all_versions;
+use crate::payer;
+use crate::report::format_bytes;
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// one `[[rule]]` table from a policy file
+#[derive(Debug)]
+pub struct Rule {
+    pub prefix: String,
+    /// versions to keep per key, counting the current one
+    pub keep_versions: Option<usize>,
+    /// noncurrent versions older than this are deleted. current versions are never expired
+    pub max_age_days: Option<i64>,
+    /// current versions older than this are moved to storage_class
+    pub transition_after_days: Option<i64>,
+    pub storage_class: Option<String>,
+}
+
+/// a retention policy, read from TOML like
+///
+/// ```toml
+/// [[rule]]
+/// prefix = "logs/"
+/// keep_versions = 5
+/// max_age_days = 90
+/// transition_after_days = 30
+/// storage_class = "STANDARD_IA"
+/// ```
+///
+/// each key is governed by the rule with the longest matching prefix
+#[derive(Debug)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+}
+
+impl Policy {
+    pub fn parse(text: &str) -> Result<Policy, Box<dyn Error>> {
+        let table: toml::Table = text.parse()?;
+        let Some(rules) = table.get("rule").and_then(|r| r.as_array()) else {
+            return Err("policy has no [[rule]] tables".into());
+        };
+        let mut policy = Policy { rules: Vec::new() };
+        for (idx, rule) in rules.iter().enumerate() {
+            let rule = rule.as_table().ok_or_else(|| format!("rule {} is not a table", idx + 1))?;
+            let integer = |name: &str| -> Result<Option<i64>, String> {
+                match rule.get(name) {
+                    None => Ok(None),
+                    Some(value) => value.as_integer().filter(|v| *v >= 0).map(Some)
+                        .ok_or_else(|| format!("rule {}: {} must be a non-negative integer", idx + 1, name)),
+                }
+            };
+            let parsed = Rule {
+                prefix: rule.get("prefix").and_then(|p| p.as_str())
+                    .ok_or_else(|| format!("rule {}: prefix is required", idx + 1))?.to_string(),
+                keep_versions: integer("keep_versions")?.map(|v| v as usize),
+                max_age_days: integer("max_age_days")?,
+                transition_after_days: integer("transition_after_days")?,
+                storage_class: rule.get("storage_class").and_then(|c| c.as_str()).map(|c| c.to_ascii_uppercase()),
+            };
+            if parsed.keep_versions == Some(0) {
+                return Err(format!("rule {}: keep_versions must be at least 1", idx + 1).into());
+            }
+            if parsed.transition_after_days.is_some() != parsed.storage_class.is_some() {
+                return Err(format!("rule {}: transition_after_days and storage_class must be set together", idx + 1).into());
+            }
+            policy.rules.push(parsed);
+        }
+        Ok(policy)
+    }
+
+    /// the index of the rule governing key
+    fn rule_for(&self, key: &str) -> Option<usize> {
+        self.rules.iter().enumerate()
+            .filter(|(_, rule)| key.starts_with(&rule.prefix))
+            .max_by_key(|(_, rule)| rule.prefix.len())
+            .map(|(idx, _)| idx)
+    }
+}
+
+/// applies every rule in policy. running it again right away changes nothing
+pub async fn run(client: &Client, bucket_name: &str, policy: &Policy, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    for (idx, rule) in policy.rules.iter().enumerate() {
+        let (versions, _) = list_all_versions(client, bucket_name, &rule.prefix).await?;
+        let mut by_key: BTreeMap<String, Vec<ObjectVersion>> = BTreeMap::new();
+        for version in versions {
+            let Some(key) = version.key() else { continue };
+            if policy.rule_for(key) == Some(idx) {
+                by_key.entry(key.to_string()).or_default().push(version);
+            }
+        }
+
+        let mut expired: Vec<ObjectRef> = Vec::new();
+        let mut expired_bytes = 0;
+        let mut transitions: Vec<(String, String)> = Vec::new();
+        for (key, mut versions) in by_key {
+            versions.sort_by_key(|v| std::cmp::Reverse(v.last_modified().map(|d| d.secs()).unwrap_or(0)));
+            for (position, version) in versions.iter().enumerate() {
+                let age_days = (now - version.last_modified().map(|d| d.secs()).unwrap_or(now)) / DAY_SECS;
+                let version_id = version.version_id().unwrap_or("null").to_string();
+                if version.is_latest() {
+                    let current_class = version.storage_class().map(|c| c.as_str()).unwrap_or("STANDARD");
+                    if let (Some(after), Some(class)) = (rule.transition_after_days, &rule.storage_class) {
+                        if age_days >= after && current_class != class {
+                            transitions.push((key.clone(), version_id));
+                        }
+                    }
+                    continue;
+                }
+                let over_count = rule.keep_versions.is_some_and(|keep| position >= keep);
+                let too_old = rule.max_age_days.is_some_and(|max| age_days > max);
+                if over_count || too_old {
+                    expired_bytes += version.size();
+                    expired.push(ObjectRef { key: key.clone(), version_id: Some(version_id) });
+                }
+            }
+        }
+
+        println!("rule '{}': {} versions to delete ({}), {} objects to transition",
+                 rule.prefix, expired.len(), format_bytes(expired_bytes), transitions.len());
+        if dry_run {
+            for object in &expired {
+                println!("  would delete {} ({})", object.key, object.version_id.as_deref().unwrap_or("null"));
+            }
+            for (key, _) in &transitions {
+                println!("  would move {} to {}", key, rule.storage_class.as_deref().unwrap_or_default());
+            }
+            continue;
+        }
+        let deleted = delete_versions(client, bucket_name, &expired).await?;
+        if let Some(class) = &rule.storage_class {
+            for (key, version_id) in &transitions {
+                transition(client, bucket_name, key, version_id, class).await?;
+                println!("  moved {} to {}", key, class);
+            }
+        }
+        println!("  deleted {} versions", deleted);
+    }
+    Ok(())
+}
+
+/// rewrites the version as the new current version in storage_class, then removes the original
+async fn transition(client: &Client, bucket_name: &str, key: &str, version_id: &str, storage_class: &str) -> Result<(), Box<dyn Error>> {
+    client.copy_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .copy_source(key::copy_source(bucket_name, key, Some(version_id)))
+        .key(key)
+        .storage_class(StorageClass::from(storage_class))
+        .send()
+        .await?;
+    client.delete_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .version_id(version_id)
+        .send()
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::error::Error;
+use std::fmt;
+use aws_sdk_s3::error::{DisplayErrorContext, ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::operation::{RequestId, RequestIdExt};
+use aws_sdk_s3::operation::abort_multipart_upload::AbortMultipartUploadError;
+use aws_sdk_s3::operation::complete_multipart_upload::CompleteMultipartUploadError;
+use aws_sdk_s3::operation::copy_object::CopyObjectError;
+use aws_sdk_s3::operation::create_bucket::CreateBucketError;
+use aws_sdk_s3::operation::create_multipart_upload::CreateMultipartUploadError;
+use aws_sdk_s3::operation::delete_bucket::DeleteBucketError;
+use aws_sdk_s3::operation::delete_bucket_analytics_configuration::DeleteBucketAnalyticsConfigurationError;
+use aws_sdk_s3::operation::delete_bucket_intelligent_tiering_configuration::DeleteBucketIntelligentTieringConfigurationError;
+use aws_sdk_s3::operation::delete_bucket_metrics_configuration::DeleteBucketMetricsConfigurationError;
+use aws_sdk_s3::operation::delete_bucket_tagging::DeleteBucketTaggingError;
+use aws_sdk_s3::operation::delete_bucket_website::DeleteBucketWebsiteError;
+use aws_sdk_s3::operation::delete_object::DeleteObjectError;
+use aws_sdk_s3::operation::delete_objects::DeleteObjectsError;
+use aws_sdk_s3::operation::get_bucket_accelerate_configuration::GetBucketAccelerateConfigurationError;
+use aws_sdk_s3::operation::get_bucket_analytics_configuration::GetBucketAnalyticsConfigurationError;
+use aws_sdk_s3::operation::get_bucket_cors::GetBucketCorsError;
+use aws_sdk_s3::operation::get_bucket_intelligent_tiering_configuration::GetBucketIntelligentTieringConfigurationError;
+use aws_sdk_s3::operation::get_bucket_lifecycle_configuration::GetBucketLifecycleConfigurationError;
+use aws_sdk_s3::operation::get_bucket_location::GetBucketLocationError;
+use aws_sdk_s3::operation::get_bucket_metrics_configuration::GetBucketMetricsConfigurationError;
+use aws_sdk_s3::operation::get_bucket_ownership_controls::GetBucketOwnershipControlsError;
+use aws_sdk_s3::operation::get_bucket_tagging::GetBucketTaggingError;
+use aws_sdk_s3::operation::get_bucket_versioning::GetBucketVersioningError;
+use aws_sdk_s3::operation::get_bucket_website::GetBucketWebsiteError;
+use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::operation::get_object_acl::GetObjectAclError;
+use aws_sdk_s3::operation::get_object_tagging::GetObjectTaggingError;
+use aws_sdk_s3::operation::head_bucket::HeadBucketError;
+use aws_sdk_s3::operation::head_object::HeadObjectError;
+use aws_sdk_s3::operation::list_bucket_analytics_configurations::ListBucketAnalyticsConfigurationsError;
+use aws_sdk_s3::operation::list_bucket_intelligent_tiering_configurations::ListBucketIntelligentTieringConfigurationsError;
+use aws_sdk_s3::operation::list_bucket_metrics_configurations::ListBucketMetricsConfigurationsError;
+use aws_sdk_s3::operation::list_buckets::ListBucketsError;
+use aws_sdk_s3::operation::list_multipart_uploads::ListMultipartUploadsError;
+use aws_sdk_s3::operation::list_object_versions::ListObjectVersionsError;
+use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Error;
+use aws_sdk_s3::operation::put_bucket_accelerate_configuration::PutBucketAccelerateConfigurationError;
+use aws_sdk_s3::operation::put_bucket_analytics_configuration::PutBucketAnalyticsConfigurationError;
+use aws_sdk_s3::operation::put_bucket_cors::PutBucketCorsError;
+use aws_sdk_s3::operation::put_bucket_encryption::PutBucketEncryptionError;
+use aws_sdk_s3::operation::put_bucket_intelligent_tiering_configuration::PutBucketIntelligentTieringConfigurationError;
+use aws_sdk_s3::operation::put_bucket_lifecycle_configuration::PutBucketLifecycleConfigurationError;
+use aws_sdk_s3::operation::put_bucket_metrics_configuration::PutBucketMetricsConfigurationError;
+use aws_sdk_s3::operation::put_bucket_ownership_controls::PutBucketOwnershipControlsError;
+use aws_sdk_s3::operation::put_bucket_tagging::PutBucketTaggingError;
+use aws_sdk_s3::operation::put_bucket_versioning::PutBucketVersioningError;
+use aws_sdk_s3::operation::put_bucket_website::PutBucketWebsiteError;
+use aws_sdk_s3::operation::put_object::PutObjectError;
+use aws_sdk_s3::operation::put_object_acl::PutObjectAclError;
+use aws_sdk_s3::operation::upload_part::UploadPartError;
+use aws_sdk_s3::operation::upload_part_copy::UploadPartCopyError;
+
+/// what S3 reported about a failed request. providers want the request ids in support tickets
+#[derive(Clone, Debug, Default)]
+pub struct ErrorDetails {
+    pub message: String,
+    pub code: Option<String>,
+    pub request_id: Option<String>,
+    pub extended_request_id: Option<String>,
+}
+
+fn from_sdk<E: Error + ProvideErrorMetadata + 'static>(err: &SdkError<E>) -> ErrorDetails {
+    let service = match err {
+        SdkError::ServiceError(service) => Some(service.err()),
+        _ => None,
+    };
+    let message = service.and_then(|e| e.message())
+        .map(|m| m.to_string())
+        .unwrap_or_else(|| DisplayErrorContext(err).to_string());
+    ErrorDetails {
+        message,
+        code: service.and_then(|e| e.code()).map(|c| c.to_string()),
+        request_id: err.request_id().map(|id| id.to_string()),
+        extended_request_id: err.extended_request_id().map(|id| id.to_string()),
+    }
+}
+
+/// tries each operation error this tool can produce, since SdkError is generic over it
+macro_rules! sdk_details {
+    ($err:expr, $($op:ty),+) => {
+        $(
+            if let Some(sdk) = $err.downcast_ref::<SdkError<$op>>() {
+                return from_sdk(sdk);
+            }
+        )+
+    };
+}
+
+impl ErrorDetails {
+    pub fn from_error(err: &(dyn Error + 'static)) -> ErrorDetails {
+        sdk_details!(err, AbortMultipartUploadError, CompleteMultipartUploadError, CopyObjectError, CreateBucketError,
+                     CreateMultipartUploadError, DeleteBucketAnalyticsConfigurationError, DeleteBucketError,
+                     DeleteBucketIntelligentTieringConfigurationError, DeleteBucketMetricsConfigurationError,
+                     DeleteBucketTaggingError, DeleteBucketWebsiteError, DeleteObjectError, DeleteObjectsError,
+                     GetBucketAccelerateConfigurationError, GetBucketAnalyticsConfigurationError, GetBucketCorsError,
+                     GetBucketIntelligentTieringConfigurationError, GetBucketLifecycleConfigurationError, GetBucketLocationError,
+                     GetBucketMetricsConfigurationError, GetBucketOwnershipControlsError, GetBucketTaggingError,
+                     GetBucketVersioningError, GetBucketWebsiteError, GetObjectAclError, GetObjectError, GetObjectTaggingError,
+                     HeadBucketError, HeadObjectError, ListBucketAnalyticsConfigurationsError,
+                     ListBucketIntelligentTieringConfigurationsError, ListBucketMetricsConfigurationsError, ListBucketsError,
+                     ListMultipartUploadsError, ListObjectVersionsError, ListObjectsV2Error,
+                     PutBucketAccelerateConfigurationError, PutBucketAnalyticsConfigurationError, PutBucketCorsError,
+                     PutBucketEncryptionError, PutBucketIntelligentTieringConfigurationError,
+                     PutBucketLifecycleConfigurationError, PutBucketMetricsConfigurationError, PutBucketOwnershipControlsError,
+                     PutBucketTaggingError, PutBucketVersioningError, PutBucketWebsiteError, PutObjectAclError, PutObjectError,
+                     UploadPartCopyError, UploadPartError);
+        ErrorDetails { message: err.to_string(), ..Default::default() }
+    }
+
+    /// what to do about errors whose message doesn't say
+    fn hint(&self) -> Option<&'static str> {
+        match self.code.as_deref()? {
+            "AccessControlListNotSupported" => Some("the bucket's object ownership is bucket-owner-enforced, which disables ACLs. \
+                leave out the ACL, or enable them with `ownership set bucket-owner-preferred`"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(code) = &self.code {
+            write!(f, "{}: ", code)?;
+        }
+        write!(f, "{}", self.message)?;
+        match (&self.request_id, &self.extended_request_id) {
+            (Some(id), Some(extended)) => write!(f, " (request id {}, extended request id {})", id, extended),
+            (Some(id), None) => write!(f, " (request id {})", id),
+            _ => Ok(()),
+        }?;
+        match self.hint() {
+            Some(hint) => write!(f, ". {}", hint),
+            None => Ok(()),
+        }
+    }
+}
+
+/// ends a command with status once it has said why, as process::exit would, but by returning so
+/// the daemon can report the status to its client rather than exit itself
+#[derive(Debug)]
+pub struct Exit(pub i32);
+
+impl fmt::Display for Exit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit status {}", self.0)
+    }
+}
+
+impl Error for Exit {}
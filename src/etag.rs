@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::path::Path;
+use md5::{Digest, Md5};
+use tokio::io::AsyncReadExt;
+use crate::sync::md5_file;
+
+const MIB: u64 = 1024 * 1024;
+
+/// part sizes tried after any hints: the AWS CLI and SDK default, the S3 minimum, and other
+/// sizes common tools use
+const COMMON_PART_SIZES: &[u64] = &[8 * MIB, 5 * MIB, 16 * MIB, 15 * MIB, 64 * MIB, 100 * MIB];
+
+/// how a local file matched an object's ETag
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EtagMatch {
+    SinglePart,
+    Multipart { parts: u64, part_size: u64 },
+    NoMatch,
+}
+
+/// the number of parts in a multipart upload's ETag, which S3 writes as md5-of-md5s-N
+pub fn part_count(etag: &str) -> Option<u64> {
+    etag.trim_matches('"').rsplit_once('-').and_then(|(_, parts)| parts.parse().ok())
+}
+
+/// the ETag S3 gives a multipart upload of the file at path in part_size parts: the MD5 of
+/// the concatenated part MD5s, then -N
+pub async fn multipart_etag(path: &Path, part_size: u64) -> Result<String, Box<dyn Error>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut part_digests = Vec::new();
+    let mut part = Md5::new();
+    let mut in_part = 0;
+    let mut parts = 0;
+    let mut buffer = vec![0u8; MIB as usize];
+    loop {
+        let want = buffer.len().min((part_size - in_part) as usize);
+        let count = file.read(&mut buffer[..want]).await?;
+        if count == 0 {
+            break;
+        }
+        part.update(&buffer[..count]);
+        in_part += count as u64;
+        if in_part == part_size {
+            part_digests.extend_from_slice(&part.finalize_reset());
+            parts += 1;
+            in_part = 0;
+        }
+    }
+    if in_part > 0 || parts == 0 {
+        part_digests.extend_from_slice(&part.finalize());
+        parts += 1;
+    }
+    Ok(format!("{:x}-{}", Md5::digest(&part_digests), parts))
+}
+
+/// part sizes that split size bytes into exactly parts parts, hints first. tools that pick a part
+/// size from the file size usually round size / parts up to a whole MiB, so that's tried too
+fn candidate_part_sizes(size: u64, parts: u64, hints: &[u64]) -> Vec<u64> {
+    let derived = size.div_ceil(parts).div_ceil(MIB) * MIB;
+    let mut candidates: Vec<u64> = Vec::new();
+    for &part_size in hints.iter().chain(COMMON_PART_SIZES).chain([derived, size.div_ceil(parts)].iter()) {
+        if part_size > 0 && size.div_ceil(part_size).max(1) == parts && !candidates.contains(&part_size) {
+            candidates.push(part_size);
+        }
+    }
+    candidates
+}
+
+/// whether the file at path has the contents of an object with etag. a multipart ETag is
+/// recomputed for each plausible part size, trying hints first. ETags of SSE-KMS and SSE-C
+/// objects aren't MD5s, so those never match
+pub async fn matches_file(path: &Path, etag: &str, hints: &[u64]) -> Result<EtagMatch, Box<dyn Error>> {
+    let etag = etag.trim_matches('"').to_ascii_lowercase();
+    let Some(parts) = part_count(&etag) else {
+        return Ok(if md5_file(path).await? == etag { EtagMatch::SinglePart } else { EtagMatch::NoMatch });
+    };
+    let size = tokio::fs::metadata(path).await?.len();
+    for part_size in candidate_part_sizes(size, parts, hints) {
+        if multipart_etag(path, part_size).await? == etag {
+            return Ok(EtagMatch::Multipart { parts, part_size });
+        }
+    }
+    Ok(EtagMatch::NoMatch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// a file in the temp directory holding len bytes, removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, len: usize) -> TempFile {
+            let path = std::env::temp_dir().join(format!("s3test-etag-{}-{}", std::process::id(), name));
+            std::fs::write(&path, (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>()).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// the ETag of len bytes in part_size parts, worked out in memory
+    fn expected(len: usize, part_size: usize) -> String {
+        let contents: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let mut digests = Vec::new();
+        let mut parts = 0;
+        for part in contents.chunks(part_size) {
+            digests.extend_from_slice(&Md5::digest(part));
+            parts += 1;
+        }
+        if parts == 0 {
+            digests.extend_from_slice(&Md5::digest(b""));
+            parts = 1;
+        }
+        format!("{:x}-{}", Md5::digest(&digests), parts)
+    }
+
+    #[test]
+    fn counts_parts() {
+        assert_eq!(part_count("\"9b2cf535f27731c974343645a3985328-3\""), Some(3));
+        assert_eq!(part_count("9b2cf535f27731c974343645a3985328"), None);
+        assert_eq!(part_count("abc-x"), None);
+    }
+
+    #[tokio::test]
+    async fn multipart_etag_matches_part_boundaries() {
+        let file = TempFile::new("parts", 10);
+        assert_eq!(multipart_etag(&file.0, 4).await.unwrap(), expected(10, 4));
+        assert!(expected(10, 4).ends_with("-3"));
+        // an exact multiple has no empty last part
+        let file = TempFile::new("exact", 8);
+        assert_eq!(multipart_etag(&file.0, 4).await.unwrap(), expected(8, 4));
+        assert!(expected(8, 4).ends_with("-2"));
+        // parts bigger than the read buffer
+        let file = TempFile::new("large", 3 * MIB as usize + 5);
+        assert_eq!(multipart_etag(&file.0, 2 * MIB).await.unwrap(), expected(3 * MIB as usize + 5, 2 * MIB as usize));
+        let file = TempFile::new("empty", 0);
+        assert_eq!(multipart_etag(&file.0, 4).await.unwrap(), expected(0, 4));
+    }
+
+    #[test]
+    fn candidates_split_into_the_right_part_count() {
+        let size = 20 * MIB;
+        let candidates = candidate_part_sizes(size, 3, &[]);
+        assert_eq!(candidates[0], 8 * MIB);
+        assert!(candidates.contains(&(7 * MIB)));
+        assert!(candidates.iter().all(|part_size| size.div_ceil(*part_size) == 3));
+        assert_eq!(candidate_part_sizes(size, 3, &[9 * MIB])[0], 9 * MIB);
+        // a hint that gives the wrong count is dropped
+        assert!(!candidate_part_sizes(size, 3, &[MIB]).contains(&MIB));
+    }
+
+    #[tokio::test]
+    async fn matches_single_and_multipart_etags() {
+        let file = TempFile::new("match", 10);
+        let single = format!("\"{:x}\"", Md5::digest((0..10).map(|i| i as u8).collect::<Vec<_>>()));
+        assert_eq!(matches_file(&file.0, &single, &[]).await.unwrap(), EtagMatch::SinglePart);
+        assert_eq!(matches_file(&file.0, &expected(10, 4), &[4]).await.unwrap(),
+                   EtagMatch::Multipart { parts: 3, part_size: 4 });
+        assert_eq!(matches_file(&file.0, &expected(10, 4), &[]).await.unwrap(), EtagMatch::Multipart { parts: 3, part_size: 4 });
+        assert_eq!(matches_file(&file.0, "\"00000000000000000000000000000000-3\"", &[4]).await.unwrap(), EtagMatch::NoMatch);
+    }
+}
@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use aws_credential_types::provider::SharedCredentialsProvider;
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use http::uri::{Authority, Scheme};
+use http::{Method, Request, Response, Uri};
+use hyper::service::Service;
+use crate::clock;
+use crate::http::try_clone;
+use crate::prometheus;
+use crate::sigv4;
+
+#[derive(Clone, Debug)]
+struct Endpoint {
+    scheme: Scheme,
+    authority: Authority,
+}
+
+impl Endpoint {
+    fn parse(url: &str) -> Result<Endpoint, Box<dyn Error>> {
+        let uri: Uri = url.parse().map_err(|err| format!("invalid endpoint {}: {}", url, err))?;
+        match (uri.scheme(), uri.authority()) {
+            (Some(scheme), Some(authority)) => Ok(Endpoint { scheme: scheme.clone(), authority: authority.clone() }),
+            _ => Err(format!("invalid endpoint {}: expected scheme://host[:port]", url).into()),
+        }
+    }
+
+    /// uri as if the SDK had been configured with this endpoint instead of primary.
+    /// a virtual-hosted bucket name in front of primary's host is kept
+    fn retarget(&self, uri: &Uri, primary: &Endpoint) -> Option<Uri> {
+        let authority = uri.authority()?.as_str();
+        let authority = if authority == primary.authority.as_str() {
+            self.authority.to_string()
+        } else {
+            let bucket = authority.strip_suffix(primary.authority.as_str())?.strip_suffix('.')?;
+            format!("{}.{}", bucket, self.authority)
+        };
+        Uri::builder()
+            .scheme(self.scheme.clone())
+            .authority(authority)
+            .path_and_query(uri.path_and_query()?.clone())
+            .build()
+            .ok()
+    }
+}
+
+/// sends reads to replicas when an endpoint can't be reached. the SDK is configured with the first
+/// endpoint, which takes every write. reads start from whichever endpoint last answered one
+#[derive(Clone)]
+pub struct Failover {
+    inner: DynConnector,
+    endpoints: Arc<Vec<Endpoint>>,
+    current: Arc<AtomicUsize>,
+    credentials: SharedCredentialsProvider,
+}
+
+impl Failover {
+    /// inner unchanged unless there's more than one endpoint
+    pub fn wrap(inner: DynConnector, endpoints: &[String], credentials: SharedCredentialsProvider) -> Result<DynConnector, Box<dyn Error>> {
+        if endpoints.len() < 2 {
+            return Ok(inner);
+        }
+        let endpoints = endpoints.iter().map(|e| Endpoint::parse(e)).collect::<Result<Vec<_>, _>>()?;
+        Ok(DynConnector::new(Failover { inner, endpoints: Arc::new(endpoints), current: Arc::default(), credentials }))
+    }
+}
+
+impl Service<Request<SdkBody>> for Failover {
+    type Response = Response<SdkBody>;
+    type Error = ConnectorError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<SdkBody>, ConnectorError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ConnectorError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<SdkBody>) -> Self::Future {
+        if !matches!(*request.method(), Method::GET | Method::HEAD) {
+            return Box::pin(self.inner.call(request));
+        }
+        let mut inner = self.inner.clone();
+        let endpoints = self.endpoints.clone();
+        let current = self.current.clone();
+        let credentials = self.credentials.clone();
+        Box::pin(async move {
+            let start = current.load(Ordering::Relaxed);
+            let mut last_error = None;
+            for step in 0..endpoints.len() {
+                let idx = (start + step) % endpoints.len();
+                let Some(mut attempt) = try_clone(&request) else { break };
+                if idx != 0 {
+                    let Some(uri) = endpoints[idx].retarget(attempt.uri(), &endpoints[0]) else { break };
+                    *attempt.uri_mut() = uri;
+                    sigv4::resign_request(&credentials, &mut attempt, clock::signing_time()).await?;
+                }
+                if step > 0 {
+                    prometheus::count_retry();
+                }
+                poll_fn(|cx| inner.poll_ready(cx)).await?;
+                match inner.call(attempt).await {
+                    Err(err) if err.is_io() || err.is_timeout() => {
+                        eprintln!("note: {} unreachable: {}", endpoints[idx].authority, err);
+                        last_error = Some(err);
+                    }
+                    result => {
+                        current.store(idx, Ordering::Relaxed);
+                        return result;
+                    }
+                }
+            }
+            match last_error {
+                Some(err) => Err(err),
+                // the request couldn't be copied or moved, so send it as the SDK built it
+                None => {
+                    poll_fn(|cx| inner.poll_ready(cx)).await?;
+                    inner.call(request).await
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,311 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use serde_json::{json, Value};
+use crate::errors::ErrorDetails;
+use crate::upload::PutOptions;
+
+/// the kinds of operation bulk commands perform on single objects
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    /// upload local_path to key
+    Put,
+    /// download key (at version_id, if set) to local_path
+    Get,
+    /// delete key (at version_id, if set)
+    Delete,
+}
+
+impl Operation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Put => "put",
+            Operation::Get => "get",
+            Operation::Delete => "delete",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Operation> {
+        match value {
+            "put" => Some(Operation::Put),
+            "get" => Some(Operation::Get),
+            "delete" => Some(Operation::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// one failed operation, enough to replay it exactly
+#[derive(Clone, Debug)]
+pub struct FailedOp {
+    pub operation: Operation,
+    pub bucket: String,
+    pub key: String,
+    pub version_id: Option<String>,
+    pub local_path: Option<PathBuf>,
+    /// for a put of a symlink kept with --preserve-symlinks, where it points. the put stores
+    /// that rather than what local_path holds
+    pub symlink_target: Option<PathBuf>,
+    /// how a put was uploaded. records written before puts stored this have none
+    pub put_options: Option<PutOptions>,
+    pub error: ErrorDetails,
+}
+
+impl FailedOp {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "op": self.operation.as_str(),
+            "bucket": self.bucket,
+            "key": self.key,
+            "version_id": self.version_id,
+            "local_path": self.local_path.as_ref().map(|p| p.to_string_lossy().to_string()),
+            "symlink_target": self.symlink_target.as_ref().map(|p| p.to_string_lossy().to_string()),
+            "put_options": self.put_options.as_ref().map(PutOptions::to_json),
+            "error": self.error.message,
+            "error_code": self.error.code,
+            "request_id": self.error.request_id,
+            "extended_request_id": self.error.extended_request_id,
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<FailedOp, String> {
+        let string = |name: &str| value[name].as_str().map(|s| s.to_string());
+        let operation = string("op").as_deref().and_then(Operation::parse).ok_or("missing or unknown op")?;
+        let op = FailedOp {
+            operation,
+            bucket: string("bucket").ok_or("missing bucket")?,
+            key: string("key").ok_or("missing key")?,
+            version_id: string("version_id"),
+            local_path: string("local_path").map(PathBuf::from),
+            symlink_target: string("symlink_target").map(PathBuf::from),
+            put_options: match &value["put_options"] {
+                Value::Null => None,
+                options => Some(PutOptions::from_json(options)?),
+            },
+            error: ErrorDetails {
+                message: string("error").unwrap_or_default(),
+                code: string("error_code"),
+                request_id: string("request_id"),
+                extended_request_id: string("extended_request_id"),
+            },
+        };
+        if op.operation != Operation::Delete && op.local_path.is_none() {
+            return Err(format!("{} needs local_path", op.operation.as_str()));
+        }
+        Ok(op)
+    }
+}
+
+/// what a bulk command does when an object fails
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorPolicy {
+    /// stop at the first failure
+    FailFast,
+    /// carry on, and fail at the end if anything failed
+    Continue,
+    /// carry on, and fail at the end only if more than this percentage failed
+    Threshold(f64),
+}
+
+impl ErrorPolicy {
+    /// parses fail-fast, continue or threshold=N%
+    pub fn parse(value: &str) -> Result<ErrorPolicy, String> {
+        match value {
+            "fail-fast" => return Ok(ErrorPolicy::FailFast),
+            "continue" => return Ok(ErrorPolicy::Continue),
+            _ => {}
+        }
+        let percent = value.strip_prefix("threshold=")
+            .ok_or("expected fail-fast, continue or threshold=N%")?;
+        let percent: f64 = percent.strip_suffix('%').unwrap_or(percent).parse()
+            .map_err(|_| format!("{} isn't a percentage", percent))?;
+        if !(0.0..=100.0).contains(&percent) {
+            return Err("the threshold must be from 0% to 100%".to_string());
+        }
+        Ok(ErrorPolicy::Threshold(percent))
+    }
+}
+
+static POLICY: OnceLock<ErrorPolicy> = OnceLock::new();
+
+/// sets the policy every FailureLog follows. only the first call has any effect
+pub fn set_policy(policy: ErrorPolicy) {
+    let _ = POLICY.set(policy);
+}
+
+/// collects per-object failures for a bulk command, appending each to a JSON lines file as it happens
+/// when given one, or printing it otherwise
+pub struct FailureLog {
+    path: Option<PathBuf>,
+    file: Option<BufWriter<File>>,
+    count: usize,
+    policy: ErrorPolicy,
+}
+
+impl FailureLog {
+    pub fn new(path: Option<&Path>) -> Result<FailureLog, Box<dyn Error>> {
+        let file = match path {
+            Some(path) => Some(BufWriter::new(File::create(path)?)),
+            None => None,
+        };
+        let policy = POLICY.get().copied().unwrap_or(ErrorPolicy::Continue);
+        Ok(FailureLog { path: path.map(|p| p.to_path_buf()), file, count: 0, policy })
+    }
+
+    /// logs op. with --error-policy fail-fast this returns an error, which stops the command
+    pub fn record(&mut self, op: FailedOp) -> Result<(), Box<dyn Error>> {
+        self.count += 1;
+        match &mut self.file {
+            Some(file) => {
+                writeln!(file, "{}", op.to_json())?;
+                file.flush()?;
+            }
+            None => println!("failed to {} {}: {}", op.operation.as_str(), op.key, op.error),
+        }
+        if self.policy == ErrorPolicy::FailFast {
+            return Err(format!("stopped after {} failed (--error-policy fail-fast)", op.key).into());
+        }
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// the error a bulk command returns once finished, if anything failed, or with a threshold,
+    /// more than that share of the attempted objects
+    pub fn finish(self, attempted: usize) -> Result<(), Box<dyn Error>> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        if let ErrorPolicy::Threshold(percent) = self.policy {
+            let failed = self.count as f64 * 100.0 / attempted.max(self.count) as f64;
+            if failed <= percent {
+                println!("{} of {} failed, within the {}% threshold", self.count, attempted, percent);
+                return Ok(());
+            }
+        }
+        match &self.path {
+            Some(path) => Err(format!("{} operations failed, see {}", self.count, path.display()).into()),
+            None => Err(format!("{} operations failed", self.count).into()),
+        }
+    }
+}
+
+/// reads a file written by FailureLog
+pub fn read(path: &Path) -> Result<Vec<FailedOp>, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut ops = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(line).map_err(|err| format!("{}:{}: {}", path.display(), idx + 1, err))?;
+        ops.push(FailedOp::from_json(&value).map_err(|err| format!("{}:{}: {}", path.display(), idx + 1, err))?);
+    }
+    Ok(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(count: usize, policy: ErrorPolicy) -> FailureLog {
+        FailureLog { path: None, file: None, count, policy }
+    }
+
+    #[test]
+    fn parses_policies() {
+        assert_eq!(ErrorPolicy::parse("fail-fast"), Ok(ErrorPolicy::FailFast));
+        assert_eq!(ErrorPolicy::parse("continue"), Ok(ErrorPolicy::Continue));
+        assert_eq!(ErrorPolicy::parse("threshold=5%"), Ok(ErrorPolicy::Threshold(5.0)));
+        assert_eq!(ErrorPolicy::parse("threshold=2.5"), Ok(ErrorPolicy::Threshold(2.5)));
+        assert_eq!(ErrorPolicy::parse("threshold=0%"), Ok(ErrorPolicy::Threshold(0.0)));
+        assert_eq!(ErrorPolicy::parse("threshold=100%"), Ok(ErrorPolicy::Threshold(100.0)));
+    }
+
+    #[test]
+    fn rejects_bad_policies() {
+        for value in ["", "stop", "threshold", "threshold=", "threshold=x%", "threshold=-1%", "threshold=100.5%", "threshold=NaN"] {
+            assert!(ErrorPolicy::parse(value).is_err(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn finish_applies_the_threshold() {
+        assert!(log(0, ErrorPolicy::Continue).finish(10).is_ok());
+        assert!(log(1, ErrorPolicy::Continue).finish(10).is_err());
+        assert!(log(3, ErrorPolicy::Threshold(30.0)).finish(10).is_ok());
+        assert!(log(3, ErrorPolicy::Threshold(29.9)).finish(10).is_err());
+        assert!(log(1, ErrorPolicy::Threshold(0.0)).finish(1000).is_err());
+        // more failures than attempts, as when a batch fails outright, counts as all of them
+        assert!(log(5, ErrorPolicy::Threshold(99.0)).finish(2).is_err());
+        assert!(log(5, ErrorPolicy::Threshold(100.0)).finish(0).is_ok());
+    }
+
+    #[test]
+    fn fail_fast_stops_at_the_first_failure() {
+        let op = FailedOp {
+            operation: Operation::Delete,
+            bucket: "bkt".to_string(),
+            key: "k".to_string(),
+            version_id: None,
+            local_path: None,
+            symlink_target: None,
+            put_options: None,
+            error: ErrorDetails { message: "denied".to_string(), code: None, request_id: None, extended_request_id: None },
+        };
+        assert!(log(0, ErrorPolicy::FailFast).record(op.clone()).is_err());
+        let mut continuing = log(0, ErrorPolicy::Threshold(50.0));
+        assert!(continuing.record(op).is_ok());
+        assert_eq!(continuing.count(), 1);
+    }
+
+    #[test]
+    fn put_failures_round_trip() {
+        let op = FailedOp {
+            operation: Operation::Put,
+            bucket: "bkt".to_string(),
+            key: "site/current".to_string(),
+            version_id: None,
+            local_path: Some(PathBuf::from("/srv/site/current")),
+            symlink_target: Some(PathBuf::from("releases/42")),
+            put_options: Some(PutOptions {
+                preserve: true,
+                web: false,
+                headers: crate::upload::HeaderArgs { cache_control: Some("no-cache".to_string()), acl: Some("private".to_string()), ..Default::default() },
+                content_type: Some("text/plain".to_string()),
+                metadata: vec![("owner".to_string(), "ops".to_string())],
+            }),
+            error: ErrorDetails { message: "slow down".to_string(), code: Some("SlowDown".to_string()), request_id: Some("R1".to_string()), extended_request_id: None },
+        };
+        let parsed = FailedOp::from_json(&op.to_json()).unwrap();
+        assert_eq!(parsed.operation, Operation::Put);
+        assert_eq!((parsed.bucket.as_str(), parsed.key.as_str()), ("bkt", "site/current"));
+        assert_eq!(parsed.local_path, op.local_path);
+        assert_eq!(parsed.symlink_target, op.symlink_target);
+        assert_eq!((parsed.error.message.as_str(), parsed.error.code.as_deref(), parsed.error.request_id.as_deref()), ("slow down", Some("SlowDown"), Some("R1")));
+        let options = parsed.put_options.unwrap();
+        assert!(options.preserve && !options.web);
+        assert_eq!(options.headers.cache_control.as_deref(), Some("no-cache"));
+        assert_eq!(options.headers.acl.as_deref(), Some("private"));
+        assert_eq!(options.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(options.metadata, [("owner".to_string(), "ops".to_string())]);
+
+        // a plain file, and records from before symlinks and options were stored
+        let file = FailedOp { symlink_target: None, put_options: None, ..op };
+        let parsed = FailedOp::from_json(&file.to_json()).unwrap();
+        assert!(parsed.symlink_target.is_none() && parsed.put_options.is_none());
+    }
+
+    #[test]
+    fn puts_and_gets_need_a_local_path() {
+        let get = json!({"op": "get", "bucket": "bkt", "key": "k"});
+        assert!(FailedOp::from_json(&get).unwrap_err().contains("local_path"));
+        let delete = json!({"op": "delete", "bucket": "bkt", "key": "k", "version_id": "v1"});
+        assert_eq!(FailedOp::from_json(&delete).unwrap().version_id.as_deref(), Some("v1"));
+        assert!(FailedOp::from_json(&json!({"op": "copy", "bucket": "bkt", "key": "k"})).is_err());
+    }
+}
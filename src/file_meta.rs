@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// user metadata key holding the file's modification time in seconds since the epoch
+pub const MTIME_KEY: &str = "mtime";
+/// user metadata key holding the file's permission bits in octal
+pub const MODE_KEY: &str = "mode";
+
+/// returns the metadata entries recording path's mtime and permission bits
+pub fn capture(path: &Path) -> io::Result<Vec<(&'static str, String)>> {
+    let info = fs::metadata(path)?;
+    let mut entries = Vec::new();
+    if let Ok(since_epoch) = info.modified()?.duration_since(UNIX_EPOCH) {
+        entries.push((MTIME_KEY, since_epoch.as_secs().to_string()));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        entries.push((MODE_KEY, format!("{:o}", info.permissions().mode() & 0o7777)));
+    }
+    Ok(entries)
+}
+
+/// the mtime recorded by capture, if present and valid
+pub fn recorded_mtime(metadata: &HashMap<String, String>) -> Option<SystemTime> {
+    let secs: u64 = metadata.get(MTIME_KEY)?.parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// applies the mtime and mode recorded by capture to path. missing or invalid entries are ignored
+pub fn restore(path: &Path, metadata: &HashMap<String, String>) -> io::Result<()> {
+    if let Some(mtime) = recorded_mtime(metadata) {
+        File::options().write(true).open(path)?.set_modified(mtime)?;
+    }
+    #[cfg(unix)]
+    if let Some(mode) = metadata.get(MODE_KEY).and_then(|m| u32::from_str_radix(m, 8).ok()) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
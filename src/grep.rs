@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::Object;
+use futures_util::{stream, TryStreamExt};
+use regex::bytes::Regex;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use crate::compression::Compression;
+use crate::download::relative_path;
+use crate::encryption;
+use crate::listing::object_pages;
+use crate::payer;
+use crate::stop;
+
+pub struct GrepOptions {
+    /// globs the key's last segment, or with a '/' its path below the prefix, has to match one of.
+    /// everything is searched when empty
+    pub include: Vec<String>,
+    /// objects searched at once. their matches are still printed in key order
+    pub concurrency: usize,
+    /// also gunzip objects whose keys end in .gz, not just those stored with Content-Encoding
+    pub gunzip: bool,
+}
+
+/// whether text matches pattern, where * matches any run of characters and ? any one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // where the last * was, and how much of text it has taken so far
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn included(prefix: &str, key: &str, include: &[String]) -> bool {
+    let relative = relative_path(prefix, key);
+    let name = key.rsplit('/').next().unwrap_or(key);
+    include.is_empty() || include.iter().any(|glob| glob_match(glob, if glob.contains('/') { relative } else { name }))
+}
+
+/// the lines of key that match pattern. compressed objects are downloaded whole to decompress
+/// them, others are read a line at a time
+async fn search(client: &Client, bucket_name: &str, key: &str, pattern: &Regex, gunzip: bool) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    let object = client.get_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .send()
+        .await?;
+    if object.metadata().is_some_and(|m| m.contains_key(encryption::ALGORITHM_KEY)) {
+        return Err("client-side encrypted, so it can't be searched".into());
+    }
+    let compression = object.content_encoding()
+        .and_then(Compression::from_content_encoding)
+        .or_else(|| (gunzip && key.ends_with(".gz")).then_some(Compression::Gzip));
+    let mut matches = Vec::new();
+    if let Some(compression) = compression {
+        let bytes = object.body.collect().await?.into_bytes();
+        let text = compression.decompress(&bytes)?;
+        let text = text.strip_suffix(b"\n").unwrap_or(&text);
+        for line in text.split(|b| *b == b'\n').filter(|_| !text.is_empty()) {
+            if pattern.is_match(line) {
+                matches.push(line.to_vec());
+            }
+        }
+        return Ok(matches);
+    }
+    let mut reader = BufReader::new(object.body.into_async_read());
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line).await? > 0 {
+        if line.ends_with(b"\n") {
+            line.pop();
+        }
+        if pattern.is_match(&line) {
+            matches.push(std::mem::take(&mut line));
+        }
+        line.clear();
+    }
+    Ok(matches)
+}
+
+/// prints key:line for every line of the objects under prefix that matches pattern, as grep
+/// does for files. objects that can't be read are reported on stderr and searching goes on.
+/// returns whether anything matched
+pub async fn grep(client: &Client, bucket_name: &str, prefix: &str, pattern: &Regex, options: &GrepOptions) -> Result<bool, Box<dyn Error>> {
+    let keys = object_pages(client, bucket_name, prefix)
+        .map_ok(|page: Vec<Object>| stream::iter(page.into_iter().filter_map(|o| o.key).map(Ok::<_, Box<dyn Error>>)))
+        .try_flatten()
+        .try_filter(|key| std::future::ready(included(prefix, key, &options.include)));
+    let mut results = pin!(keys
+        .map_ok(|key| async move {
+            let result = search(client, bucket_name, &key, pattern, options.gunzip).await;
+            Ok((key, result))
+        })
+        .try_buffered(options.concurrency.max(1)));
+    let (mut matched, mut failed) = (false, 0);
+    while let Some((key, result)) = stop::within(results.try_next()).await?? {
+        match result {
+            Ok(lines) => {
+                for line in lines {
+                    matched = true;
+                    println!("{}:{}", key, String::from_utf8_lossy(&line));
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {}", key, err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} objects couldn't be searched", failed).into());
+    }
+    Ok(matched)
+}
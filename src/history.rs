@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_s3::types::{ChecksumMode, Owner};
+use clap::Args;
+use futures_util::TryStreamExt;
+use crate::download::download_to;
+use crate::inventory::csv_field;
+use crate::listing::version_pages;
+use crate::payer;
+use crate::report::format_bytes;
+
+/// the owner's display name, or its canonical id where the provider leaves the name out
+fn owner_name(owner: Option<&Owner>) -> String {
+    owner.and_then(|o| o.display_name().or(o.id())).unwrap_or("unknown").to_string()
+}
+
+fn in_range(modified: Option<&DateTime>, since: Option<DateTime>, until: Option<DateTime>) -> bool {
+    let modified = modified.map(|d| d.secs()).unwrap_or(0);
+    !(since.is_some_and(|since| modified < since.secs()) || until.is_some_and(|until| modified > until.secs()))
+}
+
+/// what one owner wrote, for --by-owner
+#[derive(Default)]
+struct OwnerGroup {
+    lines: Vec<String>,
+    versions: usize,
+    bytes: i64,
+    markers: usize,
+}
+
+/// prints every version and delete marker under prefix last modified between since and until,
+/// both inclusive, with who wrote it. by_owner groups them under each owner with totals
+pub async fn list_versions(client: &Client, bucket_name: &str, prefix: &str, since: Option<DateTime>, until: Option<DateTime>, by_owner: bool) -> Result<(), Box<dyn Error>> {
+    let mut groups: BTreeMap<String, OwnerGroup> = BTreeMap::new();
+    let mut pages = pin!(version_pages(client, bucket_name, prefix));
+    while let Some((versions, markers)) = pages.try_next().await? {
+        for version in versions.iter().filter(|v| in_range(v.last_modified(), since, until)) {
+            let owner = owner_name(version.owner());
+            let str = version.e_tag().unwrap().to_string().to_ascii_lowercase();
+            let line = format!("version: {}: {} ({})", version.version_id().unwrap(), version.size(), &str[1..str.len()-1]);
+            if by_owner {
+                let group = groups.entry(owner).or_default();
+                group.lines.push(line);
+                group.versions += 1;
+                group.bytes += version.size();
+            } else {
+                println!("{} by {}", line, owner);
+            }
+        }
+        for marker in markers.iter().filter(|m| in_range(m.last_modified(), since, until)) {
+            let owner = owner_name(marker.owner());
+            let line = format!("delete marker: {}", marker.version_id().unwrap_or("null"));
+            if by_owner {
+                let group = groups.entry(owner).or_default();
+                group.lines.push(line);
+                group.markers += 1;
+            } else {
+                println!("{} by {}", line, owner);
+            }
+        }
+    }
+    for (owner, group) in groups {
+        println!("{}: {} versions ({}), {} delete markers", owner, group.versions, format_bytes(group.bytes), group.markers);
+        for line in group.lines {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+fn sort_key(time: &DateTime) -> (i64, u32) {
+    (time.secs(), time.subsec_nanos())
+}
+
+/// prints the version of key that was current at timestamp, or that the key was deleted or
+/// didn't exist yet
+pub async fn version_at(client: &Client, bucket_name: &str, key: &str, timestamp: DateTime) -> Result<(), Box<dyn Error>> {
+    let entries = key_history(client, bucket_name, key).await?;
+    let latest = entries.iter()
+        .rev()
+        .find(|entry| entry.modified.as_ref().is_some_and(|modified| sort_key(modified) <= sort_key(&timestamp)));
+    let when = timestamp.fmt(DateTimeFormat::DateTime)?;
+    match latest {
+        None => println!("{} didn't exist at {}", key, when),
+        Some(latest) => {
+            let modified = latest.modified.map(|d| d.fmt(DateTimeFormat::DateTime)).transpose()?.unwrap_or_default();
+            if latest.deleted {
+                println!("{} was deleted at {} (delete marker {} from {})", key, when, latest.version_id, modified);
+            } else {
+                println!("version at {}: {} (modified {})", when, latest.version_id, modified);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// one entry in a key's history, oldest first
+struct Entry {
+    version_id: String,
+    modified: Option<DateTime>,
+    size: i64,
+    e_tag: String,
+    deleted: bool,
+}
+
+/// the versions and delete markers of exactly key, oldest first
+async fn key_history(client: &Client, bucket_name: &str, key: &str) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    let mut pages = pin!(version_pages(client, bucket_name, key));
+    while let Some((versions, markers)) = pages.try_next().await? {
+        let past_key = versions.iter().any(|v| v.key().is_some_and(|listed| listed > key))
+            || markers.iter().any(|m| m.key().is_some_and(|listed| listed > key));
+        for version in versions.iter().filter(|v| v.key() == Some(key)) {
+            entries.push(Entry {
+                version_id: version.version_id().unwrap_or("null").to_string(),
+                modified: version.last_modified().copied(),
+                size: version.size(),
+                e_tag: version.e_tag().unwrap_or_default().trim_matches('"').to_string(),
+                deleted: false,
+            });
+        }
+        for marker in markers.iter().filter(|m| m.key() == Some(key)) {
+            entries.push(Entry {
+                version_id: marker.version_id().unwrap_or("null").to_string(),
+                modified: marker.last_modified().copied(),
+                size: 0,
+                e_tag: String::new(),
+                deleted: true,
+            });
+        }
+        if past_key {
+            break;
+        }
+    }
+    // listings put a key's newest version first, so reversing keeps same-second entries in order
+    entries.reverse();
+    entries.sort_by_key(|entry| entry.modified.as_ref().map(sort_key));
+    Ok(entries)
+}
+
+/// downloads every version of key into dir as name.v001_<version id>, oldest first, and writes
+/// name.versions.csv there listing each version and delete marker in order
+pub async fn get_all_versions(client: &Client, bucket_name: &str, key: &str, dir: &str) -> Result<(), Box<dyn Error>> {
+    let entries = key_history(client, bucket_name, key).await?;
+    if entries.is_empty() {
+        println!("no versions of {}", key);
+        return Ok(());
+    }
+    let name = key.rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or(key);
+    fs::create_dir_all(dir)?;
+    let manifest_path = Path::new(dir).join(format!("{}.versions.csv", name));
+    let mut manifest = BufWriter::new(File::create(&manifest_path)?);
+    writeln!(manifest, "file,version_id,last_modified,size,e_tag,delete_marker")?;
+    let mut number = 0;
+    for entry in &entries {
+        let modified = entry.modified.and_then(|d| d.fmt(DateTimeFormat::DateTime).ok()).unwrap_or_default();
+        let file_name = if entry.deleted {
+            String::new()
+        } else {
+            number += 1;
+            let file_name = format!("{}.v{:03}_{}", name, number, entry.version_id);
+            let bytes = download_to(client, bucket_name, key, Some(&entry.version_id), &Path::new(dir).join(&file_name)).await?;
+            println!("got {} ({} bytes)", file_name, bytes);
+            file_name
+        };
+        writeln!(manifest, "{},{},{},{},{},{}", csv_field(&file_name), csv_field(&entry.version_id), modified, entry.size, csv_field(&entry.e_tag), entry.deleted)?;
+    }
+    manifest.flush()?;
+    println!("downloaded {} versions to {}, listed in {}", number, dir, manifest_path.display());
+    Ok(())
+}
+
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// which versions of a key DeleteVersions removes. every given filter has to match
+#[derive(Args, Clone, Debug)]
+#[group(required = true, multiple = true)]
+pub struct VersionFilter {
+    #[arg(long, value_name = "DAYS", help = "only versions last modified more than this many days ago")]
+    older_than: Option<i64>,
+    #[arg(long, value_name = "N", help = "never delete the N newest versions")]
+    except_latest: Option<usize>,
+    #[arg(long, value_name = "CHECKSUM", help = "only versions with this ETag or base64 SHA-256 checksum")]
+    matching_checksum: Option<String>,
+}
+
+/// whether the version's ETag is checksum, or its stored SHA-256 is when checksum looks like one
+async fn matches_checksum(client: &Client, bucket_name: &str, key: &str, entry: &Entry, checksum: &str) -> Result<bool, Box<dyn Error>> {
+    let checksum = checksum.trim_matches('"');
+    if entry.e_tag.eq_ignore_ascii_case(checksum) {
+        return Ok(true);
+    }
+    // base64 of 32 bytes
+    if checksum.len() != 44 {
+        return Ok(false);
+    }
+    let head = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .version_id(&entry.version_id)
+        .checksum_mode(ChecksumMode::Enabled)
+        .send()
+        .await?;
+    Ok(head.checksum_sha256() == Some(checksum))
+}
+
+/// permanently deletes the versions of key that match filter. delete markers are left alone
+pub async fn delete_versions(client: &Client, bucket_name: &str, key: &str, filter: &VersionFilter, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let versions: Vec<Entry> = key_history(client, bucket_name, key).await?.into_iter().filter(|entry| !entry.deleted).collect();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    // oldest first, so the newest ones to keep are at the end
+    let candidates = &versions[..versions.len().saturating_sub(filter.except_latest.unwrap_or(0))];
+    let mut deleted = 0;
+    for entry in candidates {
+        let modified = entry.modified.map(|d| d.secs()).unwrap_or(now);
+        if filter.older_than.is_some_and(|days| now - modified <= days * DAY_SECS) {
+            continue;
+        }
+        if let Some(checksum) = &filter.matching_checksum {
+            if !matches_checksum(client, bucket_name, key, entry, checksum).await? {
+                continue;
+            }
+        }
+        if dry_run {
+            println!("would delete version {}", entry.version_id);
+        } else {
+            client.delete_object()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(key)
+                .version_id(&entry.version_id)
+                .send()
+                .await?;
+            println!("deleted version {}", entry.version_id);
+        }
+        deleted += 1;
+    }
+    println!("{} {} of {} versions", if dry_run { "would delete" } else { "deleted" }, deleted, versions.len());
+    Ok(())
+}
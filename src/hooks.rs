@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::future::poll_fn;
+use std::sync::OnceLock;
+use std::time::Duration;
+use aws_smithy_http::body::SdkBody;
+use clap::Args;
+use http::{Method, Request, Uri};
+use hyper::service::Service;
+use serde_json::{json, Value};
+use tokio::process::Command;
+use crate::http::{connector, HttpArgs};
+use crate::summary;
+use crate::usage;
+
+/// how long the webhook gets to answer before the run ends without it
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// what to run and where to post once the command finishes, so scheduled jobs can report
+/// without a wrapper script
+#[derive(Args, Debug, Clone)]
+pub struct HookArgs {
+    #[arg(long, global = true, value_name = "COMMAND", help = "run this with sh when the command succeeds, with S3TEST_COMMAND, S3TEST_STATUS and S3TEST_SUMMARY (the webhook's JSON) set")]
+    pub on_success_exec: Option<String>,
+    #[arg(long, global = true, value_name = "COMMAND", help = "run this with sh when the command fails, with S3TEST_ERROR set as well")]
+    pub on_failure_exec: Option<String>,
+    #[arg(long, global = true, value_name = "URL", value_parser = parse_url, help = "POST a JSON summary here when the command finishes. its text field is what Slack webhooks show")]
+    pub webhook_url: Option<Uri>,
+}
+
+fn parse_url(value: &str) -> Result<Uri, String> {
+    let url: Uri = value.parse().map_err(|err| format!("{}", err))?;
+    match url.scheme_str() {
+        Some("http") | Some("https") if url.host().is_some() => Ok(url),
+        _ => Err(format!("{} isn't an http:// or https:// URL", value)),
+    }
+}
+
+static HOOKS: OnceLock<(HookArgs, HttpArgs)> = OnceLock::new();
+
+/// sets the hooks that finished runs. the webhook is posted through the same proxy and TLS settings
+/// as requests to S3. only the first call has any effect
+pub fn set(hooks: &HookArgs, http: &HttpArgs) {
+    // --header is for S3, and may well carry credentials the webhook shouldn't see
+    let http = HttpArgs { headers: Vec::new(), ..http.clone() };
+    let _ = HOOKS.set((hooks.clone(), http));
+}
+
+async fn exec(script: &str, payload: &Value, error: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut command = Command::new("sh");
+    command.arg("-c")
+        .arg(script)
+        .env("S3TEST_COMMAND", usage::command())
+        .env("S3TEST_STATUS", if error.is_none() { "success" } else { "failure" })
+        .env("S3TEST_SUMMARY", payload.to_string());
+    if let Some(error) = error {
+        command.env("S3TEST_ERROR", error);
+    }
+    let status = command.status().await?;
+    if !status.success() {
+        return Err(format!("exited with {}", status).into());
+    }
+    Ok(())
+}
+
+async fn post(url: &Uri, http: &HttpArgs, payload: &Value) -> Result<(), Box<dyn Error>> {
+    let mut connector = connector(http)?;
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(url.clone())
+        .header("content-type", "application/json")
+        .body(SdkBody::from(payload.to_string()))?;
+    let response = tokio::time::timeout(WEBHOOK_TIMEOUT, async {
+        poll_fn(|cx| connector.poll_ready(cx)).await?;
+        connector.call(request).await
+    }).await.map_err(|_| format!("no answer after {}s", WEBHOOK_TIMEOUT.as_secs()))??;
+    if !response.status().is_success() {
+        return Err(format!("answered {}", response.status()).into());
+    }
+    Ok(())
+}
+
+/// runs the hooks for a run that ended with error, or succeeded. the summary is the last one a
+/// bulk command printed, if any. hooks that fail are reported but don't change the exit status,
+/// and nothing runs for commands that exit early on their own, as on a usage error or a second Ctrl-C
+pub async fn finished(error: Option<&str>) {
+    let Some((hooks, http)) = HOOKS.get() else { return };
+    let exec_command = if error.is_none() { hooks.on_success_exec.as_deref() } else { hooks.on_failure_exec.as_deref() };
+    if exec_command.is_none() && hooks.webhook_url.is_none() {
+        return;
+    }
+    let summary = summary::take_last();
+    let mut text = format!("s3test {} {}", usage::command(), if error.is_none() { "succeeded" } else { "failed" });
+    if let Some(detail) = error.or(summary.as_ref().map(|(line, _)| line.as_str())) {
+        text.push_str(": ");
+        text.push_str(detail);
+    }
+    let payload = json!({
+        "text": text,
+        "command": usage::command(),
+        "status": if error.is_none() { "success" } else { "failure" },
+        "error": error,
+        "summary": summary.map(|(_, value)| value),
+    });
+    if let Some(command) = exec_command {
+        if let Err(err) = exec(command, &payload, error).await {
+            eprintln!("error: --on-{}-exec: {}", if error.is_none() { "success" } else { "failure" }, err);
+        }
+    }
+    if let Some(url) = &hooks.webhook_url {
+        if let Err(err) = post(url, http, &payload).await {
+            eprintln!("error: --webhook-url {}: {}", url, err);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use aws_sdk_s3::config::AppName;
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_client::hyper_ext;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use clap::{Args, ValueEnum};
+use http::header::{HeaderName, HeaderValue, USER_AGENT};
+use http::{Request, Response};
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+use crate::proxy::{ProxyConnector, ProxySettings};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Http2 {
+    /// use HTTP/2 when the server offers it during the TLS handshake
+    Negotiate,
+    /// only speak HTTP/1.1
+    Off,
+    /// only speak HTTP/2, including over plain http
+    Only,
+}
+
+/// options for the HTTP client the SDK sends requests through
+#[derive(Args, Debug, Clone)]
+pub struct HttpArgs {
+    #[arg(long, global = true, value_name = "PEM", help = "also trust the CA certificates in this PEM file")]
+    pub ca_bundle: Option<PathBuf>,
+    #[arg(long, global = true, help = "don't verify server certificates. only for testing")]
+    pub insecure_skip_tls_verify: bool,
+    #[arg(long, global = true, value_name = "URL", help = "send requests through this http:// proxy. defaults to HTTPS_PROXY or HTTP_PROXY, except for hosts in NO_PROXY")]
+    pub proxy: Option<String>,
+    #[arg(long, global = true, value_enum, default_value = "negotiate", help = "whether to use HTTP/2")]
+    pub http2: Http2,
+    #[arg(long, global = true, value_name = "N", help = "idle connections to keep open per host for reuse. 0 disables reuse")]
+    pub max_connections: Option<usize>,
+    #[arg(long, global = true, value_name = "SECS", help = "close pooled connections after this long idle. defaults to 90")]
+    pub idle_timeout: Option<u64>,
+    #[arg(long, global = true, value_name = "SECS", help = "send TCP keep-alive probes after this long without traffic")]
+    pub tcp_keepalive: Option<u64>,
+    #[arg(long, global = true, value_name = "NAME", value_parser = parse_app_name, help = "appended to the user agent as app/NAME")]
+    pub user_agent_suffix: Option<AppName>,
+    #[arg(long = "header", global = true, value_name = "NAME:VALUE", value_parser = parse_header, help = "add this header to every request. may be repeated")]
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+fn parse_app_name(value: &str) -> Result<AppName, String> {
+    AppName::new(value.to_string())
+        .map_err(|_| "must be ASCII letters, digits or one of !#$%&'*+-.^_`|~".to_string())
+}
+
+fn parse_header(value: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = value.split_once(':').ok_or("expected NAME:VALUE")?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|err| err.to_string())?;
+    // headers are added after signing, and these have to be signed or are set by the SDK
+    if name.as_str().starts_with("x-amz-") || name == http::header::AUTHORIZATION || name == http::header::HOST {
+        return Err(format!("{} can't be overridden", name));
+    }
+    let value = HeaderValue::from_str(value.trim()).map_err(|err| err.to_string())?;
+    Ok((name, value))
+}
+
+/// adds the --header headers to each request on its way to the network. the SDK only puts
+/// app_name in x-amz-user-agent, so the suffix is added to the plain user-agent here too
+#[derive(Clone)]
+struct ExtraHeaders {
+    inner: DynConnector,
+    user_agent_suffix: Option<String>,
+    headers: Arc<Vec<(HeaderName, HeaderValue)>>,
+}
+
+impl Service<Request<SdkBody>> for ExtraHeaders {
+    type Response = Response<SdkBody>;
+    type Error = ConnectorError;
+    type Future = <DynConnector as Service<Request<SdkBody>>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ConnectorError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<SdkBody>) -> Self::Future {
+        if let Some(suffix) = &self.user_agent_suffix {
+            let agent = request.headers().get(USER_AGENT).and_then(|v| v.to_str().ok()).unwrap_or_default();
+            if let Ok(value) = HeaderValue::from_str(format!("{} app/{}", agent, suffix).trim_start()) {
+                request.headers_mut().insert(USER_AGENT, value);
+            }
+        }
+        for (name, value) in self.headers.iter() {
+            request.headers_mut().append(name.clone(), value.clone());
+        }
+        self.inner.call(request)
+    }
+}
+
+/// accepts any server certificate
+struct NoVerify;
+
+impl ServerCertVerifier for NoVerify {
+    fn verify_server_cert(&self, _end_entity: &Certificate, _intermediates: &[Certificate], _server_name: &ServerName,
+                          _scts: &mut dyn Iterator<Item = &[u8]>, _ocsp_response: &[u8], _now: SystemTime) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// the system's root certificates plus any in ca_bundle
+fn root_store(args: &HttpArgs) -> Result<RootCertStore, Box<dyn Error>> {
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs()? {
+        // the system store often holds a few certificates rustls can't parse
+        let _ = roots.add(&Certificate(cert.0));
+    }
+    if let Some(path) = &args.ca_bundle {
+        let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))?;
+        if certs.is_empty() {
+            return Err(format!("no certificates found in {}", path.display()).into());
+        }
+        for cert in certs {
+            roots.add(&Certificate(cert))?;
+        }
+    }
+    Ok(roots)
+}
+
+fn tls_config(args: &HttpArgs) -> Result<ClientConfig, Box<dyn Error>> {
+    let builder = ClientConfig::builder().with_safe_defaults();
+    let config = if args.insecure_skip_tls_verify {
+        builder.with_custom_certificate_verifier(Arc::new(NoVerify)).with_no_client_auth()
+    } else {
+        builder.with_root_certificates(root_store(args)?).with_no_client_auth()
+    };
+    Ok(config)
+}
+
+/// copies a request if its body can be sent again
+pub fn try_clone(request: &Request<SdkBody>) -> Option<Request<SdkBody>> {
+    let mut copy = Request::new(request.body().try_clone()?);
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    Some(copy)
+}
+
+/// builds the connector to give Config::http_connector
+pub fn connector(args: &HttpArgs) -> Result<DynConnector, Box<dyn Error>> {
+    let mut direct = HttpConnector::new();
+    // the tls layer above handles https, so let https uris through
+    direct.enforce_http(false);
+    direct.set_keepalive(args.tcp_keepalive.map(Duration::from_secs));
+    let proxied = ProxyConnector::new(direct, ProxySettings::new(args.proxy.as_deref())?);
+
+    let tls = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(tls_config(args)?)
+        .https_or_http();
+    // the protocols here are the ones offered in the TLS handshake
+    let https = match args.http2 {
+        Http2::Negotiate => tls.enable_http1().enable_http2().wrap_connector(proxied),
+        Http2::Off => tls.enable_http1().wrap_connector(proxied),
+        Http2::Only => tls.enable_http2().wrap_connector(proxied),
+    };
+
+    let mut pool = hyper::Client::builder();
+    pool.http2_only(matches!(args.http2, Http2::Only));
+    if let Some(max) = args.max_connections {
+        pool.pool_max_idle_per_host(max);
+    }
+    if let Some(secs) = args.idle_timeout {
+        pool.pool_idle_timeout(Duration::from_secs(secs));
+    }
+    let adapter = DynConnector::new(hyper_ext::Adapter::builder().hyper_builder(pool).build(https));
+    if args.headers.is_empty() && args.user_agent_suffix.is_none() {
+        return Ok(adapter);
+    }
+    Ok(DynConnector::new(ExtraHeaders {
+        inner: adapter,
+        user_agent_suffix: args.user_agent_suffix.as_ref().map(|name| name.to_string()),
+        headers: Arc::new(args.headers.clone()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_headers() {
+        let (name, value) = parse_header("X-Request-Source: nightly backup ").unwrap();
+        assert_eq!((name.as_str(), value.to_str().unwrap()), ("x-request-source", "nightly backup"));
+        // only the first colon separates the name
+        let (name, value) = parse_header("x-trace:a:b:c").unwrap();
+        assert_eq!((name.as_str(), value.to_str().unwrap()), ("x-trace", "a:b:c"));
+        let (_, value) = parse_header("x-empty:").unwrap();
+        assert!(value.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_and_signed_headers() {
+        for value in ["no-colon", ":value", "bad name:v", "x-ok:line\nbreak"] {
+            assert!(parse_header(value).is_err(), "{:?}", value);
+        }
+        for value in ["X-Amz-Date:now", "x-amz-meta-a:b", "Authorization:AWS x", "host:example.com"] {
+            assert!(parse_header(value).unwrap_err().contains("can't be overridden"), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn app_names_are_tokens() {
+        assert!(parse_app_name("nightly-backup_v2").is_ok());
+        assert!(parse_app_name("has space").is_err());
+        assert!(parse_app_name("slash/not").is_err());
+    }
+}
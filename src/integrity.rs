@@ -0,0 +1,114 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{ChecksumAlgorithm, ChecksumMode, MetadataDirective};
+use clap::ValueEnum;
+use crate::key;
+use crate::payer;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Algorithm {
+    Crc32,
+    Crc32c,
+    Sha1,
+    Sha256,
+}
+
+impl Algorithm {
+    fn sdk(self) -> ChecksumAlgorithm {
+        match self {
+            Algorithm::Crc32 => ChecksumAlgorithm::Crc32,
+            Algorithm::Crc32c => ChecksumAlgorithm::Crc32C,
+            Algorithm::Sha1 => ChecksumAlgorithm::Sha1,
+            Algorithm::Sha256 => ChecksumAlgorithm::Sha256,
+        }
+    }
+}
+
+/// S3 stores a multipart upload's checksum as a checksum of the part checksums, suffixed -N
+fn describe(value: &str) -> String {
+    match value.rsplit_once('-') {
+        Some((_, parts)) if parts.parse::<u32>().is_ok() => format!("{} (composite of {} parts)", value, parts),
+        _ => format!("{} (full object)", value),
+    }
+}
+
+/// prints the ETag and every checksum stored with key
+pub async fn checksum_info(client: &Client, bucket_name: &str, key: &str) -> Result<(), Box<dyn Error>> {
+    let head = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .checksum_mode(ChecksumMode::Enabled)
+        .send()
+        .await?;
+    let e_tag = head.e_tag().unwrap_or_default();
+    match e_tag.trim_matches('"').rsplit_once('-') {
+        Some((_, parts)) => println!("etag: {} (multipart, {} parts)", e_tag, parts),
+        None => println!("etag: {} (single part)", e_tag),
+    }
+    let checksums = [
+        ("crc32", head.checksum_crc32()),
+        ("crc32c", head.checksum_crc32_c()),
+        ("sha1", head.checksum_sha1()),
+        ("sha256", head.checksum_sha256()),
+    ];
+    let mut found = false;
+    for (name, value) in checksums {
+        if let Some(value) = value {
+            println!("{}: {}", name, describe(value));
+            found = true;
+        }
+    }
+    if !found {
+        println!("no checksums stored");
+    }
+    Ok(())
+}
+
+/// copies key onto itself so S3 computes and stores an algorithm checksum for it. in a versioned
+/// bucket that makes a new version; metadata, content headers, storage class and encryption
+/// are carried over
+pub async fn rechecksum(client: &Client, bucket_name: &str, key: &str, algorithm: Algorithm) -> Result<(), Box<dyn Error>> {
+    let head = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .send()
+        .await?;
+    if head.content_length() > key::MAX_COPY_SIZE {
+        return Err(format!("{} is over 5 GiB, which needs a multipart copy", key).into());
+    }
+    let result = client.copy_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .copy_source(key::copy_source(bucket_name, key, head.version_id()))
+        .key(key)
+        .checksum_algorithm(algorithm.sdk())
+        // S3 only copies an object onto itself when something changes, and a new checksum
+        // algorithm doesn't count, so the metadata is replaced with what it already was
+        .metadata_directive(MetadataDirective::Replace)
+        .set_metadata(head.metadata().cloned())
+        .set_content_type(head.content_type().map(str::to_string))
+        .set_content_encoding(head.content_encoding().map(str::to_string))
+        .set_content_disposition(head.content_disposition().map(str::to_string))
+        .set_content_language(head.content_language().map(str::to_string))
+        .set_cache_control(head.cache_control().map(str::to_string))
+        .set_expires(head.expires().cloned())
+        .set_storage_class(head.storage_class().cloned())
+        .set_server_side_encryption(head.server_side_encryption().cloned())
+        .set_ssekms_key_id(head.ssekms_key_id().map(str::to_string))
+        .send()
+        .await?;
+    let copied = result.copy_object_result();
+    let checksum = copied.and_then(|c| match algorithm {
+        Algorithm::Crc32 => c.checksum_crc32(),
+        Algorithm::Crc32c => c.checksum_crc32_c(),
+        Algorithm::Sha1 => c.checksum_sha1(),
+        Algorithm::Sha256 => c.checksum_sha256(),
+    });
+    println!("{} now has {} checksum {}", key, algorithm.sdk().as_str(), checksum.unwrap_or("<not returned>"));
+    if let Some(version_id) = result.version_id() {
+        println!("new version: {}", version_id);
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_s3::types::{Object, ObjectVersion};
+use clap::ValueEnum;
+use futures_util::{StreamExt, TryStreamExt};
+use crate::listing::{object_pages_from, version_pages};
+use crate::resume::ResumeFile;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    /// requires building with the `parquet` feature
+    Parquet,
+}
+
+pub struct InventoryRow {
+    pub key: String,
+    /// empty when listing without versions
+    pub version_id: String,
+    pub is_latest: bool,
+    pub size: i64,
+    pub e_tag: String,
+    pub storage_class: String,
+    pub checksum_algorithm: String,
+    /// RFC 3339, empty if unknown
+    pub last_modified: String,
+}
+
+const COLUMNS: [&str; 8] = ["key", "version_id", "is_latest", "size", "e_tag", "storage_class", "checksum_algorithm", "last_modified"];
+
+fn format_time(time: Option<&DateTime>) -> String {
+    time.and_then(|t| t.fmt(DateTimeFormat::DateTime).ok()).unwrap_or_default()
+}
+
+fn version_row(version: &ObjectVersion) -> InventoryRow {
+    InventoryRow {
+        key: version.key().unwrap_or_default().to_string(),
+        version_id: version.version_id().unwrap_or_default().to_string(),
+        is_latest: version.is_latest(),
+        size: version.size(),
+        e_tag: version.e_tag().unwrap_or_default().trim_matches('"').to_string(),
+        storage_class: version.storage_class().map(|c| c.as_str().to_string()).unwrap_or_default(),
+        checksum_algorithm: join_algorithms(version.checksum_algorithm().unwrap_or_default().iter().map(|a| a.as_str())),
+        last_modified: format_time(version.last_modified()),
+    }
+}
+
+fn object_row(object: &Object) -> InventoryRow {
+    InventoryRow {
+        key: object.key().unwrap_or_default().to_string(),
+        version_id: String::new(),
+        is_latest: true,
+        size: object.size(),
+        e_tag: object.e_tag().unwrap_or_default().trim_matches('"').to_string(),
+        storage_class: object.storage_class().map(|c| c.as_str().to_string()).unwrap_or_default(),
+        checksum_algorithm: join_algorithms(object.checksum_algorithm().unwrap_or_default().iter().map(|a| a.as_str())),
+        last_modified: format_time(object.last_modified()),
+    }
+}
+
+/// writes one row per object (or per version) under prefix to output. CSV rows are written a
+/// listing page at a time; parquet row groups need every row first. with resume set, a CSV
+/// inventory of current objects saves the token for the next page there after writing each one,
+/// and a later run appends to output from that page
+pub async fn run(client: &Client, bucket_name: &str, prefix: &str, output: &str, format: Format, include_versions: bool, resume: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let resume = resume.map(|path| ResumeFile::new(path, bucket_name, prefix));
+    if resume.is_some() && (include_versions || format != Format::Csv) {
+        return Err("--resume-token-file only works for csv inventories of current objects".into());
+    }
+    let start = match &resume {
+        Some(resume) => resume.load()?,
+        None => None,
+    };
+    let resuming = start.is_some();
+    let mut pages = pin!(if include_versions {
+        version_pages(client, bucket_name, prefix)
+            .map_ok(|(versions, _)| (versions.iter().map(version_row).collect::<Vec<_>>(), None))
+            .left_stream()
+    } else {
+        object_pages_from(client, bucket_name, prefix, start)
+            .map_ok(|(objects, next)| (objects.iter().map(object_row).collect::<Vec<_>>(), next))
+            .right_stream()
+    });
+    let count = match format {
+        Format::Csv => {
+            let mut out = if resuming {
+                BufWriter::new(OpenOptions::new().append(true).open(output)?)
+            } else {
+                let mut out = BufWriter::new(File::create(output)?);
+                writeln!(out, "{}", COLUMNS.join(","))?;
+                out
+            };
+            let mut count = 0;
+            while let Some((rows, next)) = pages.try_next().await? {
+                write_csv_rows(&mut out, &rows)?;
+                count += rows.len();
+                if let Some(resume) = &resume {
+                    out.flush()?;
+                    resume.save(next.as_deref())?;
+                }
+            }
+            out.flush()?;
+            count
+        }
+        Format::Parquet => {
+            let mut rows = Vec::new();
+            while let Some((page, _)) = pages.try_next().await? {
+                rows.extend(page);
+            }
+            write_parquet(output, &rows)?;
+            rows.len()
+        }
+    };
+    println!("wrote {} rows to {}", count, output);
+    Ok(())
+}
+
+fn join_algorithms<'a>(algorithms: impl Iterator<Item = &'a str>) -> String {
+    algorithms.collect::<Vec<_>>().join(";")
+}
+
+/// quotes a CSV field if it contains a delimiter, quote, or line break
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_csv_rows(out: &mut impl Write, rows: &[InventoryRow]) -> Result<(), Box<dyn Error>> {
+    for row in rows {
+        writeln!(out, "{},{},{},{},{},{},{},{}", csv_field(&row.key), csv_field(&row.version_id), row.is_latest, row.size,
+                 csv_field(&row.e_tag), csv_field(&row.storage_class), csv_field(&row.checksum_algorithm), row.last_modified)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_output: &str, _rows: &[InventoryRow]) -> Result<(), Box<dyn Error>> {
+    Err("parquet output requires building with --features parquet".into())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(output: &str, rows: &[InventoryRow]) -> Result<(), Box<dyn Error>> {
+    use std::sync::Arc;
+    use parquet::data_type::{BoolType, ByteArray, ByteArrayType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    const SCHEMA: &str = "message inventory {
+        REQUIRED BYTE_ARRAY key (UTF8);
+        REQUIRED BYTE_ARRAY version_id (UTF8);
+        REQUIRED BOOLEAN is_latest;
+        REQUIRED INT64 size;
+        REQUIRED BYTE_ARRAY e_tag (UTF8);
+        REQUIRED BYTE_ARRAY storage_class (UTF8);
+        REQUIRED BYTE_ARRAY checksum_algorithm (UTF8);
+        REQUIRED BYTE_ARRAY last_modified (UTF8);
+    }";
+    const ROW_GROUP_SIZE: usize = 100_000;
+
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let mut writer = SerializedFileWriter::new(File::create(output)?, schema, Arc::new(WriterProperties::builder().build()))?;
+    for group in rows.chunks(ROW_GROUP_SIZE) {
+        let strings = |field: fn(&InventoryRow) -> &str| -> Vec<ByteArray> {
+            group.iter().map(|row| ByteArray::from(field(row))).collect()
+        };
+        let mut row_group = writer.next_row_group()?;
+        let mut column = 0;
+        while let Some(mut writer) = row_group.next_column()? {
+            match COLUMNS[column] {
+                "is_latest" => {
+                    let values: Vec<bool> = group.iter().map(|row| row.is_latest).collect();
+                    writer.typed::<BoolType>().write_batch(&values, None, None)?;
+                }
+                "size" => {
+                    let values: Vec<i64> = group.iter().map(|row| row.size).collect();
+                    writer.typed::<Int64Type>().write_batch(&values, None, None)?;
+                }
+                name => {
+                    let values = match name {
+                        "key" => strings(|row| &row.key),
+                        "version_id" => strings(|row| &row.version_id),
+                        "e_tag" => strings(|row| &row.e_tag),
+                        "storage_class" => strings(|row| &row.storage_class),
+                        "checksum_algorithm" => strings(|row| &row.checksum_algorithm),
+                        _ => strings(|row| &row.last_modified),
+                    };
+                    writer.typed::<ByteArrayType>().write_batch(&values, None, None)?;
+                }
+            }
+            writer.close()?;
+            column += 1;
+        }
+        row_group.close()?;
+    }
+    writer.close()?;
+    Ok(())
+}
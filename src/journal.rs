@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+const HEADER: &str = "# s3test sync journal";
+
+/// what Sync last knew about a local file it had synced
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    pub mtime: i64,
+    pub size: u64,
+    /// the ETag: hex md5 of the contents, or a multipart ETag. empty if never computed
+    pub checksum: String,
+    /// remote version id, empty if unknown
+    pub version_id: String,
+}
+
+/// entries keyed by path relative to the synced directory, for one bucket and prefix
+pub struct Journal {
+    bucket_name: String,
+    prefix: String,
+    pub entries: HashMap<String, JournalEntry>,
+}
+
+impl Journal {
+    pub fn new(bucket_name: &str, prefix: &str) -> Journal {
+        Journal { bucket_name: bucket_name.to_string(), prefix: prefix.to_string(), entries: HashMap::new() }
+    }
+
+    /// loads the journal at path. returns None if it doesn't exist or was written for a different bucket or prefix
+    pub fn load(path: &Path, bucket_name: &str, prefix: &str) -> Result<Option<Journal>, Box<dyn Error>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut lines = text.lines();
+        let expected = format!("{}\t{}\t{}", HEADER, bucket_name, urlencoding::encode(prefix));
+        if lines.next() != Some(expected.as_str()) {
+            println!("journal {} is for a different bucket or prefix, ignoring it", path.display());
+            return Ok(None);
+        }
+        let mut journal = Journal::new(bucket_name, prefix);
+        for (idx, line) in lines.enumerate() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [relative, mtime, size, checksum, version_id] = fields[..] else {
+                return Err(format!("{}:{}: malformed journal entry", path.display(), idx + 2).into());
+            };
+            let entry = JournalEntry {
+                mtime: mtime.parse()?,
+                size: size.parse()?,
+                checksum: checksum.to_string(),
+                version_id: version_id.to_string(),
+            };
+            journal.entries.insert(urlencoding::decode(relative)?.into_owned(), entry);
+        }
+        Ok(Some(journal))
+    }
+
+    /// writes the journal to path, replacing it atomically
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        writeln!(file, "{}\t{}\t{}", HEADER, self.bucket_name, urlencoding::encode(&self.prefix))?;
+        let mut keys: Vec<&String> = self.entries.keys().collect();
+        keys.sort();
+        for relative in keys {
+            let entry = &self.entries[relative];
+            writeln!(file, "{}\t{}\t{}\t{}\t{}", urlencoding::encode(relative), entry.mtime, entry.size, entry.checksum, entry.version_id)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::path::{Component, Path, PathBuf};
+
+/// the longest key S3 accepts, in bytes of UTF-8
+pub const MAX_LEN: usize = 1024;
+
+/// checks that key can be stored and listed: not empty, at most MAX_LEN bytes, and without the
+/// control characters XML listings can't carry. spaces, '+', '%' and non-ASCII are all fine
+pub fn validate(key: &str) -> Result<(), Box<dyn Error>> {
+    if key.is_empty() {
+        return Err("the key can't be empty".into());
+    }
+    if key.len() > MAX_LEN {
+        return Err(format!("key is {} bytes, more than the {} S3 allows", key.len(), MAX_LEN).into());
+    }
+    if key.chars().any(|c| c < ' ' || c == '\u{7f}') {
+        return Err(format!("key {:?} has control characters, which S3 listings can't return", key).into());
+    }
+    Ok(())
+}
+
+/// percent-encodes each path segment of key, leaving the '/' separators readable
+pub fn encode_path(key: &str) -> String {
+    key.split('/').map(|segment| urlencoding::encode(segment)).collect::<Vec<_>>().join("/")
+}
+
+/// the x-amz-copy-source value for key in bucket_name, which S3 expects URL-encoded
+pub fn copy_source(bucket_name: &str, key: &str, version_id: Option<&str>) -> String {
+    let source = format!("{}/{}", bucket_name, encode_path(key));
+    match version_id {
+        Some(version_id) => format!("{}?versionId={}", source, urlencoding::encode(version_id)),
+        None => source,
+    }
+}
+
+/// the largest object a single CopyObject can copy, and the most one UploadPartCopy copies
+pub const MAX_COPY_SIZE: i64 = 5 * 1024 * 1024 * 1024;
+
+/// the local path relative to a download directory for the part of a key below a prefix. empty
+/// and '.' segments are dropped; '..' segments, and ones the platform would read as a root or
+/// drive, are refused, so a key can't write outside the directory
+pub fn local_relative(relative: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let mut path = PathBuf::new();
+    for segment in relative.split('/').filter(|segment| !segment.is_empty() && *segment != ".") {
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => path.push(segment),
+            _ => return Err(format!("{:?} can't be written under a local directory", relative).into()),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        return Err(format!("{:?} has no file name", relative).into());
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRICKY: &[(&str, &str)] = &[
+        ("plain.txt", "plain.txt"),
+        ("with space.txt", "with%20space.txt"),
+        ("a+b", "a%2Bb"),
+        ("100%", "100%25"),
+        ("dir/sub dir/file", "dir/sub%20dir/file"),
+        ("a//b/", "a//b/"),
+        ("q?x=1&y#z", "q%3Fx%3D1%26y%23z"),
+        ("naïve/日本.txt", "na%C3%AFve/%E6%97%A5%E6%9C%AC.txt"),
+        ("~tilde_-.", "~tilde_-."),
+    ];
+
+    #[test]
+    fn tricky_keys_are_valid() {
+        for (key, _) in TRICKY {
+            assert!(validate(key).is_ok(), "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn rejects_empty_long_and_control_keys() {
+        assert!(validate("").is_err());
+        assert!(validate(&"k".repeat(MAX_LEN)).is_ok());
+        assert!(validate(&"k".repeat(MAX_LEN + 1)).is_err());
+        // the limit is in bytes, not characters
+        assert!(validate(&"é".repeat(MAX_LEN / 2 + 1)).is_err());
+        assert!(validate("line\nbreak").is_err());
+        assert!(validate("nul\0").is_err());
+        assert!(validate("del\u{7f}").is_err());
+    }
+
+    #[test]
+    fn encodes_segments_and_keeps_slashes() {
+        for (key, encoded) in TRICKY {
+            assert_eq!(encode_path(key), *encoded, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        for (key, _) in TRICKY {
+            assert_eq!(urlencoding::decode(&encode_path(key)).unwrap(), *key);
+        }
+    }
+
+    #[test]
+    fn local_paths_stay_under_the_directory() {
+        assert_eq!(local_relative("a/b c/d.txt").unwrap(), Path::new("a").join("b c").join("d.txt"));
+        assert_eq!(local_relative("a//./b").unwrap(), Path::new("a").join("b"));
+        assert_eq!(local_relative("-rf").unwrap(), Path::new("-rf"));
+        assert_eq!(local_relative("dots..in..name").unwrap(), Path::new("dots..in..name"));
+        assert!(local_relative("../escape").is_err());
+        assert!(local_relative("a/../../escape").is_err());
+        assert!(local_relative("a/..").is_err());
+        assert!(local_relative("").is_err());
+        assert!(local_relative("./").is_err());
+    }
+
+    #[test]
+    fn copy_source_encodes_key_and_version() {
+        assert_eq!(copy_source("bkt", "a b+c", None), "bkt/a%20b%2Bc");
+        assert_eq!(copy_source("bkt", "dir/100%", Some("v1.x+y")), "bkt/dir/100%25?versionId=v1.x%2By");
+    }
+}
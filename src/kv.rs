@@ -0,0 +1,206 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::ChecksumMode;
+use clap::Subcommand;
+use http::HeaderValue;
+use crate::checksum::Sha256;
+use crate::errors::Exit;
+use crate::key;
+use crate::listing::list_all_objects;
+use crate::payer;
+use crate::summary::{self, Outcome};
+use crate::upload::join_key;
+
+/// the largest value Set stores. anything bigger belongs in an object of its own
+const MAX_VALUE: usize = 1024 * 1024;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum KvAction {
+    /// print a key's value
+    Get {
+        namespace: String,
+        key: String,
+        #[arg(long, help = "print the value's ETag instead, for Set --if-etag")]
+        etag: bool,
+    },
+    /// set a key's value
+    Set {
+        namespace: String,
+        key: String,
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+        #[arg(long, value_name = "ETAG", conflicts_with = "if_absent", help = "only set it if its value still has this ETag, as Get --etag printed")]
+        if_etag: Option<String>,
+        #[arg(long, help = "only set it if it has no value")]
+        if_absent: bool,
+    },
+    /// remove a key
+    Del {
+        namespace: String,
+        key: String,
+        #[arg(long, value_name = "ETAG", help = "only remove it if its value still has this ETag")]
+        if_etag: Option<String>,
+    },
+    /// list a namespace's keys
+    List {
+        namespace: String,
+    },
+}
+
+/// the object holding key in namespace
+fn object_key(prefix: &str, namespace: &str, key: &str) -> Result<String, Box<dyn Error>> {
+    if namespace.is_empty() || namespace.contains('/') {
+        return Err(format!("namespace {:?} must be non-empty and have no '/'", namespace).into());
+    }
+    if key.is_empty() {
+        return Err("the key can't be empty".into());
+    }
+    let object = join_key(&join_key(prefix, namespace), key);
+    key::validate(&object)?;
+    Ok(object)
+}
+
+/// the current value's ETag and SHA-256, or None if there's no value
+async fn current(client: &Client, bucket_name: &str, object: &str) -> Result<Option<(String, Option<String>)>, Box<dyn Error>> {
+    let result = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(object)
+        .checksum_mode(ChecksumMode::Enabled)
+        .send()
+        .await;
+    match result {
+        Ok(head) => Ok(Some((head.e_tag().unwrap_or_default().to_string(), head.checksum_sha256().map(str::to_string)))),
+        Err(SdkError::ServiceError(err)) if err.err().is_not_found() => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// ends the command after a condition failed, which another writer changing the value in between causes
+fn conflict(object: &str, expected: &str) -> Box<dyn Error> {
+    println!("conflict: {} doesn't have ETag {} any more. Get it again and retry", object, expected);
+    Exit(1).into()
+}
+
+/// a tiny config store: each key is a small object under prefix/namespace/, stored with its
+/// SHA-256, which S3 checks on the way in and Get checks on the way out. writes given an ETag
+/// only happen if the value still has it, so readers who change values can't overwrite each
+/// other's changes
+pub async fn run(client: &Client, bucket_name: &str, prefix: &str, action: &KvAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        KvAction::Get { namespace, key, etag } => {
+            let object = object_key(prefix, namespace, key)?;
+            let result = client.get_object()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(&object)
+                .checksum_mode(ChecksumMode::Enabled)
+                .send()
+                .await;
+            let result = match result {
+                Ok(result) => result,
+                Err(SdkError::ServiceError(err)) if err.err().is_no_such_key() => {
+                    println!("{} has no value", object);
+                    return Err(Exit(1).into());
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if *etag {
+                println!("{}", result.e_tag().unwrap_or_default());
+                return Ok(());
+            }
+            let bytes = result.body.collect().await?.into_bytes();
+            println!("{}", String::from_utf8_lossy(&bytes));
+        }
+        KvAction::Set { namespace, key, value, if_etag, if_absent } => {
+            let object = object_key(prefix, namespace, key)?;
+            if value.len() > MAX_VALUE {
+                return Err(format!("values are limited to {} bytes", MAX_VALUE).into());
+            }
+            let checksum = Sha256::of(value.as_bytes());
+            let existing = current(client, bucket_name, &object).await?;
+            match (&existing, if_etag, if_absent) {
+                (Some((e_tag, _)), Some(expected), _) if e_tag != expected => return Err(conflict(&object, expected)),
+                (None, Some(expected), _) => return Err(conflict(&object, expected)),
+                (Some(_), _, true) => {
+                    println!("{} already has a value", object);
+                    return Err(Exit(1).into());
+                }
+                (Some((_, Some(sha256))), _, _) if *sha256 == checksum.base64() => {
+                    println!("{} is already set to that", object);
+                    summary::report("kv-set", &object, Outcome::NoOp);
+                    return Ok(());
+                }
+                _ => {}
+            }
+            // the condition is what was checked, so a write in between fails rather than being lost
+            let condition = match &existing {
+                Some((e_tag, _)) => ("if-match", HeaderValue::from_str(e_tag)?),
+                None => ("if-none-match", HeaderValue::from_static("*")),
+            };
+            let request = client.put_object()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(&object)
+                .content_type("text/plain; charset=utf-8")
+                .checksum_sha256(checksum.base64())
+                .body(ByteStream::from(value.clone().into_bytes()));
+            let request = checksum.sign_with(request.customize().await?)
+                .mutate_request(move |request| {
+                    request.headers_mut().insert(condition.0, condition.1.clone());
+                });
+            let result = match request.send().await {
+                Ok(result) => result,
+                Err(SdkError::ServiceError(err)) if err.raw().http().status().as_u16() == 412 => {
+                    let seen = existing.map(|(e_tag, _)| e_tag).unwrap_or_else(|| "none".to_string());
+                    return Err(conflict(&object, &seen));
+                }
+                Err(err) => return Err(err.into()),
+            };
+            println!("set {}: etag {}", object, result.e_tag().unwrap_or_default());
+            summary::report("kv-set", &object, Outcome::Changed);
+        }
+        KvAction::Del { namespace, key, if_etag } => {
+            let object = object_key(prefix, namespace, key)?;
+            let Some((e_tag, _)) = current(client, bucket_name, &object).await? else {
+                if let Some(expected) = if_etag {
+                    return Err(conflict(&object, expected));
+                }
+                println!("{} has no value", object);
+                summary::report("kv-del", &object, Outcome::NoOp);
+                return Ok(());
+            };
+            if let Some(expected) = if_etag.as_ref().filter(|expected| **expected != e_tag) {
+                return Err(conflict(&object, expected));
+            }
+            let condition = HeaderValue::from_str(&e_tag)?;
+            let request = client.delete_object()
+                .bucket(bucket_name)
+                .set_request_payer(payer::get())
+                .key(&object)
+                .customize()
+                .await?
+                .mutate_request(move |request| {
+                    request.headers_mut().insert("if-match", condition.clone());
+                });
+            match request.send().await {
+                Ok(_) => {}
+                Err(SdkError::ServiceError(err)) if err.raw().http().status().as_u16() == 412 => return Err(conflict(&object, &e_tag)),
+                Err(err) => return Err(err.into()),
+            }
+            println!("deleted {}", object);
+            summary::report("kv-del", &object, Outcome::Changed);
+        }
+        KvAction::List { namespace } => {
+            let dir = join_key(&join_key(prefix, namespace), "");
+            for stored in list_all_objects(client, bucket_name, &dir).await? {
+                if let Some(key) = stored.key().and_then(|key| key.strip_prefix(&dir)) {
+                    println!("{}", key);
+                }
+            }
+        }
+    }
+    Ok(())
+}
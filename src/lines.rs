@@ -0,0 +1,90 @@
+use std::error::Error;
+use std::io::{self, Write};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use crate::download;
+use crate::key;
+use crate::payer;
+
+/// bytes fetched by the first ranged GET. each one after fetches twice as many as the last, so
+/// long lines take a few requests rather than many
+const FIRST_RANGE: u64 = 64 * 1024;
+
+async fn head(client: &Client, bucket_name: &str, name: &str, version_id: Option<&str>) -> Result<HeadObjectOutput, Box<dyn Error>> {
+    let head = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(name)
+        .set_version_id(version_id.map(str::to_string))
+        .send()
+        .await?;
+    if !download::is_plain(&head, false) {
+        return Err(format!("{} is compressed or encrypted, so its lines can't be read a range at a time. use Get", name).into());
+    }
+    Ok(head)
+}
+
+/// the inclusive byte range of the version head describes
+pub async fn fetch(client: &Client, bucket_name: &str, name: &str, head: &HeadObjectOutput, (start, end): (u64, u64)) -> Result<Vec<u8>, Box<dyn Error>> {
+    let result = download::range_get(client, bucket_name, name, head, (start, end)).send().await?;
+    Ok(result.body.collect().await?.into_bytes().to_vec())
+}
+
+/// prints the first count lines of name, fetching ranges from the start until it has them
+pub async fn head_lines(client: &Client, bucket_name: &str, name: &str, version_id: Option<&str>, count: usize) -> Result<(), Box<dyn Error>> {
+    key::validate(name)?;
+    let head = head(client, bucket_name, name, version_id).await?;
+    let size = head.content_length().max(0) as u64;
+    let mut bytes = Vec::new();
+    let mut range = FIRST_RANGE;
+    let end = loop {
+        let newlines: Vec<usize> = bytes.iter().enumerate().filter(|(_, b)| **b == b'\n').map(|(idx, _)| idx).collect();
+        if count == 0 {
+            break 0;
+        }
+        if let Some(last) = newlines.get(count - 1) {
+            break last + 1;
+        }
+        if bytes.len() as u64 >= size {
+            break bytes.len();
+        }
+        let start = bytes.len() as u64;
+        bytes.extend(fetch(client, bucket_name, name, &head, (start, (start + range).min(size) - 1)).await?);
+        range *= 2;
+    };
+    io::stdout().write_all(&bytes[..end])?;
+    Ok(())
+}
+
+/// prints the last count lines of name, fetching ranges back from the end until it has them. a
+/// newline ending the object doesn't count as the start of another line
+pub async fn tail_lines(client: &Client, bucket_name: &str, name: &str, version_id: Option<&str>, count: usize) -> Result<(), Box<dyn Error>> {
+    key::validate(name)?;
+    let head = head(client, bucket_name, name, version_id).await?;
+    let size = head.content_length().max(0) as u64;
+    // bytes holds the object from start to the end
+    let mut bytes = Vec::new();
+    let mut start = size;
+    let mut range = FIRST_RANGE;
+    let begin = loop {
+        let body = bytes.strip_suffix(b"\n").unwrap_or(&bytes);
+        let newlines: Vec<usize> = body.iter().enumerate().filter(|(_, b)| **b == b'\n').map(|(idx, _)| idx).collect();
+        if count == 0 {
+            break bytes.len();
+        }
+        if newlines.len() >= count {
+            break newlines[newlines.len() - count] + 1;
+        }
+        if start == 0 {
+            break 0;
+        }
+        let from = start.saturating_sub(range);
+        let mut fetched = fetch(client, bucket_name, name, &head, (from, start - 1)).await?;
+        fetched.extend(bytes);
+        bytes = fetched;
+        start = from;
+        range *= 2;
+    };
+    io::stdout().write_all(&bytes[begin..])?;
+    Ok(())
+}
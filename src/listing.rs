@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::DateTimeFormat;
+use aws_sdk_s3::types::{DeleteMarkerEntry, Object, ObjectVersion};
+use clap::{Args, ValueEnum};
+use futures_util::{stream, Stream, TryStreamExt};
+use crate::payer;
+use crate::report::format_bytes;
+use crate::resume::ResumeFile;
+
+/// the most keys ListObjectsV2 returns per request
+const MAX_PAGE_SIZE: i32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SortKey {
+    Name,
+    Size,
+    Mtime,
+}
+
+#[derive(Args, Clone, Debug)]
+pub struct LsOptions {
+    #[arg(long, value_name = "KEY", help = "only list keys after this one")]
+    start_after: Option<String>,
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), help = "list this many objects, over as many requests as it takes. defaults to one page")]
+    max_keys: Option<u64>,
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(i32).range(1..=1000), help = "objects to ask for per request, for gateways that are slow to return 1000")]
+    page_size: Option<i32>,
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["sort", "limit"], help = "start from the position saved here, and save where this listing ended")]
+    resume_token_file: Option<PathBuf>,
+    #[arg(long, value_enum, help = "list every page (or --max-keys objects), then sort by this")]
+    sort: Option<SortKey>,
+    #[arg(long, requires = "sort", help = "sort the other way: largest, newest or last name first")]
+    reverse: bool,
+    #[arg(long, value_name = "N", help = "print only the first N objects, after sorting")]
+    limit: Option<usize>,
+    #[arg(long, help = "show each key's size with the number and size of its noncurrent versions")]
+    versions: bool,
+}
+
+fn sort_objects(objects: &mut [Object], key: SortKey, reverse: bool) {
+    match key {
+        SortKey::Name => objects.sort_by(|a, b| a.key().cmp(&b.key())),
+        SortKey::Size => objects.sort_by_key(|object| object.size()),
+        SortKey::Mtime => objects.sort_by_key(|object| object.last_modified().map(|d| (d.secs(), d.subsec_nanos()))),
+    }
+    if reverse {
+        objects.reverse();
+    }
+}
+
+/// the count and bytes of the noncurrent versions of each key under prefix from first to last.
+/// versions are listed in key order, so the listing stops once it's past last
+async fn noncurrent_usage(client: &Client, bucket_name: &str, prefix: &str, first: &str, last: &str) -> Result<HashMap<String, (usize, i64)>, Box<dyn Error>> {
+    let mut usage: HashMap<String, (usize, i64)> = HashMap::new();
+    let mut pages = pin!(version_pages(client, bucket_name, prefix));
+    while let Some((versions, _)) = pages.try_next().await? {
+        for version in versions {
+            let Some(key) = version.key() else { continue };
+            if key > last {
+                return Ok(usage);
+            }
+            if key < first || version.is_latest() {
+                continue;
+            }
+            let entry = usage.entry(key.to_string()).or_default();
+            entry.0 += 1;
+            entry.1 += version.size();
+        }
+    }
+    Ok(usage)
+}
+
+fn print_object(object: &Object, sort: Option<SortKey>, usage: Option<&HashMap<String, (usize, i64)>>) {
+    let key = object.key().unwrap_or("<none>");
+    let mut details = Vec::new();
+    if sort == Some(SortKey::Size) || usage.is_some() {
+        details.push(format!("{} bytes", object.size()));
+    }
+    if sort == Some(SortKey::Mtime) {
+        details.push(object.last_modified().and_then(|d| d.fmt(DateTimeFormat::DateTime).ok()).unwrap_or_default());
+    }
+    if let Some(usage) = usage {
+        let (count, bytes) = usage.get(key).copied().unwrap_or_default();
+        details.push(format!("{} noncurrent versions, {}", count, format_bytes(bytes)));
+    }
+    if details.is_empty() {
+        println!("Object: {:?}", key);
+    } else {
+        println!("Object: {:?} ({})", key, details.join(", "));
+    }
+}
+
+/// prints the keys under prefix: one page, or max_keys objects. the last request asks for just
+/// what's left, so a saved position never skips keys that weren't printed. sorting lists every
+/// page first; a limit without sorting stops listing once it has enough
+pub async fn ls(client: &Client, bucket_name: &str, prefix: &str, options: &LsOptions) -> Result<(), Box<dyn Error>> {
+    let resume = options.resume_token_file.as_deref().map(|path| ResumeFile::new(path, bucket_name, prefix));
+    let mut token = match &resume {
+        Some(resume) => resume.load()?,
+        None => None,
+    };
+    // a continuation token already encodes where the listing started
+    let mut start_after = if token.is_none() { options.start_after.clone() } else { None };
+    let all_pages = options.sort.is_some() || options.limit.is_some();
+    // noncurrent usage is looked up for the whole key range once it's listed
+    let collect = all_pages || options.versions;
+    let unsorted_limit = options.limit.filter(|_| options.sort.is_none()).map(|limit| limit as u64);
+    let max_keys = options.max_keys.into_iter().chain(unsorted_limit).min();
+    let mut collected = Vec::new();
+    let mut listed: u64 = 0;
+    loop {
+        let remaining = max_keys.map(|max| i32::try_from(max - listed).unwrap_or(i32::MAX));
+        let page_size = match (options.page_size, remaining) {
+            (Some(size), Some(remaining)) => Some(size.min(remaining)),
+            (None, Some(remaining)) => Some(remaining.min(MAX_PAGE_SIZE)),
+            (size, None) => size,
+        };
+        let result = client.list_objects_v2()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .prefix(prefix)
+            .set_continuation_token(token.take())
+            .set_start_after(start_after.take())
+            .set_max_keys(page_size)
+            .send()
+            .await?;
+        let objects = result.contents.unwrap_or_default();
+        listed += objects.len() as u64;
+        if collect {
+            collected.extend(objects);
+        } else {
+            objects.iter().for_each(|object| print_object(object, None, None));
+        }
+        token = if result.is_truncated { result.next_continuation_token } else { None };
+        let done = match max_keys {
+            Some(max) => listed >= max,
+            None => !all_pages,
+        };
+        if token.is_none() || done {
+            break;
+        }
+    }
+    let usage = match (options.versions, collected.first().and_then(|o| o.key()), collected.last().and_then(|o| o.key())) {
+        (true, Some(first), Some(last)) => Some(noncurrent_usage(client, bucket_name, prefix, first, last).await?),
+        (true, _, _) => Some(HashMap::new()),
+        _ => None,
+    };
+    if let Some(key) = options.sort {
+        sort_objects(&mut collected, key, options.reverse);
+    }
+    collected.iter().take(options.limit.unwrap_or(usize::MAX)).for_each(|object| print_object(object, options.sort, usage.as_ref()));
+    if listed == 0 {
+        println!("no contents");
+    }
+    if let Some(resume) = &resume {
+        resume.save(token.as_deref())?;
+        match token {
+            Some(_) => println!("saved the position for the next page to {}", resume.path.display()),
+            None => println!("end of listing"),
+        }
+    }
+    Ok(())
+}
+
+/// pages of the objects under prefix, each listed when the stream is polled for it, so
+/// work on one page can start before the next is requested
+pub fn object_pages<'a>(client: &'a Client, bucket_name: &'a str, prefix: &'a str) -> impl Stream<Item = Result<Vec<Object>, Box<dyn Error>>> + 'a {
+    object_pages_from(client, bucket_name, prefix, None).map_ok(|(objects, _)| objects)
+}
+
+/// pages of the objects under prefix starting at a continuation token, each with the token for
+/// the page after it, or None for the last page
+pub fn object_pages_from<'a>(client: &'a Client, bucket_name: &'a str, prefix: &'a str, start: Option<String>) -> impl Stream<Item = Result<(Vec<Object>, Option<String>), Box<dyn Error>>> + 'a {
+    // the state is the continuation token to send, or None after the last page
+    stream::try_unfold(Some(start), move |token: Option<Option<String>>| async move {
+        let Some(token) = token else { return Ok(None) };
+        let result = client.list_objects_v2()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .prefix(prefix)
+            .set_continuation_token(token)
+            .send()
+            .await?;
+        let next = match (result.is_truncated, result.next_continuation_token) {
+            (true, Some(token)) => Some(token),
+            _ => None,
+        };
+        let page = (result.contents.unwrap_or_default(), next.clone());
+        Ok::<_, Box<dyn Error>>(Some((page, next.map(Some))))
+    })
+}
+
+/// pages of the versions and delete markers under prefix, listed as the stream is polled
+pub fn version_pages<'a>(client: &'a Client, bucket_name: &'a str, prefix: &'a str) -> impl Stream<Item = Result<(Vec<ObjectVersion>, Vec<DeleteMarkerEntry>), Box<dyn Error>>> + 'a {
+    // the state is the key and version id markers to send, or None after the last page
+    stream::try_unfold(Some((None, None)), move |markers: Option<(Option<String>, Option<String>)>| async move {
+        let Some((key_marker, version_marker)) = markers else { return Ok(None) };
+        let result = client.list_object_versions()
+            .bucket(bucket_name)
+            .prefix(prefix)
+            .set_key_marker(key_marker)
+            .set_version_id_marker(version_marker)
+            .send()
+            .await?;
+        let next = match (result.is_truncated, result.next_key_marker) {
+            (true, Some(key_marker)) => Some((Some(key_marker), result.next_version_id_marker)),
+            _ => None,
+        };
+        let page = (result.versions.unwrap_or_default(), result.delete_markers.unwrap_or_default());
+        Ok::<_, Box<dyn Error>>(Some((page, next)))
+    })
+}
+
+/// returns every object under prefix, following continuation tokens
+pub async fn list_all_objects(client: &Client, bucket_name: &str, prefix: &str) -> Result<Vec<Object>, Box<dyn Error>> {
+    let mut objects = Vec::new();
+    let mut pages = pin!(object_pages(client, bucket_name, prefix));
+    while let Some(page) = pages.try_next().await? {
+        objects.extend(page);
+    }
+    Ok(objects)
+}
+
+/// returns every version and delete marker under prefix, following key/version markers
+pub async fn list_all_versions(client: &Client, bucket_name: &str, prefix: &str) -> Result<(Vec<ObjectVersion>, Vec<DeleteMarkerEntry>), Box<dyn Error>> {
+    let mut versions = Vec::new();
+    let mut markers = Vec::new();
+    let mut pages = pin!(version_pages(client, bucket_name, prefix));
+    while let Some((page_versions, page_markers)) = pages.try_next().await? {
+        versions.extend(page_versions);
+        markers.extend(page_markers);
+    }
+    Ok((versions, markers))
+}
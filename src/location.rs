@@ -0,0 +1,27 @@
+use crate::config::Settings;
+
+/// marks a key argument that names its own bucket
+const SCHEME: &str = "s3://";
+
+/// splits a key or prefix argument into the bucket and key it names. `s3://bucket/key` names its
+/// own bucket, which may be an access point ARN or an alias; anything else is a key in default_bucket.
+/// only --bucket brings an alias's endpoint and region along
+pub fn split<'a>(arg: &'a str, default_bucket: &'a str, settings: &'a Settings) -> (&'a str, &'a str) {
+    let Some(rest) = arg.strip_prefix(SCHEME) else { return (default_bucket, arg) };
+    // ARN resources have slashes of their own: accesspoint/NAME or outpost/ID/accesspoint/NAME
+    let slashes = match rest.starts_with("arn:") {
+        true if rest.contains(":outpost/") => 3,
+        true => 1,
+        false => 0,
+    };
+    let (bucket, key) = match rest.match_indices('/').nth(slashes) {
+        Some((idx, _)) => (&rest[..idx], &rest[idx + 1..]),
+        None => (rest, ""),
+    };
+    if let Some(alias) = settings.aliases.get(bucket) {
+        if alias.endpoint.is_some() || alias.region.is_some() {
+            eprintln!("note: using {} through the default endpoint and region. pass --bucket {} to use its own", alias.bucket, bucket);
+        }
+    }
+    (settings.bucket(bucket), key)
+}
@@ -0,0 +1,143 @@
+use std::env;
+use std::error::Error;
+use std::process;
+use std::time::Duration;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::ByteStream;
+use clap::Args;
+use http::HeaderValue;
+use crate::clock;
+use crate::key;
+use crate::payer;
+use crate::stop;
+
+/// where lock objects live in the bucket
+const PREFIX: &str = ".s3test-lock/";
+
+const OWNER_KEY: &str = "s3test-lock-owner";
+/// seconds since the epoch after which the lock is stale and can be taken over
+const EXPIRES_KEY: &str = "s3test-lock-expires";
+
+// no doc comment, which clap would show as the program's description
+#[derive(Args, Debug, Clone)]
+pub struct LockArgs {
+    #[arg(long, global = true, value_name = "NAME", help = "hold a lock object with this name in the bucket while running, so two runs with the same lock can't overlap")]
+    pub lock: Option<String>,
+
+    #[arg(long, global = true, value_name = "DURATION", default_value = "1h", value_parser = stop::parse_duration, requires = "lock", help = "how long the lock lasts if this run dies without releasing it")]
+    pub lock_ttl: Duration,
+
+    #[arg(long, global = true, requires = "lock", help = "take the lock even if another run holds it, for when that run is gone")]
+    pub break_lock: bool,
+}
+
+impl LockArgs {
+    /// takes the --lock lock in bucket_name, if one was asked for
+    pub async fn acquire(&self, client: &Client, bucket_name: &str) -> Result<Option<Lock>, Box<dyn Error>> {
+        match &self.lock {
+            Some(name) => Ok(Some(acquire(client, bucket_name, name, self.lock_ttl, self.break_lock).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// a lock object this run created, deleted again when it's dropped
+pub struct Lock {
+    client: Client,
+    bucket_name: String,
+    key: String,
+    version_id: Option<String>,
+}
+
+/// the version holding key, who holds it and until when
+struct Holder {
+    version_id: Option<String>,
+    owner: String,
+    expires: i64,
+}
+
+async fn holder(client: &Client, bucket_name: &str, key: &str) -> Result<Option<Holder>, Box<dyn Error>> {
+    let head = match client.head_object().bucket(bucket_name).set_request_payer(payer::get()).key(key).send().await {
+        Ok(head) => head,
+        Err(SdkError::ServiceError(err)) if err.err().is_not_found() => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let metadata = head.metadata();
+    let value = |name: &str| metadata.and_then(|m| m.get(name)).cloned().unwrap_or_default();
+    Ok(Some(Holder {
+        version_id: head.version_id().map(|v| v.to_string()),
+        owner: value(OWNER_KEY),
+        expires: value(EXPIRES_KEY).parse().unwrap_or(0),
+    }))
+}
+
+/// puts key only if there's no current object there. None if there already is one
+async fn try_put(client: &Client, bucket_name: &str, key: &str, owner: &str, expires: i64) -> Result<Option<Option<String>>, Box<dyn Error>> {
+    let request = client.put_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .metadata(OWNER_KEY, owner)
+        .metadata(EXPIRES_KEY, expires.to_string())
+        .body(ByteStream::from_static(b""))
+        .customize()
+        .await?
+        .mutate_request(|request| {
+            request.headers_mut().insert("if-none-match", HeaderValue::from_static("*"));
+        });
+    match request.send().await {
+        Ok(result) => Ok(Some(result.version_id().map(|v| v.to_string()))),
+        Err(SdkError::ServiceError(err)) if err.raw().http().status().as_u16() == 412 => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// deletes just the version that held the lock, so a lock someone else has taken since stays put
+async fn remove(client: &Client, bucket_name: &str, key: &str, version_id: Option<&str>) -> Result<(), Box<dyn Error>> {
+    client.delete_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .set_version_id(version_id.map(|v| v.to_string()))
+        .send()
+        .await?;
+    Ok(())
+}
+
+/// takes the lock called name in bucket_name for ttl, so another run asking for the same lock
+/// fails instead of working alongside this one. a lock past its expiry is taken over, and with
+/// break_lock so is one that isn't
+pub async fn acquire(client: &Client, bucket_name: &str, name: &str, ttl: Duration, break_lock: bool) -> Result<Lock, Box<dyn Error>> {
+    let key = format!("{}{}", PREFIX, name);
+    key::validate(&key)?;
+    let owner = format!("{}:{}", env::var("HOSTNAME").unwrap_or_else(|_| "unknown".to_string()), process::id());
+    let expires = clock::signing_time() + ttl.as_secs() as i64;
+    // once to take a free lock, and once more after removing an expired or broken one
+    for _ in 0..2 {
+        if let Some(version_id) = try_put(client, bucket_name, &key, &owner, expires).await? {
+            return Ok(Lock { client: client.clone(), bucket_name: bucket_name.to_string(), key, version_id });
+        }
+        let Some(holder) = holder(client, bucket_name, &key).await? else { continue };
+        let expired = holder.expires <= clock::signing_time();
+        if !expired && !break_lock {
+            return Err(format!("lock {} is held by {} for another {}s. use --break-lock if that run is gone",
+                               name, holder.owner, holder.expires - clock::signing_time()).into());
+        }
+        eprintln!("note: taking over lock {} from {}{}", name, holder.owner, if expired { ", which expired" } else { "" });
+        remove(client, bucket_name, &key, holder.version_id.as_deref()).await?;
+    }
+    Err(format!("lock {} was taken by another run at the same time", name).into())
+}
+
+impl Drop for Lock {
+    /// releasing has to happen before the process exits, so it blocks this worker thread
+    fn drop(&mut self) {
+        let result = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(remove(&self.client, &self.bucket_name, &self.key, self.version_id.as_deref()))
+        });
+        if let Err(err) = result {
+            eprintln!("note: couldn't release lock {}: {}. it expires on its own", self.key, err);
+        }
+    }
+}
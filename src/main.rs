@@ -1,182 +1,1552 @@
 use std::error::Error;
 use std::process;
 use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use aws_sdk_config::{config::Credentials};
+use aws_credential_types::provider::SharedCredentialsProvider;
 use aws_sdk_s3::{Client, Config};
 use aws_sdk_s3::config::Region;
-use aws_sdk_s3::operation::list_objects_v2::ListObjectsV2Output;
-use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::primitives::DateTime;
 use aws_sdk_s3::types::BucketVersioningStatus::Enabled;
-use aws_sdk_s3::types::ChecksumAlgorithm;
+use aws_sdk_s3::types::RequestPayer;
 use clap::{Parser, Subcommand};
-use md5::{Digest};
 use dotenv::dotenv;
+use errors::Exit;
+use summary::Outcome;
+
+mod accelerate;
+mod acl;
+mod anonymous;
+mod append;
+mod archive;
+mod bucket;
+mod bucket_tags;
+mod arn;
+mod cas;
+mod checksum;
+mod chunked;
+mod clone_bucket;
+mod clock;
+mod compression;
+mod config;
+mod configure;
+mod copy;
+mod curl;
+mod cost;
+#[cfg(unix)]
+mod daemon;
+mod debug_sign;
+mod dedup;
+mod delete;
+mod doctor;
+mod download;
+mod encryption;
+mod enforce;
+mod errors;
+mod etag;
+mod failover;
+mod failures;
+mod file_meta;
+mod grep;
+mod history;
+mod hooks;
+mod http;
+mod journal;
+mod key;
+mod kv;
+mod location;
+mod lock;
+mod integrity;
+mod inventory;
+mod lines;
+mod listing;
+mod manifest;
+mod mapped;
+mod meta;
+mod metrics;
+mod migrate;
+mod mirror;
+mod ownership;
+mod payer;
+mod prefix_tree;
+mod presign_post;
+mod preview;
+mod progress;
+mod prometheus;
+mod proxy;
+mod put_manifest;
+mod query;
+mod region;
+mod region_cache;
+mod report;
+mod resume;
+mod retry;
+mod secrets;
+mod sigv2;
+mod sigv4;
+mod stop;
+mod summary;
+mod sync;
+mod template;
+mod tiering;
+mod tree;
+mod upload;
+mod usage;
+mod watch;
+mod website;
 
 #[derive(Subcommand, Clone, Debug)]
 enum Commands {
-    ListFiles,
+    ListFiles {
+        #[command(flatten)]
+        options: listing::LsOptions,
+    },
     Ls {
+        #[arg(allow_hyphen_values = true)]
         prefix: String,
+        #[command(flatten)]
+        options: listing::LsOptions,
     },
     ListVersions {
+        #[arg(allow_hyphen_values = true)]
         name: String,
+        #[arg(long, value_name = "RFC3339", value_parser = manifest::parse_time, help = "only versions modified at or after this time")]
+        since: Option<DateTime>,
+        #[arg(long, value_name = "RFC3339", value_parser = manifest::parse_time, help = "only versions modified at or before this time")]
+        until: Option<DateTime>,
+        #[arg(long, help = "group versions and delete markers under the owner that wrote them")]
+        by_owner: bool,
+    },
+    Tree {
+        #[arg(allow_hyphen_values = true, default_value = "")]
+        prefix: String,
+        #[arg(long, help = "levels of directories to expand. deeper ones only show totals")]
+        depth: Option<usize>,
+    },
+    VersionAt {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(value_name = "RFC3339", value_parser = manifest::parse_time)]
+        timestamp: DateTime,
     },
     PutVersion {
+        #[arg(allow_hyphen_values = true, help = "key to upload to. {date}, {uuid}, {filename} and {sha256:N} are filled in")]
+        name: String,
+        file_path: String,
+        #[arg(long, help = "content type to store. defaults to a guess from the file extension")]
+        content_type: Option<String>,
+        #[arg(long, value_enum, conflicts_with = "content_encoding", help = "compress the file before upload, setting Content-Encoding. with --encrypt it's recorded in metadata instead")]
+        compress: Option<compression::Compression>,
+        #[arg(long, requires = "key_file", help = "encrypt the body client-side with AES-256-GCM before upload")]
+        encrypt: bool,
+        #[arg(long, value_name = "PATH", help = "file containing a 32 byte key, raw or hex encoded")]
+        key_file: Option<String>,
+        #[arg(long, help = "record the file's mtime and permission bits in object metadata")]
+        preserve: bool,
+        #[arg(long, help = "refuse the upload if any version has the same contents, not just the current one")]
+        dedup_all_versions: bool,
+        #[arg(long, help = "succeed without uploading when a version already has the same contents, reporting a no-op")]
+        skip_unchanged: bool,
+        #[command(flatten)]
+        headers: upload::HeaderArgs,
+    },
+    #[command(about = "store a file under its SHA-256, so the same contents are only ever stored once")]
+    PutCas {
+        file_path: PathBuf,
+        #[arg(long, allow_hyphen_values = true, default_value = "cas/", help = "prefix the content-addressed keys go under")]
+        prefix: String,
+        #[arg(long, help = "content type to store. defaults to a guess from the file extension")]
+        content_type: Option<String>,
+    },
+    #[command(about = "link a logical key to a blob PutCas stored, counting it as a reference")]
+    Link {
+        #[arg(help = "the SHA-256 PutCas stored the blob under")]
+        hash: String,
+        logical_key: String,
+        #[arg(long, allow_hyphen_values = true, default_value = "cas/", help = "prefix the content-addressed keys go under")]
+        prefix: String,
+    },
+    #[command(about = "remove a logical key's link, leaving its blob for CasGc once nothing links to it")]
+    Unlink {
+        logical_key: String,
+        #[arg(long, allow_hyphen_values = true, default_value = "cas/", help = "prefix the content-addressed keys go under")]
+        prefix: String,
+    },
+    #[command(about = "delete the blobs under the CAS prefix that nothing links to")]
+    CasGc {
+        #[arg(long, allow_hyphen_values = true, default_value = "cas/", help = "prefix the content-addressed keys go under")]
+        prefix: String,
+        #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = stop::parse_duration, help = "keep unlinked blobs newer than this, which PutCas may have just stored for a Link to follow")]
+        min_age: Duration,
+        #[arg(long, help = "list the blobs that would be deleted without deleting them")]
+        dry_run: bool,
+    },
+    #[command(about = "store a large file as chunks and a manifest, uploading only the chunks that changed since the last put")]
+    ChunkedPut {
+        file_path: PathBuf,
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(long, default_value_t = 64 * 1024 * 1024, value_name = "BYTES", help = "bytes in each chunk, or the average with --content-defined")]
+        chunk_size: u64,
+        #[arg(long, help = "cut chunks where the contents say to rather than every --chunk-size bytes, so inserting or deleting bytes only changes the chunks around them")]
+        content_defined: bool,
+        #[arg(long, default_value_t = 4, help = "chunks to upload at once")]
+        concurrency: usize,
+    },
+    #[command(about = "reassemble a file stored with ChunkedPut")]
+    ChunkedGet {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        file_path: PathBuf,
+        #[arg(long, help = "version of the manifest to reassemble. defaults to the latest")]
+        version_id: Option<String>,
+        #[arg(long, default_value_t = 4, help = "chunks to download at once")]
+        concurrency: usize,
+    },
+    #[command(about = "add a file or stdin to the end of an object, copying the existing bytes server-side instead of uploading them again")]
+    Append {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(required_unless_present = "stdin", conflicts_with = "stdin")]
+        file_path: Option<PathBuf>,
+        #[arg(long, help = "append what's read from stdin")]
+        stdin: bool,
+    },
+    #[command(about = "print the lines of the objects under a prefix that match a regular expression, as key:line")]
+    Grep {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        pattern: String,
+        #[arg(long, value_name = "GLOB", help = "only search objects whose name matches this, such as '*.log'. a glob with a '/' matches the key below the prefix. may be repeated")]
+        include: Vec<String>,
+        #[arg(short = 'i', long, help = "match regardless of case")]
+        ignore_case: bool,
+        #[arg(long, help = "also decompress objects whose keys end in .gz, not just those stored with a Content-Encoding")]
+        gunzip: bool,
+        #[arg(long, default_value_t = 4, help = "objects to search at once")]
+        concurrency: usize,
+    },
+    #[command(about = "show the start of an object as text, or as a hexdump if it's binary")]
+    Preview {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(long, default_value_t = 4, value_name = "KB", help = "KiB to fetch from the start of the object")]
+        kb: u64,
+        #[arg(long, help = "show a hexdump even if the object is text")]
+        hex: bool,
+        #[arg(long, help = "version to show. defaults to the latest")]
+        version_id: Option<String>,
+    },
+    #[command(about = "print the first lines of an object, fetching only the bytes they're in")]
+    HeadLines {
+        #[arg(allow_hyphen_values = true)]
         name: String,
+        #[arg(short = 'n', long = "lines", default_value_t = 10, help = "lines to print")]
+        count: usize,
+        #[arg(long, help = "version to read. defaults to the latest")]
+        version_id: Option<String>,
+    },
+    #[command(about = "print the last lines of an object, fetching only the bytes they're in")]
+    TailLines {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(short = 'n', long = "lines", default_value_t = 10, help = "lines to print")]
+        count: usize,
+        #[arg(long, help = "version to read. defaults to the latest")]
+        version_id: Option<String>,
+    },
+    Get {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(required_unless_present = "jq", help = "file to write to. with --jq, defaults to printing what it selects")]
+        file_path: Option<String>,
+        #[arg(long, help = "version to download. defaults to the latest")]
+        version_id: Option<String>,
+        #[arg(long, help = "write the body as stored, even if Content-Encoding says it is compressed")]
+        no_decompress: bool,
+        #[arg(long, value_name = "PATH", help = "key used to decrypt client-side encrypted objects")]
+        key_file: Option<String>,
+        #[arg(long, help = "restore the mtime and permission bits recorded at upload")]
+        preserve: bool,
+        #[arg(long, default_value_t = 4, help = "ranged GETs to run at once for objects larger than --part-size")]
+        concurrency: usize,
+        #[arg(long, default_value_t = 8 * 1024 * 1024, value_name = "BYTES", help = "bytes fetched by each ranged GET")]
+        part_size: u64,
+        #[arg(long, value_name = "QUERY", value_parser = query::parse, help = "parse the object as JSON and keep only what this jq-style path selects, such as .db.hosts[0] or .items[].id")]
+        jq: Option<query::Query>,
+        #[arg(long, requires = "jq", help = "with --jq, write selected strings without quotes")]
+        raw_output: bool,
+    },
+    GetAllVersions {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(help = "directory to write name.v001_<version id> files and name.versions.csv to")]
+        dir: String,
+    },
+    Verify {
         file_path: String,
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(long = "part-size", value_name = "BYTES", help = "part size to try first if the object was uploaded in parts. may be repeated")]
+        part_sizes: Vec<u64>,
+    },
+    ChecksumInfo {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+    },
+    Rechecksum {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(value_enum)]
+        algorithm: integrity::Algorithm,
+    },
+    GetObjectAcl {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(long, help = "version to show. defaults to the latest")]
+        version_id: Option<String>,
+    },
+    PutObjectAcl {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[arg(long, help = "version to change. defaults to the latest")]
+        version_id: Option<String>,
+        #[arg(long, value_parser = acl::canned_acl_parser(), required_unless_present = "grants", help = "canned ACL to replace the object's ACL with")]
+        canned_acl: Option<String>,
+        #[arg(long = "grant", value_name = "PERMISSION=TYPE=VALUE", value_parser = acl::parse_grant, conflicts_with = "canned_acl",
+              help = "a grant to replace the ACL with, e.g. read=uri=http://acs.amazonaws.com/groups/global/AllUsers. permission is read, read-acp, write-acp or full-control; type is id, uri or emailAddress. may be repeated")]
+        grants: Vec<acl::Grant>,
     },
     DeleteVersion {
+        #[arg(allow_hyphen_values = true)]
         name: String,
         version: String,
+        #[arg(long, help = "succeed when the key or version is already gone, reporting a no-op")]
+        ignore_missing: bool,
+    },
+    DeleteVersions {
+        #[arg(allow_hyphen_values = true)]
+        name: String,
+        #[command(flatten)]
+        filter: history::VersionFilter,
+        #[arg(long, help = "print the versions that would be deleted without deleting them")]
+        dry_run: bool,
     },
     CopyObject {
+        #[arg(allow_hyphen_values = true)]
         source: String,
+        #[arg(allow_hyphen_values = true)]
         dest: String,
+        #[command(flatten)]
+        options: copy::CopyArgs,
+    },
+    Report {
+        #[arg(long, default_value_t = 10, help = "number of largest objects to show")]
+        top: usize,
+        #[arg(long, help = "show bytes per top-level prefix")]
+        by_prefix: bool,
+        #[arg(long, help = "include noncurrent versions and delete markers")]
+        include_versions: bool,
+    },
+    CostEstimate {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        #[arg(long, value_enum, default_value = "aws", help = "built-in price table to use")]
+        provider: cost::Provider,
+        #[arg(long, value_name = "FILE", help = "price table file of '<storage class> <price per GiB-month>' lines")]
+        price_table: Option<String>,
+    },
+    Archive {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        #[arg(help = "tar file to write, gzipped if it ends in .gz or .tgz")]
+        output: String,
+    },
+    Unarchive {
+        #[arg(help = "tar file to read, gzipped if it ends in .gz or .tgz")]
+        archive: String,
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+    },
+    UploadDir {
+        local_dir: String,
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        #[command(flatten)]
+        symlinks: tree::SymlinkArgs,
+        #[arg(long, help = "record each file's mtime and permission bits in object metadata")]
+        preserve: bool,
+        #[arg(long, value_name = "PATH", help = "write failed uploads here as JSON lines, for Retry")]
+        failures_out: Option<PathBuf>,
+        #[command(flatten)]
+        headers: upload::HeaderArgs,
+    },
+    PutManifest {
+        #[arg(help = "CSV with path, key and optional content_type and metadata (k=v;k=v) columns, or the same as .json")]
+        manifest: PathBuf,
+        #[arg(long, default_value_t = 4, help = "uploads to run at once")]
+        concurrency: usize,
+        #[arg(long, value_name = "PATH", help = "write each row's result here as CSV")]
+        report: Option<PathBuf>,
+        #[arg(long, value_name = "PATH", help = "write failed uploads here as JSON lines, for Retry")]
+        failures_out: Option<PathBuf>,
+    },
+    Sync {
+        local_dir: String,
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        #[arg(long, value_enum, default_value = "mtime-and-size", help = "how to detect changed files")]
+        compare: sync::Compare,
+        #[command(flatten)]
+        symlinks: tree::SymlinkArgs,
+        #[arg(long, help = "record each file's mtime and permission bits in object metadata")]
+        preserve: bool,
+        #[arg(long, value_name = "PATH", help = "journal of synced files; later runs only examine files changed since")]
+        journal: Option<PathBuf>,
+        #[arg(long, value_name = "PATH", help = "write failed uploads here as JSON lines, for Retry")]
+        failures_out: Option<PathBuf>,
+        #[arg(long = "part-size", value_name = "BYTES", help = "with --compare checksum, a part size to try first for multipart ETags. may be repeated")]
+        part_sizes: Vec<u64>,
+        #[arg(long, value_name = "BYTES", help = "store files larger than this as content-defined chunks, as ChunkedPut --content-defined does, so changes only upload the chunks that changed. --preserve doesn't apply to them")]
+        chunked_over: Option<u64>,
+        #[arg(long, default_value_t = 8 * 1024 * 1024, value_name = "BYTES", help = "average chunk size for files over --chunked-over")]
+        chunk_size: u64,
+    },
+    #[command(about = "list a prefix every interval and report the keys added, removed or modified since the last listing")]
+    Watch {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        #[arg(long, value_name = "DURATION", default_value = "30s", value_parser = stop::parse_duration, help = "how often to list the prefix")]
+        interval: Duration,
+        #[arg(long, value_name = "COMMAND", help = "run this with sh for each change, with S3TEST_CHANGE, S3TEST_BUCKET, S3TEST_KEY, S3TEST_SIZE and S3TEST_ETAG set")]
+        exec: Option<String>,
+        #[arg(long, value_name = "ADDR", help = "serve Prometheus metrics at http://ADDR/metrics, such as 127.0.0.1:9900")]
+        metrics_addr: Option<SocketAddr>,
+    },
+    Mirror {
+        local_dir: String,
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        #[command(flatten)]
+        symlinks: tree::SymlinkArgs,
+        #[arg(long, help = "delete objects when their local files are removed")]
+        delete: bool,
+        #[arg(long, default_value_t = 500, value_name = "MS", help = "wait this long after the last change before uploading")]
+        debounce_ms: u64,
+        #[arg(long, help = "record each file's mtime and permission bits in object metadata")]
+        preserve: bool,
+        #[command(flatten)]
+        headers: upload::HeaderArgs,
+        #[arg(long, value_name = "ADDR", help = "serve Prometheus metrics at http://ADDR/metrics, such as 127.0.0.1:9900")]
+        metrics_addr: Option<SocketAddr>,
+    },
+    Website {
+        #[command(subcommand)]
+        action: website::WebsiteAction,
+    },
+    #[command(about = "get, set or delete small values under a prefix, refusing writes that would lose another writer's change")]
+    Kv {
+        #[command(subcommand)]
+        action: kv::KvAction,
+        #[arg(long, allow_hyphen_values = true, default_value = "kv/", help = "prefix the namespaces go under")]
+        prefix: String,
+    },
+    #[command(about = "get, replace or remove the bucket's tags, such as cost-allocation tags")]
+    BucketTags {
+        #[command(subcommand)]
+        action: bucket_tags::TagsAction,
+    },
+    Ownership {
+        #[command(subcommand)]
+        action: ownership::OwnershipAction,
+    },
+    IntelligentTiering {
+        #[command(subcommand)]
+        action: tiering::TieringAction,
+    },
+    Accelerate {
+        #[command(subcommand)]
+        action: accelerate::AccelerateAction,
+    },
+    Metrics {
+        #[command(subcommand)]
+        action: metrics::MetricsAction,
+    },
+    Analytics {
+        #[command(subcommand)]
+        action: metrics::AnalyticsAction,
+    },
+    Publish {
+        local_dir: String,
+        #[arg(allow_hyphen_values = true, default_value = "")]
+        prefix: String,
+        #[arg(long, value_enum, default_value = "checksum", help = "how to detect changed files. site generators tend to rewrite every mtime")]
+        compare: sync::Compare,
+    },
+    Enforce {
+        #[arg(long, value_name = "FILE", help = "TOML retention policy of [[rule]] tables")]
+        policy_file: String,
+        #[arg(long, help = "print what would change without changing anything")]
+        dry_run: bool,
+    },
+    Inventory {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        output: String,
+        #[arg(long, value_enum, default_value = "csv")]
+        format: inventory::Format,
+        #[arg(long, help = "list every version, not just current objects")]
+        include_versions: bool,
+        #[arg(long, value_name = "PATH", help = "save the listing position here after each page, and append from it on the next run")]
+        resume_token_file: Option<PathBuf>,
+    },
+    #[command(about = "write the headers, metadata and tags of every object under a prefix as JSON lines")]
+    MetaExport {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        output: PathBuf,
+        #[arg(long, default_value_t = 4, help = "objects to look up at once")]
+        concurrency: usize,
+    },
+    #[command(about = "re-apply what MetaExport wrote to the same keys, copying each object onto itself")]
+    MetaImport {
+        input: PathBuf,
+        #[arg(long, default_value_t = 4, help = "copies to run at once")]
+        concurrency: usize,
+    },
+    BatchManifest {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        #[command(flatten)]
+        filter: manifest::ManifestFilter,
+        #[arg(short, long, help = "file to write. defaults to stdout")]
+        output: Option<String>,
+    },
+    Rm {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        #[arg(long, help = "permanently delete every version and delete marker")]
+        all_versions: bool,
+        #[arg(long, default_value_t = 4, help = "delete requests to run at once")]
+        concurrency: usize,
+        #[arg(long, value_name = "PATH", help = "write failed deletes here as JSON lines, for Retry")]
+        failures_out: Option<PathBuf>,
+        #[arg(long, help = "count keys and versions that are already gone as skipped, not failed")]
+        ignore_missing: bool,
+    },
+    DownloadPrefix {
+        #[arg(allow_hyphen_values = true)]
+        prefix: String,
+        local_dir: String,
+        #[arg(long, default_value_t = 4, help = "downloads to run at once")]
+        concurrency: usize,
+        #[arg(long, value_name = "PATH", help = "write failed downloads here as JSON lines, for Retry")]
+        failures_out: Option<PathBuf>,
+        #[arg(long, value_name = "PATH", help = "save the listing position here after each page, and start from it on the next run")]
+        resume_token_file: Option<PathBuf>,
+    },
+    Doctor,
+    #[command(about = "stream objects from one profile's endpoint to another's, keeping metadata and tags")]
+    Migrate {
+        #[command(flatten)]
+        args: migrate::MigrateArgs,
+    },
+    #[command(about = "keep a client ready and run the commands --use-daemon sends, one at a time, until interrupted. they run with the daemon's bucket, credentials, endpoint and other options")]
+    Daemon,
+    Configure {
+        #[arg(long, help = "keep the access and secret key in the OS keyring instead of the config file")]
+        store_keyring: bool,
+    },
+    #[command(about = "bytes uploaded and downloaded per day, from the ledger every run adds to")]
+    Usage {
+        #[arg(long, value_name = "WHEN", value_parser = usage::parse_since, help = "only runs since then: a duration back from now such as 7d, or an RFC 3339 time")]
+        since: Option<i64>,
+        #[arg(long, help = "list each run instead of daily totals")]
+        runs: bool,
+        #[arg(long, help = "split the totals by the host the requests went to")]
+        by_host: bool,
+    },
+    BucketLocation,
+    #[command(about = "list the buckets these credentials own, with when they were created")]
+    ListBuckets {
+        #[arg(long, default_value = "", help = "only list buckets whose names start with this")]
+        prefix: String,
+        #[arg(long, help = "also count each bucket's objects and bytes, which lists all of them")]
+        with_usage: bool,
+        #[arg(long, default_value_t = 4, requires = "with_usage", help = "buckets to count at once")]
+        concurrency: usize,
+    },
+    #[command(about = "exits 0 if the bucket exists and is accessible, 3 if access is denied, 4 if it doesn't exist")]
+    BucketExists {
+        name: String,
+    },
+    #[command(about = "delete every version, delete marker and incomplete upload in a bucket, then the bucket")]
+    NukeBucket {
+        name: String,
+        #[arg(long, help = "don't ask for the bucket's name to confirm")]
+        yes: bool,
+    },
+    #[command(about = "create dest if needed, then copy source's versioning, lifecycle, CORS and tags, and its objects")]
+    CloneBucket {
+        source: String,
+        dest: String,
+        #[arg(long, help = "copy every version and delete marker, oldest first, instead of just current objects")]
+        include_versions: bool,
+    },
+    #[command(about = "create a bucket in --region")]
+    CreateBucket {
+        name: String,
+        #[command(flatten)]
+        options: bucket::CreateArgs,
+    },
+    PresignPost {
+        #[arg(allow_hyphen_values = true, help = "prefix uploaded keys must start with. the form's key is this followed by the file's name")]
+        prefix: String,
+        #[arg(long, value_name = "BYTES", help = "largest file the form accepts")]
+        max_size: u64,
+        #[arg(long, value_name = "SECS", default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..=presign_post::MAX_EXPIRES), help = "how long the form can be used for")]
+        expires: u64,
+        #[arg(long, help = "required Content-Type. one ending in / (e.g. image/) allows any type starting with it")]
+        content_type: Option<String>,
+    },
+    DebugSign {
+        #[arg(value_enum)]
+        method: debug_sign::Method,
+        #[arg(allow_hyphen_values = true)]
+        key: String,
+    },
+    Retry {
+        #[arg(help = "JSON lines file written by --failures-out")]
+        failures_file: PathBuf,
+        #[arg(long, value_name = "PATH", help = "write operations that fail again here")]
+        failures_out: Option<PathBuf>,
     },
 }
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    #[arg(short, long, value_name = "BUCKET NAME", help = "bucket name to use. defaults to 'enlighten-server-local'")]
+    #[arg(short, long, global = true, value_name = "BUCKET NAME", help = "bucket name, or access point or Outposts access point ARN, to use. defaults to 'enlighten-server-local'. key arguments of the form s3://BUCKET/KEY use their own bucket")]
     bucket: Option<String>,
 
+    #[arg(long, global = true, value_name = "NAME", help = "config file profile to use. defaults to S3TEST_PROFILE or 'default'")]
+    profile: Option<String>,
+
+    #[arg(long, global = true, value_name = "URL", help = "try this one of the profile's endpoints first")]
+    prefer_endpoint: Option<String>,
+
+    #[arg(long, global = true, value_name = "URL", help = "endpoint to use, overriding ENDPOINT and the config file")]
+    endpoint: Option<String>,
+
+    #[arg(long, global = true, help = "region to use, overriding REGION and the config file")]
+    region: Option<String>,
+
+    #[arg(long, global = true, help = "look up the bucket's region when it isn't in the cache next to the config file, and use that instead of REGION and the config file")]
+    region_per_bucket: bool,
+
+    #[arg(long, global = true, help = "access key to use, overriding ACCESS_KEY, the keyring and the config file")]
+    access_key: Option<String>,
+
+    #[arg(long, global = true, help = "secret key to use with --access-key. visible to other local users in the process list")]
+    secret_key: Option<String>,
+
+    #[arg(long, global = true, conflicts_with_all = ["access_key", "secret_key"], help = "send requests unsigned, for public buckets. no credentials are needed")]
+    no_sign_request: bool,
+
+    #[arg(long, global = true, conflicts_with = "no_sign_request", help = "print a curl command with a presigned URL for Get, PutVersion or DeleteVersion instead of running it")]
+    emit_curl: bool,
+
+    #[arg(long, global = true, value_enum, default_value = "v4", help = "how to sign requests. v2 is for legacy endpoints that don't accept SigV4")]
+    signature_version: sigv2::SignatureVersion,
+
+    #[arg(long, global = true, value_name = "PAYER", value_parser = ["requester"], help = "acknowledge requester-pays charges. ListVersions and other version listings don't support it")]
+    request_payer: Option<String>,
+
+    #[arg(long, global = true, help = "use the S3 Transfer Acceleration endpoint. the bucket needs it enabled, see Accelerate")]
+    accelerate: bool,
+
+    #[arg(long, global = true, value_enum, default_value = "bar", help = "how to show progress. json writes events with the operation, key, counts and rate to stderr, one per line")]
+    progress: progress::ProgressMode,
+
+    #[arg(long, global = true, value_enum, default_value = "text", help = "how Sync, UploadDir, DownloadPrefix and Rm print the summary at the end")]
+    summary: summary::SummaryFormat,
+
+    #[arg(long, global = true, value_name = "POLICY", default_value = "continue", value_parser = failures::ErrorPolicy::parse, help = "what bulk commands do when an object fails: fail-fast stops, continue fails at the end, threshold=N% fails at the end only if more than N% failed")]
+    error_policy: failures::ErrorPolicy,
+
+    #[arg(long, global = true, value_name = "DURATION", value_parser = stop::parse_duration, help = "stop after this long, such as 30m. bulk commands stop cleanly, saving journals and failure logs")]
+    deadline: Option<Duration>,
+
+    #[command(flatten)]
+    locking: lock::LockArgs,
+
+    #[arg(long, global = true, help = "use the dual-stack (IPv4 and IPv6) endpoint")]
+    dualstack: bool,
+
+    #[arg(long, global = true, help = "use the FIPS endpoint")]
+    fips: bool,
+
+    #[command(flatten)]
+    http: http::HttpArgs,
+
+    #[command(flatten)]
+    hooks: hooks::HookArgs,
+
+    #[arg(long, global = true, help = "run the command in the daemon on --daemon-socket, which has a client ready, or here if none is running")]
+    use_daemon: bool,
+
+    #[arg(long, global = true, value_name = "PATH", help = "the socket Daemon listens on and --use-daemon connects to. defaults to daemon.sock next to the config file")]
+    daemon_socket: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() {
+    let result = run().await;
+    hooks::finished(result.as_ref().err().map(|err| errors::ErrorDetails::from_error(err.as_ref()).to_string()).as_deref()).await;
+    usage::record();
+    if let Err(err) = result {
+        // the command has already said why
+        if let Some(Exit(status)) = err.downcast_ref() {
+            process::exit(*status);
+        }
+        eprintln!("error: {}", errors::ErrorDetails::from_error(err.as_ref()));
+        process::exit(stop::error_status());
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
 
     let args = Args::parse();
-    let bucket_name;
-    if args.bucket.is_some() {
-        bucket_name = args.bucket.as_ref().unwrap().to_string();
-    } else {
-        bucket_name = env::var("BUCKET_NAME").expect("must specify BUCKET_NAME");
+    // the daemon's client is already set up, so none is made here
+    #[cfg(unix)]
+    if args.use_daemon && !matches!(args.command, Some(Commands::Daemon)) {
+        // the daemon runs the hooks and records the usage, so nothing is left for here to do
+        if let Some(status) = daemon::forward(args.daemon_socket.as_deref())? {
+            return match status {
+                0 => Ok(()),
+                status => Err(Exit(status).into()),
+            };
+        }
+    }
+    stop::handle_interrupts();
+    hooks::set(&args.hooks, &args.http);
+    if let Some(after) = args.deadline {
+        stop::set_deadline(after);
+    }
+    let profile_name = args.profile.clone()
+        .or_else(|| env::var("S3TEST_PROFILE").ok())
+        .unwrap_or_else(|| config::DEFAULT_PROFILE.to_string());
+    // configure is how a missing bucket, region or credentials get set up, so it needs none of them
+    if let Some(Commands::Configure { store_keyring }) = &args.command {
+        return configure::run(&profile_name, &args.http, *store_keyring).await;
+    }
+    if let Some(Commands::Usage { since, runs, by_host }) = &args.command {
+        return usage::report(*since, *runs, *by_host);
+    }
+    if let Some(command) = &args.command {
+        usage::set_command(&command_name(command));
     }
+    let settings = config::Settings::load()?;
+    let profile = settings.profile(&profile_name)?;
+    let bucket_name = match &args.bucket {
+        Some(bucket) => bucket.to_string(),
+        None => env::var("BUCKET_NAME").ok()
+            .or(profile.bucket.clone())
+            // these name their own bucket, so they need no default
+            .or_else(|| match &args.command {
+                Some(Commands::BucketExists { name }) | Some(Commands::CreateBucket { name, .. })
+                | Some(Commands::NukeBucket { name, .. }) | Some(Commands::CloneBucket { source: name, .. }) => Some(name.clone()),
+                // these are about every bucket, or get theirs from other profiles
+                Some(Commands::ListBuckets { .. }) | Some(Commands::Migrate { .. }) => Some(String::new()),
+                _ => None,
+            })
+            .expect("must specify BUCKET_NAME"),
+    };
+    // an alias's endpoint and region win over the environment, since naming it asks for them
+    let alias = settings.aliases.get(&bucket_name).cloned();
+    let bucket_name = settings.bucket(&bucket_name).to_string();
+    let bucket_arn = arn::BucketArn::parse(&bucket_name)?;
 
 
-    let creds = Credentials::from_keys(
-        env::var("ACCESS_KEY").expect("must specify ACCESS_KEY"),
-        env::var("SECRET_KEY").expect("must specify SECRET_KEY"),
-        None);
-    let region= Region::new(env::var("REGION").expect("Must specify REGION"));
-    let config = Config::builder()
-        .credentials_provider(creds)
-        .endpoint_url(env::var("ENDPOINT").expect("must specify ENDPOINT"))
+    progress::set_mode(args.progress);
+    summary::set_format(args.summary);
+    failures::set_policy(args.error_policy);
+    if let Some(payer) = &args.request_payer {
+        payer::set(RequestPayer::from(payer.as_str()));
+    }
+    if args.signature_version == sigv2::SignatureVersion::V2 {
+        if let Some((name, _)) = args.http.headers.iter().find(|(name, _)| sigv2::SIGNED_HEADERS.contains(&name.as_str())) {
+            return Err(format!("--header {} can't be used with --signature-version v2, which signs it", name).into());
+        }
+    }
+    // the source and destination each bring their own endpoint and credentials
+    if let Some(Commands::Migrate { args: migrate_args }) = &args.command {
+        if args.access_key.is_some() || args.secret_key.is_some() || args.no_sign_request {
+            return Err("Migrate signs with the keys of --source-profile and --dest-profile, so it can't take --access-key, --secret-key or --no-sign-request".into());
+        }
+        return migrate::run(&settings, &args.http, migrate_args, args.signature_version, &args.locking).await;
+    }
+
+    // keys saved by configure --store-keyring come first
+    if args.access_key.is_some() != args.secret_key.is_some() {
+        println!("--access-key and --secret-key must be used together");
+        return Err(Exit(1).into());
+    }
+    let creds = if args.no_sign_request {
+        anonymous::placeholder_credentials()
+    } else {
+        // the keyring is only asked when --access-key wasn't given, since it can prompt to be unlocked
+        let keys = args.access_key.clone().zip(args.secret_key.clone()).or_else(|| secrets::load(&profile_name));
+        match (keys, env_var(&["ACCESS_KEY", "AWS_ACCESS_KEY_ID"])) {
+            (Some((access_key, secret_key)), _) => Credentials::from_keys(access_key, secret_key, None),
+            // a session token only goes with keys from the environment
+            (None, Some(access_key)) => Credentials::from_keys(
+                access_key,
+                env_var(&["SECRET_KEY", "AWS_SECRET_ACCESS_KEY"]).expect("must specify SECRET_KEY"),
+                env::var("AWS_SESSION_TOKEN").ok()),
+            (None, None) => Credentials::from_keys(
+                profile.access_key.clone().expect("must specify ACCESS_KEY"),
+                profile.secret_key.clone().expect("must specify SECRET_KEY"),
+                None),
+        }
+    };
+    let mut endpoints = match (args.endpoint.clone().or(alias.as_ref().and_then(|alias| alias.endpoint.clone())), env_var(&["ENDPOINT", "AWS_ENDPOINT_URL"])) {
+        (Some(endpoint), _) | (None, Some(endpoint)) => vec![endpoint],
+        (None, None) => profile.endpoints.clone(),
+    };
+    if let Some(preferred) = &args.prefer_endpoint {
+        let Some(idx) = endpoints.iter().position(|e| e == preferred) else {
+            println!("{} is not one of the profile's endpoints", preferred);
+            return Err(Exit(1).into());
+        };
+        let preferred = endpoints.remove(idx);
+        endpoints.insert(0, preferred);
+    }
+    // the endpoint variants only apply to AWS's own endpoints
+    let endpoint = endpoints.first().cloned();
+    let mut region_cache = args.region_per_bucket.then(|| region_cache::RegionCache::load(endpoint.as_deref()));
+    let cached_region = region_cache.as_ref().and_then(|cache| cache.get(&bucket_name)).map(|region| region.to_string());
+    let region = Region::new(args.region.clone()
+        .or(cached_region.clone())
+        .or(alias.as_ref().and_then(|alias| alias.region.clone()))
+        .or(env_var(&["REGION", "AWS_REGION"]))
+        .or(profile.region.clone())
+        .or(bucket_arn.as_ref().map(|arn| arn.region.clone()))
+        // the lookup has to be signed for some region
+        .or(args.region_per_bucket.then(|| "us-east-1".to_string()))
+        .expect("Must specify REGION"));
+    if endpoint.is_some() && (args.accelerate || args.dualstack || args.fips) {
+        println!("--accelerate, --dualstack and --fips can't be used with a custom endpoint");
+        return Err(Exit(1).into());
+    }
+    let credentials = SharedCredentialsProvider::new(creds);
+    let mut connector = usage::Metered::wrap(http::connector(&args.http)?);
+    let metrics_addr = match &args.command {
+        Some(Commands::Mirror { metrics_addr, .. }) | Some(Commands::Watch { metrics_addr, .. }) => *metrics_addr,
+        _ => None,
+    };
+    if let Some(addr) = metrics_addr {
+        prometheus::serve(addr).await?;
+        connector = prometheus::Counted::wrap(connector);
+    }
+    // innermost, so the request is signed as finally addressed and dated
+    if args.signature_version == sigv2::SignatureVersion::V2 {
+        connector = sigv2::SigV2::wrap(connector, credentials.clone(), &endpoints);
+    }
+    let mut connector = clock::SkewCorrection::wrap(
+        failover::Failover::wrap(connector, &endpoints, credentials.clone())?,
+        credentials.clone());
+    // outermost, so the connectors that re-sign see unsigned requests and leave them alone
+    if args.no_sign_request {
+        connector = anonymous::Unsigned::wrap(connector);
+    }
+    let mut builder = Config::builder()
+        .credentials_provider(credentials.clone())
         .region(region)
-        .build();
-    let client = Client::from_conf(config);
-
-    //make sure versioning is enabled
-    let v_res = client.get_bucket_versioning()
-        .bucket(bucket_name.clone())
-        .send()
-        .await?;
-    if v_res.status.is_none() || *v_res.status().unwrap() != Enabled {
-        println!("versioning not enabled");
-        process::exit(1);
-    }
-
-    match &args.command {
-        None => {
-            println!("no command specified");
-            process::exit(1);
-        }
-        Some(Commands::ListFiles) => {
-            let result = client.list_objects_v2()
-                .bucket(bucket_name.clone())
-                .send()
-                .await?;
-            display_object_list(result);
+        .accelerate(args.accelerate)
+        .use_dual_stack(args.dualstack)
+        .use_fips(args.fips)
+        // requests to an access point are signed for the ARN's region, whatever REGION says
+        .use_arn_region(bucket_arn.is_some())
+        .http_connector(connector);
+    builder.set_endpoint_url(endpoint);
+    builder.set_app_name(args.http.user_agent_suffix.clone());
+    // nothing may be sent for DebugSign, including the versioning check
+    if let Some(Commands::DebugSign { method, key }) = &args.command {
+        let (bucket, key) = location::split(key, &bucket_name, &settings);
+        key::validate(key)?;
+        return debug_sign::run(builder, bucket, *method, key).await;
+    }
+    let mut client = Client::from_conf(builder.clone().build());
+    // an explicit --region wins, and there's nothing to look up for ARNs or commands that create or list buckets
+    let lookup = !matches!(args.command, Some(Commands::CreateBucket { .. }) | Some(Commands::ListBuckets { .. }));
+    if let Some(cache) = region_cache.as_mut().filter(|_| lookup && args.region.is_none() && cached_region.is_none() && bucket_arn.is_none()) {
+        match region::lookup(&client, &bucket_name).await {
+            Ok(found) => {
+                if client.conf().region().map(|r| r.as_ref()) != Some(found.as_str()) {
+                    client = Client::from_conf(builder.clone().region(Region::new(found.clone())).build());
+                }
+                if let Err(err) = cache.save(&bucket_name, &found) {
+                    eprintln!("note: couldn't save the region of {}: {}", bucket_name, err);
+                }
+            }
+            Err(err) => eprintln!("note: couldn't look up the region of {}: {}", bucket_name, errors::ErrorDetails::from_error(err.as_ref())),
         }
-        Some(Commands::Ls { prefix} ) => {
-            let result = client.list_objects_v2()
-                .bucket(bucket_name.clone())
-                .prefix(prefix.clone())
-                .send()
-                .await?;
-            display_object_list(result);
-        }
-        Some(Commands::ListVersions { name}) => {
-            let ver_result = client.list_object_versions()
-                .bucket(bucket_name.clone())
-                .set_prefix(Some(name.clone()))
-                .send().await?;
-            if let Some(versions) = ver_result.versions {
-                for version in versions {
-                    let str = version.e_tag().unwrap().to_string().to_ascii_lowercase();
-                    println!("version: {}: {} ({})", version.version_id().unwrap(), version.size(), &str[1..str.len()-1]);
+    }
+    // presigning is done locally, so there's no need to check the bucket first
+    if args.emit_curl {
+        match &args.command {
+            Some(Commands::Get { name, file_path, version_id, .. }) => {
+                let (bucket, name) = location::split(name, &bucket_name, &settings);
+                key::validate(name)?;
+                // curl can't apply --jq, so it writes the whole object to stdout
+                let operation = curl::Operation::Get { key: name, version_id: version_id.as_deref(), file_path: file_path.as_deref().unwrap_or("-") };
+                return curl::emit(&client, bucket, operation).await;
+            }
+            Some(Commands::PutVersion { name, file_path, content_type, compress: None, encrypt: false, headers, .. }) => {
+                let (bucket, pattern) = location::split(name, &bucket_name, &settings);
+                let name = template::expand(pattern, file_path, || Ok(checksum::Sha256::of(&std::fs::read(file_path)?).hex()))?;
+                key::validate(&name)?;
+                let content_type = match content_type {
+                    Some(content_type) => content_type.clone(),
+                    None => mime_guess::from_path(file_path).first_or_octet_stream().to_string(),
+                };
+                let operation = curl::Operation::Put { key: &name, file_path, content_type: &content_type, headers };
+                return curl::emit(&client, bucket, operation).await;
+            }
+            Some(Commands::PutVersion { .. }) => {
+                println!("--emit-curl can't compress or encrypt uploads");
+                return Err(Exit(1).into());
+            }
+            Some(Commands::DeleteVersion { name, version, .. }) => {
+                let (bucket, name) = location::split(name, &bucket_name, &settings);
+                key::validate(name)?;
+                return curl::emit(&client, bucket, curl::Operation::Delete { key: name, version_id: version }).await;
+            }
+            _ => {
+                println!("--emit-curl only works with Get, PutVersion and DeleteVersion");
+                return Err(Exit(1).into());
+            }
+        }
+    }
+    if let Some(Commands::Doctor) = &args.command {
+        return doctor::run(&client, &bucket_name).await;
+    }
+    // the bucket may well not exist for these
+    if let Some(Commands::BucketExists { name }) = &args.command {
+        return match bucket::exists(&client, settings.bucket(name)).await? {
+            0 => Ok(()),
+            status => Err(Exit(status).into()),
+        };
+    }
+    if let Some(Commands::CreateBucket { name, options }) = &args.command {
+        return bucket::create(&client, settings.bucket(name), options).await;
+    }
+    // and for this one, it needn't be versioned
+    if let Some(Commands::NukeBucket { name, yes }) = &args.command {
+        return bucket::nuke(&client, settings.bucket(name), *yes).await;
+    }
+    if let Some(Commands::CloneBucket { source, dest, include_versions }) = &args.command {
+        return clone_bucket::run(&client, settings.bucket(source), settings.bucket(dest), *include_versions).await;
+    }
+    if let Some(Commands::ListBuckets { prefix, with_usage, concurrency }) = &args.command {
+        return bucket::list(&client, &builder, prefix, *with_usage, *concurrency).await;
+    }
+    if let Some(Commands::PresignPost { prefix, max_size, expires, content_type }) = &args.command {
+        let (bucket, prefix) = location::split(prefix, &bucket_name, &settings);
+        return presign_post::run(&client, bucket, prefix, *max_size, *expires, content_type.as_deref()).await;
+    }
+
+    //make sure versioning is enabled. access points don't offer GetBucketVersioning
+    if let Some(arn) = &bucket_arn {
+        eprintln!("note: versioning can't be checked through {}", arn);
+    } else {
+        let v_res = match client.get_bucket_versioning().bucket(bucket_name.clone()).send().await {
+            Ok(result) => result,
+            Err(err) => {
+                // the first request doubles as a check that REGION is right
+                let Some(region) = region::redirect_hint(&err, client.conf().region()) else { return Err(err.into()) };
+                eprintln!("note: {} is in {}, not {}. retrying there", bucket_name, region,
+                          client.conf().region().map(|r| r.to_string()).unwrap_or_default());
+                // a cached region is stale once the bucket has moved
+                if let Some(cache) = region_cache.as_mut() {
+                    if let Err(err) = cache.save(&bucket_name, region.as_ref()) {
+                        eprintln!("note: couldn't save the region of {}: {}", bucket_name, err);
+                    }
                 }
+                client = Client::from_conf(builder.region(region).build());
+                client.get_bucket_versioning()
+                    .bucket(bucket_name.clone())
+                    .send()
+                    .await?
+            }
+        };
+        if v_res.status.is_none() || *v_res.status().unwrap() != Enabled {
+            println!("versioning not enabled");
+            return Err(Exit(1).into());
+        }
+    }
+    // released when this returns, however it returns
+    let _lock = args.locking.acquire(&client, &bucket_name).await?;
+
+    let Some(command) = &args.command else {
+        println!("no command specified");
+        return Err(Exit(1).into());
+    };
+    if let Commands::Daemon = command {
+        #[cfg(unix)]
+        return serve_daemon(&client, &bucket_name, &settings, args.daemon_socket.as_deref()).await;
+        #[cfg(not(unix))]
+        return Err("the daemon needs unix domain sockets".into());
+    }
+    dispatch(command, &client, &bucket_name, &settings).await
+}
+
+/// runs the commands --use-daemon sends, one at a time so each can have the process's stdout,
+/// with this run's client. a client that goes away, as when it's interrupted, stops its command
+#[cfg(unix)]
+async fn serve_daemon(client: &Client, bucket_name: &str, settings: &config::Settings, socket: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    use std::panic::AssertUnwindSafe;
+    use clap::CommandFactory;
+    use futures_util::FutureExt;
+
+    let own = Args::command().get_matches();
+    let listener = daemon::Listener::bind(socket)?;
+    println!("listening on {}", listener.path().display());
+    // what starting up sent, so the first command's line doesn't include it
+    usage::record();
+    loop {
+        let request = stop::within(listener.accept()).await??;
+        let attached = match request.attach() {
+            Ok(attached) => attached,
+            Err(err) => {
+                eprintln!("daemon: can't use the client's stdio: {}", err);
+                continue;
+            }
+        };
+        let status = tokio::select! {
+            // a panic, such as writing to a closed pipe, only ends its own command
+            result = AssertUnwindSafe(daemon_command(&own, request.args(), client, bucket_name, settings)).catch_unwind() => result.unwrap_or(101),
+            _ = request.hung_up() => 130,
+        };
+        // however the command ended, its traffic is its own
+        usage::record();
+        usage::set_command("Daemon");
+        drop(attached);
+        request.finish(status).await;
+    }
+}
+
+/// runs a command line a client sent and returns its exit status. the daemon's options were set
+/// up when it started, so one the command line sets to something else is refused rather than ignored
+#[cfg(unix)]
+async fn daemon_command(own: &clap::ArgMatches, argv: &[std::ffi::OsString], client: &Client, bucket_name: &str, settings: &config::Settings) -> i32 {
+    use clap::{ArgMatches, CommandFactory, FromArgMatches};
+    use clap::parser::ValueSource;
+
+    fn raw(matches: &ArgMatches, id: &str) -> Option<Vec<std::ffi::OsString>> {
+        matches.get_raw(id).map(|values| values.map(|value| value.to_os_string()).collect())
+    }
+    let parsed = Args::command().try_get_matches_from(argv).and_then(|matches| Ok((Args::from_arg_matches(&matches)?, matches)));
+    let (args, matches) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            let _ = err.print();
+            return err.exit_code();
+        }
+    };
+    let changed = Args::command().get_arguments()
+        .filter(|arg| !matches!(arg.get_id().as_str(), "use_daemon" | "daemon_socket"))
+        .find(|arg| {
+            let id = arg.get_id().as_str();
+            matches.value_source(id) == Some(ValueSource::CommandLine) && raw(&matches, id) != raw(own, id)
+        })
+        .map(|arg| arg.get_long().unwrap_or(arg.get_id().as_str()).to_string());
+    if let Some(changed) = changed {
+        eprintln!("error: --{} was set when the daemon started. restart it to change it", changed);
+        return 2;
+    }
+    let Some(command) = &args.command else {
+        println!("no command specified");
+        return 1;
+    };
+    if matches!(command, Commands::DebugSign { .. } | Commands::Doctor | Commands::PresignPost { .. } | Commands::BucketExists { .. } | Commands::CreateBucket { .. } | Commands::NukeBucket { .. } | Commands::CloneBucket { .. } | Commands::ListBuckets { .. } | Commands::Migrate { .. } | Commands::Configure { .. } | Commands::Usage { .. } | Commands::Daemon) {
+        eprintln!("error: {} can't be run through the daemon", command_name(command));
+        return 2;
+    }
+    // the hooks are the daemon's, which the command line can't have changed
+    usage::set_command(&command_name(command));
+    let result = dispatch(command, client, bucket_name, settings).await;
+    hooks::finished(result.as_ref().err().map(|err| errors::ErrorDetails::from_error(err.as_ref()).to_string()).as_deref()).await;
+    match result {
+        Ok(()) => 0,
+        Err(err) => match err.downcast_ref() {
+            Some(Exit(status)) => *status,
+            None => {
+                eprintln!("error: {}", errors::ErrorDetails::from_error(err.as_ref()));
+                stop::error_status()
             }
+        },
+    }
+}
+
+/// a command's variant name, such as MetaExport
+fn command_name(command: &Commands) -> String {
+    let name = format!("{:?}", command);
+    name.split(|c: char| !c.is_alphanumeric()).next().unwrap_or_default().to_string()
+}
+
+/// runs the commands that need a versioned bucket, once it's been checked and any lock taken
+async fn dispatch(command: &Commands, client: &Client, bucket_name: &str, settings: &config::Settings) -> Result<(), Box<dyn Error>> {
+    match command {
+        Commands::ListFiles { options } => {
+            listing::ls(client, bucket_name, "", options).await?;
+        }
+        Commands::Ls { prefix, options } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            listing::ls(client, bucket, prefix, options).await?;
         }
-        Some(Commands::PutVersion { name, file_path }) => {
-            let bytes = tokio::fs::read(file_path).await?;
-            let hash = format!("{:x}", md5::Md5::digest(&bytes));
-            let exist = get_version_for_hash(&client, &name, &hash, &bucket_name).await?;
+        Commands::Tree { prefix, depth } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            prefix_tree::run(client, bucket, prefix, *depth).await?;
+        }
+        Commands::ListVersions { name, since, until, by_owner } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            history::list_versions(client, bucket, name, *since, *until, *by_owner).await?;
+        }
+        Commands::VersionAt { name, timestamp } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            history::version_at(client, bucket, name, *timestamp).await?;
+        }
+        Commands::PutVersion { name, file_path, content_type, compress, encrypt, key_file, preserve, dedup_all_versions, skip_unchanged, headers } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            let mut nonce = None;
+            // a file uploaded as is is hashed and sent straight from its mapping
+            let bytes = if compress.is_none() && !*encrypt {
+                mapped::read(Path::new(file_path)).await?
+            } else {
+                let mut bytes = tokio::fs::read(file_path).await?;
+                if let Some(compression) = compress {
+                    bytes = compression.compress(&bytes)?;
+                }
+                if *encrypt {
+                    let key = encryption::read_key(key_file.as_ref().unwrap())?;
+                    let (encrypted, nonce_hex) = encryption::encrypt(&key, bytes)?;
+                    bytes = encrypted;
+                    nonce = Some(nonce_hex);
+                }
+                bytes.into()
+            };
+            let checksum = checksum::Sha256::of(&bytes);
+            let pattern = name;
+            let name = template::expand(pattern, file_path, || {
+                // the body is only the file itself when it's uploaded as is
+                if compress.is_none() && !*encrypt {
+                    Ok(checksum.hex())
+                } else {
+                    Ok(checksum::Sha256::of(&std::fs::read(file_path)?).hex())
+                }
+            })?;
+            let name = name.as_str();
+            key::validate(name)?;
+            if name != pattern {
+                println!("uploading to {}", name);
+            }
+            let exist = dedup::find_duplicate(client, bucket, name, &bytes, &checksum, *dedup_all_versions).await?;
             if let Some(ver) = exist {
                 println!("version already exists: {}", ver);
-                process::exit(1);
+                if !*skip_unchanged {
+                    return Err(Exit(1).into());
+                }
+                summary::report("put", name, Outcome::NoOp);
+                return Ok(());
             }
-            let result = client.put_object()
-                .bucket(bucket_name.clone())
+            let content_type = match content_type {
+                Some(content_type) => content_type.clone(),
+                None => mime_guess::from_path(file_path).first_or_octet_stream().to_string(),
+            };
+            let request = client.put_object()
+                .bucket(bucket)
+                .set_request_payer(payer::get())
                 .key(name)
-                .checksum_algorithm(ChecksumAlgorithm::Sha256)
-                .body(ByteStream::from(bytes))
+                .content_type(content_type)
+                .checksum_sha256(checksum.base64())
+                .body(bytes.body(0..bytes.len()).await?);
+            let mut request = headers.apply(request);
+            if let Some(compression) = compress {
+                // what's stored is ciphertext when it's encrypted, which nothing should decompress as is
+                request = match nonce {
+                    Some(_) => request.metadata(compression::METADATA_KEY, compression.content_encoding()),
+                    None => request.content_encoding(compression.content_encoding()),
+                };
+            }
+            if let Some(nonce) = nonce {
+                request = request
+                    .metadata(encryption::ALGORITHM_KEY, encryption::ALGORITHM)
+                    .metadata(encryption::NONCE_KEY, nonce);
+            }
+            if *preserve {
+                for (key, value) in file_meta::capture(Path::new(file_path))? {
+                    request = request.metadata(key, value);
+                }
+            }
+            let result = checksum.sign_with(request.customize().await?).send().await?;
+            println!("put version: {}", result.version_id().unwrap());
+            summary::report("put", name, Outcome::Changed);
+        }
+        Commands::PutCas { file_path, prefix, content_type } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            cas::put(client, bucket, prefix, file_path, content_type.as_deref()).await?;
+        }
+        Commands::Link { hash, logical_key, prefix } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            cas::link(client, bucket, prefix, hash, logical_key).await?;
+        }
+        Commands::Unlink { logical_key, prefix } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            cas::unlink(client, bucket, prefix, logical_key).await?;
+        }
+        Commands::CasGc { prefix, min_age, dry_run } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            cas::gc(client, bucket, prefix, *min_age, *dry_run).await?;
+        }
+        Commands::ChunkedPut { file_path, name, chunk_size, content_defined, concurrency } => {
+            if *chunk_size == 0 {
+                println!("--chunk-size must be more than zero");
+                return Err(Exit(1).into());
+            }
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            let chunking = if *content_defined { chunked::Chunking::ContentDefined(*chunk_size) } else { chunked::Chunking::Fixed(*chunk_size) };
+            chunked::put(client, bucket, file_path, name, chunking, *concurrency).await?;
+        }
+        Commands::ChunkedGet { name, file_path, version_id, concurrency } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            chunked::get(client, bucket, name, version_id.as_deref(), file_path, *concurrency).await?;
+        }
+        Commands::Append { name, file_path, stdin: _ } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            let source = match file_path {
+                Some(path) => append::Source::File(path),
+                None => append::Source::Stdin,
+            };
+            append::append(client, bucket, name, source).await?;
+        }
+        Commands::Grep { prefix, pattern, include, ignore_case, gunzip, concurrency } => {
+            let pattern = match regex::bytes::RegexBuilder::new(pattern).case_insensitive(*ignore_case).build() {
+                Ok(pattern) => pattern,
+                Err(err) => {
+                    println!("invalid pattern: {}", err);
+                    return Err(Exit(1).into());
+                }
+            };
+            let options = grep::GrepOptions { include: include.clone(), concurrency: *concurrency, gunzip: *gunzip };
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            // like grep, finding nothing is a failure
+            if !grep::grep(client, bucket, prefix, &pattern, &options).await? {
+                return Err(Exit(1).into());
+            }
+        }
+        Commands::Preview { name, kb, hex, version_id } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            preview::preview(client, bucket, name, version_id.as_deref(), kb * 1024, *hex).await?;
+        }
+        Commands::HeadLines { name, count, version_id } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            lines::head_lines(client, bucket, name, version_id.as_deref(), *count).await?;
+        }
+        Commands::TailLines { name, count, version_id } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            lines::tail_lines(client, bucket, name, version_id.as_deref(), *count).await?;
+        }
+        Commands::Get { name, file_path, version_id, no_decompress, key_file, preserve, concurrency, part_size, jq, raw_output } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            if let (Some(file_path), true, None) = (file_path, *concurrency > 1, jq) {
+                let head = client.head_object()
+                    .bucket(bucket)
+                    .set_request_payer(payer::get())
+                    .key(name)
+                    .set_version_id(version_id.clone())
+                    .send()
+                    .await?;
+                if head.content_length() as u64 > *part_size && download::is_plain(&head, *no_decompress) {
+                    let options = download::RangedOptions { part_size: *part_size, concurrency: *concurrency };
+                    let bytes = download::download_ranged(client, bucket, name, &head, Path::new(file_path), &options).await?;
+                    if *preserve {
+                        file_meta::restore(Path::new(file_path), &head.metadata().cloned().unwrap_or_default())?;
+                    }
+                    println!("got version: {} ({} bytes)", head.version_id().unwrap_or("null"), bytes);
+                    return Ok(());
+                }
+            }
+            let result = client.get_object()
+                .bucket(bucket)
+                .set_request_payer(payer::get())
+                .key(name)
+                .set_version_id(version_id.clone())
                 .send()
                 .await?;
-            println!("put version: {}", result.version_id().unwrap());
+            let version = result.version_id().unwrap_or("null").to_string();
+            let metadata = result.metadata().cloned().unwrap_or_default();
+            let compression = compression::Compression::of_object(result.content_encoding(), &metadata);
+            let mut bytes = result.body.collect().await?.into_bytes().to_vec();
+            if let Some(algorithm) = metadata.get(encryption::ALGORITHM_KEY) {
+                if algorithm != encryption::ALGORITHM {
+                    println!("unsupported encryption algorithm: {}", algorithm);
+                    return Err(Exit(1).into());
+                }
+                let Some(key_file) = key_file else {
+                    println!("object is encrypted, specify --key-file");
+                    return Err(Exit(1).into());
+                };
+                let key = encryption::read_key(key_file)?;
+                let nonce = metadata.get(encryption::NONCE_KEY).ok_or("object is missing its encryption nonce")?;
+                bytes = encryption::decrypt(&key, bytes, nonce)?;
+            }
+            if let (Some(compression), false) = (compression, *no_decompress) {
+                bytes = compression.decompress(&bytes)?;
+            }
+            if let Some(query) = jq {
+                let value: serde_json::Value = serde_json::from_slice(&bytes).map_err(|err| format!("{} isn't JSON: {}", name, err))?;
+                let text = query::render(&query.run(&value)?, *raw_output)?;
+                match file_path {
+                    Some(file_path) => tokio::fs::write(file_path, &text).await?,
+                    None => print!("{}", text),
+                }
+                return Ok(());
+            }
+            let Some(file_path) = file_path else { unreachable!("required without --jq") };
+            tokio::fs::write(file_path, &bytes).await?;
+            if *preserve {
+                file_meta::restore(Path::new(file_path), &metadata)?;
+            }
+            println!("got version: {} ({} bytes)", version, bytes.len());
+        }
+        Commands::GetAllVersions { name, dir } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            history::get_all_versions(client, bucket, name, dir).await?;
+        }
+        Commands::Verify { file_path, name, part_sizes } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            let head = client.head_object()
+                .bucket(bucket)
+                .set_request_payer(payer::get())
+                .key(name)
+                .send()
+                .await?;
+            match etag::matches_file(Path::new(file_path), head.e_tag().unwrap_or_default(), part_sizes).await? {
+                etag::EtagMatch::SinglePart => println!("{} matches {}", file_path, name),
+                etag::EtagMatch::Multipart { parts, part_size } => println!("{} matches {} ({} parts of {} bytes)", file_path, name, parts, part_size),
+                etag::EtagMatch::NoMatch => {
+                    println!("{} doesn't match {}", file_path, name);
+                    return Err(Exit(1).into());
+                }
+            }
         }
-        Some(Commands::DeleteVersion { name, version }) => {
+        Commands::ChecksumInfo { name } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            integrity::checksum_info(client, bucket, name).await?;
+        }
+        Commands::Rechecksum { name, algorithm } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            integrity::rechecksum(client, bucket, name, *algorithm).await?;
+        }
+        Commands::GetObjectAcl { name, version_id } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            acl::get(client, bucket, name, version_id.as_deref()).await?;
+        }
+        Commands::PutObjectAcl { name, version_id, canned_acl, grants } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            acl::put(client, bucket, name, version_id.as_deref(), canned_acl.as_deref(), grants).await?;
+        }
+        Commands::DeleteVersion { name, version, ignore_missing } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
             let result = client.delete_object()
-                .bucket(bucket_name.clone())
+                .bucket(bucket)
+                .set_request_payer(payer::get())
                 .key(name)
                 .version_id(version)
                 .send()
-                .await?;
-            println!("delete result: {:?}", result);
+                .await;
+            match result {
+                Ok(result) => {
+                    println!("delete result: {:?}", result);
+                    summary::report("delete-version", name, Outcome::Changed);
+                }
+                Err(SdkError::ServiceError(err)) if *ignore_missing && delete::is_missing(err.err().code()) => {
+                    println!("{} ({}) is already gone", name, version);
+                    summary::report("delete-version", name, Outcome::NoOp);
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Commands::DeleteVersions { name, filter, dry_run } => {
+            let (bucket, name) = location::split(name, bucket_name, settings);
+            key::validate(name)?;
+            history::delete_versions(client, bucket, name, filter, *dry_run).await?;
         }
-        Some(Commands::CopyObject { source, dest }) => {
-            let result = client.copy_object()
-                .bucket(bucket_name.clone())
-                .copy_source(format!("{}/{}", bucket_name, source))
-                .key(dest)
+        Commands::CopyObject { source, dest, options } => {
+            let (source_bucket, source) = location::split(source, bucket_name, settings);
+            let (bucket, dest) = location::split(dest, bucket_name, settings);
+            key::validate(source)?;
+            key::validate(dest)?;
+            copy::run(client, source_bucket, source, bucket, dest, options).await?;
+        }
+        Commands::Report { top, by_prefix, include_versions } => {
+            report::run(client, bucket_name, *top, *by_prefix, *include_versions).await?;
+        }
+        Commands::CostEstimate { prefix, provider, price_table } => {
+            let prices = match price_table {
+                Some(path) => cost::PriceTable::parse(&tokio::fs::read_to_string(path).await?)?,
+                None => cost::PriceTable::builtin(*provider),
+            };
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            cost::run(client, bucket, prefix, &prices).await?;
+        }
+        Commands::Archive { prefix, output } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            archive::archive(client, bucket, prefix, output).await?;
+        }
+        Commands::Unarchive { archive, prefix } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            archive::unarchive(client, bucket, archive, prefix).await?;
+        }
+        Commands::UploadDir { local_dir, prefix, symlinks, preserve, failures_out, headers } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            let options = upload::PutOptions { preserve: *preserve, headers: headers.clone(), ..Default::default() };
+            upload::upload_dir(client, bucket, local_dir, prefix, symlinks.mode(), &options, failures_out.as_deref()).await?;
+        }
+        Commands::PutManifest { manifest, concurrency, report, failures_out } => {
+            put_manifest::run(client, bucket_name, manifest, *concurrency, report.as_deref(), failures_out.as_deref()).await?;
+        }
+        Commands::Sync { local_dir, prefix, compare, symlinks, preserve, journal, failures_out, part_sizes, chunked_over, chunk_size } => {
+            let options = sync::SyncOptions {
+                compare: *compare,
+                symlinks: symlinks.mode(),
+                put: upload::PutOptions { preserve: *preserve, ..Default::default() },
+                journal: journal.clone(),
+                failures_out: failures_out.clone(),
+                part_sizes: part_sizes.clone(),
+                chunked_over: *chunked_over,
+                chunk_size: *chunk_size,
+            };
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            sync::run(client, bucket, local_dir, prefix, &options).await?;
+        }
+        Commands::Watch { prefix, interval, exec, .. } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            watch::run(client, bucket, prefix, *interval, exec.as_deref()).await?;
+        }
+        Commands::Mirror { local_dir, prefix, symlinks, delete, debounce_ms, preserve, headers, .. } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            let options = mirror::sync_options(symlinks.mode(), upload::PutOptions { preserve: *preserve, headers: headers.clone(), ..Default::default() });
+            mirror::run(client, bucket, local_dir, prefix, &options, *delete, Duration::from_millis(*debounce_ms)).await?;
+        }
+        Commands::Website { action } => {
+            website::run(client, bucket_name, action).await?;
+        }
+        Commands::Kv { action, prefix } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            kv::run(client, bucket, prefix, action).await?;
+        }
+        Commands::BucketTags { action } => {
+            bucket_tags::run(client, bucket_name, action).await?;
+        }
+        Commands::Ownership { action } => {
+            ownership::run(client, bucket_name, action).await?;
+        }
+        Commands::IntelligentTiering { action } => {
+            tiering::run(client, bucket_name, action).await?;
+        }
+        Commands::Accelerate { action } => {
+            accelerate::run(client, bucket_name, action).await?;
+        }
+        Commands::Metrics { action } => {
+            metrics::run_metrics(client, bucket_name, action).await?;
+        }
+        Commands::Analytics { action } => {
+            metrics::run_analytics(client, bucket_name, action).await?;
+        }
+        Commands::Publish { local_dir, prefix, compare } => {
+            let options = sync::SyncOptions {
+                compare: *compare,
+                symlinks: tree::SymlinkMode::Follow,
+                put: upload::PutOptions { web: true, ..Default::default() },
+                journal: None,
+                failures_out: None,
+                part_sizes: Vec::new(),
+                chunked_over: None,
+                chunk_size: 0,
+            };
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            sync::run(client, bucket, local_dir, prefix, &options).await?;
+        }
+        Commands::Enforce { policy_file, dry_run } => {
+            let policy = enforce::Policy::parse(&tokio::fs::read_to_string(policy_file).await?)?;
+            enforce::run(client, bucket_name, &policy, *dry_run).await?;
+        }
+        Commands::Inventory { prefix, output, format, include_versions, resume_token_file } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            inventory::run(client, bucket, prefix, output, *format, *include_versions, resume_token_file.as_deref()).await?;
+        }
+        Commands::MetaExport { prefix, output, concurrency } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            meta::export(client, bucket, prefix, output, *concurrency).await?;
+        }
+        Commands::MetaImport { input, concurrency } => {
+            meta::import(client, bucket_name, input, *concurrency).await?;
+        }
+        Commands::BatchManifest { prefix, filter, output } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            manifest::run(client, bucket, prefix, filter, output.as_deref()).await?;
+        }
+        Commands::Rm { prefix, all_versions, concurrency, failures_out, ignore_missing } => {
+            let options = delete::RmOptions {
+                all_versions: *all_versions,
+                concurrency: *concurrency,
+                failures_out: failures_out.clone(),
+                ignore_missing: *ignore_missing,
+            };
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            delete::rm(client, bucket, prefix, &options).await?;
+        }
+        Commands::DownloadPrefix { prefix, local_dir, concurrency, failures_out, resume_token_file } => {
+            let (bucket, prefix) = location::split(prefix, bucket_name, settings);
+            download::download_prefix(client, bucket, prefix, local_dir, *concurrency, failures_out.as_deref(), resume_token_file.as_deref()).await?;
+        }
+        Commands::Retry { failures_file, failures_out } => {
+            retry::run(client, failures_file, failures_out.as_deref()).await?;
+        }
+        Commands::BucketLocation => {
+            let result = client.get_bucket_location()
+                .bucket(bucket_name)
                 .send()
                 .await?;
-            println!("copy result: {:?}", result);
+            println!("{}", region::location_name(result.location_constraint()));
         }
+        Commands::DebugSign { .. } | Commands::Doctor | Commands::PresignPost { .. } | Commands::BucketExists { .. } | Commands::CreateBucket { .. } | Commands::NukeBucket { .. } | Commands::CloneBucket { .. } | Commands::ListBuckets { .. } | Commands::Migrate { .. } | Commands::Configure { .. } | Commands::Usage { .. } => unreachable!("handled before the versioning check"),
+        Commands::Daemon => unreachable!("handled before dispatching"),
     }
     Ok(())
 }
 
-fn display_object_list(result: ListObjectsV2Output) {
-    if let Some(contents) = result.contents {
-        for object in contents {
-            println!("Object: {:?}", object.key().unwrap_or("<none>"));
-        }
-    } else {
-        println!("no contents");
-    }
-}
-
-/// returns the version_id if already exists
-async fn get_version_for_hash(client: &Client, name: &String, hash: &String, bucket_name: &String) -> Result<Option<String>, Box<dyn Error>> {
-    let ver_result = client.list_object_versions()
-        .bucket(bucket_name)
-        .set_prefix(Some(name.clone()))
-        .send().await?;
-    if let Some(versions) = ver_result.versions {
-        for version in versions {
-            let str = &version.e_tag().unwrap().to_string().to_ascii_lowercase();
-            let str = str[1..str.len()-1].to_string();
-            if str == *hash {
-                return Ok(Some(version.version_id().unwrap().to_string()));
-            }
-        }
-        return Ok(None)
-    }
-    Ok(None)
+/// the first of names that's set. the tool's own names come before the AWS_* equivalents
+fn env_var(names: &[&str]) -> Option<String> {
+    names.iter().find_map(|name| env::var(name).ok())
 }
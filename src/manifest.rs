@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_s3::types::ObjectVersion;
+use clap::Args;
+use crate::key;
+use crate::listing::list_all_versions;
+
+#[derive(Args, Clone, Debug)]
+pub struct ManifestFilter {
+    #[arg(long, help = "include noncurrent versions, not just the current one")]
+    all_versions: bool,
+    #[arg(long, help = "only versions in this storage class")]
+    storage_class: Option<String>,
+    #[arg(long, value_name = "BYTES", help = "only versions at least this large")]
+    min_size: Option<i64>,
+    #[arg(long, value_name = "BYTES", help = "only versions at most this large")]
+    max_size: Option<i64>,
+    #[arg(long, value_name = "RFC3339", value_parser = parse_time, help = "only versions modified before this time")]
+    modified_before: Option<DateTime>,
+    #[arg(long, value_name = "RFC3339", value_parser = parse_time, help = "only versions modified at or after this time")]
+    modified_after: Option<DateTime>,
+}
+
+pub fn parse_time(value: &str) -> Result<DateTime, String> {
+    DateTime::from_str(value, DateTimeFormat::DateTime).map_err(|err| err.to_string())
+}
+
+impl ManifestFilter {
+    fn matches(&self, version: &ObjectVersion) -> bool {
+        let class = version.storage_class().map(|c| c.as_str()).unwrap_or("STANDARD");
+        let modified = version.last_modified().map(|d| d.secs()).unwrap_or(0);
+        (self.all_versions || version.is_latest())
+            && self.storage_class.as_ref().is_none_or(|c| c.eq_ignore_ascii_case(class))
+            && self.min_size.is_none_or(|min| version.size() >= min)
+            && self.max_size.is_none_or(|max| version.size() <= max)
+            && self.modified_before.is_none_or(|before| modified < before.secs())
+            && self.modified_after.is_none_or(|after| modified >= after.secs())
+    }
+}
+
+/// writes a `bucket,key,version-id` CSV in the format S3 Batch Operations reads, to output or stdout
+pub async fn run(client: &Client, bucket_name: &str, prefix: &str, filter: &ManifestFilter, output: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let (versions, _) = list_all_versions(client, bucket_name, prefix).await?;
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut count = 0;
+    for version in versions.iter().filter(|v| filter.matches(v)) {
+        let Some(key) = version.key() else { continue };
+        writeln!(out, "{},{},{}", bucket_name, key::encode_path(key), version.version_id().unwrap_or("null"))?;
+        count += 1;
+    }
+    out.flush()?;
+    if output.is_some() {
+        println!("wrote {} entries", count);
+    }
+    Ok(())
+}
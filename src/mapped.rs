@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_smithy_http::byte_stream::Length;
+use bytes::Bytes;
+use memmap2::Mmap;
+
+/// how much of a file an upload reads at a time
+const READ_BUFFER: usize = 64 * 1024;
+
+/// a file's contents, memory mapped when possible so hashing it doesn't need a copy in memory.
+/// uploads read the file itself, so nothing outlives the mapping, which is unmapped with this
+pub struct Mapped {
+    contents: Contents,
+}
+
+enum Contents {
+    File { map: Mmap, path: PathBuf },
+    Memory(Bytes),
+}
+
+/// the contents of the file at path, memory mapped when possible. falls back to reading the file
+/// for empty files and filesystems that can't be mapped
+pub async fn read(path: &Path) -> io::Result<Mapped> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() > 0 {
+        // like any other read, this expects the file not to change until the upload is done
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Ok(Mapped { contents: Contents::File { map, path: path.to_path_buf() } });
+        }
+    }
+    Ok(Mapped::from(tokio::fs::read(path).await?))
+}
+
+impl Mapped {
+    /// a body for the bytes in range, read from the file a buffer at a time if it's mapped
+    pub async fn body(&self, range: Range<usize>) -> Result<ByteStream, Box<dyn Error>> {
+        match &self.contents {
+            Contents::File { path, .. } => Ok(ByteStream::read_from()
+                .path(path)
+                .offset(range.start as u64)
+                .length(Length::Exact(range.len() as u64))
+                .buffer_size(READ_BUFFER)
+                .build()
+                .await?),
+            Contents::Memory(bytes) => Ok(ByteStream::from(bytes.slice(range))),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Mapped {
+    fn from(bytes: Vec<u8>) -> Mapped {
+        Mapped { contents: Contents::Memory(bytes.into()) }
+    }
+}
+
+impl Deref for Mapped {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.contents {
+            Contents::File { map, .. } => map,
+            Contents::Memory(bytes) => bytes,
+        }
+    }
+}
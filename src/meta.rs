@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_s3::types::{MetadataDirective, TaggingDirective};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use serde_json::{json, Value};
+use crate::copy::tagging;
+use crate::key;
+use crate::listing::object_pages;
+use crate::payer;
+use crate::progress::Progress;
+use crate::stop;
+use crate::summary::Summary;
+
+/// what a plain copy between providers can lose: the headers S3 stores with an object, its
+/// user metadata and its tags
+#[derive(Clone, Debug)]
+struct ObjectMeta {
+    key: String,
+    content_type: Option<String>,
+    cache_control: Option<String>,
+    content_disposition: Option<String>,
+    content_encoding: Option<String>,
+    content_language: Option<String>,
+    /// HTTP date, as the Expires header has it
+    expires: Option<String>,
+    metadata: BTreeMap<String, String>,
+    tags: BTreeMap<String, String>,
+}
+
+impl ObjectMeta {
+    fn to_json(&self) -> Value {
+        json!({
+            "key": self.key,
+            "content_type": self.content_type,
+            "cache_control": self.cache_control,
+            "content_disposition": self.content_disposition,
+            "content_encoding": self.content_encoding,
+            "content_language": self.content_language,
+            "expires": self.expires,
+            "metadata": self.metadata,
+            "tags": self.tags,
+        })
+    }
+
+    fn from_json(value: &Value) -> Result<ObjectMeta, String> {
+        let string = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+        let map = |name: &str| -> Result<BTreeMap<String, String>, String> {
+            let mut map = BTreeMap::new();
+            if let Some(entries) = value.get(name).and_then(Value::as_object) {
+                for (key, value) in entries {
+                    let value = value.as_str().ok_or_else(|| format!("{} values must be strings", name))?;
+                    map.insert(key.clone(), value.to_string());
+                }
+            }
+            Ok(map)
+        };
+        Ok(ObjectMeta {
+            key: string("key").ok_or("missing key")?,
+            content_type: string("content_type"),
+            cache_control: string("cache_control"),
+            content_disposition: string("content_disposition"),
+            content_encoding: string("content_encoding"),
+            content_language: string("content_language"),
+            expires: string("expires"),
+            metadata: map("metadata")?,
+            tags: map("tags")?,
+        })
+    }
+}
+
+async fn capture(client: &Client, bucket_name: &str, key: &str) -> Result<ObjectMeta, Box<dyn Error>> {
+    let head = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .send()
+        .await?;
+    let tags = client.get_object_tagging()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .send()
+        .await?;
+    Ok(ObjectMeta {
+        key: key.to_string(),
+        content_type: head.content_type().map(str::to_string),
+        cache_control: head.cache_control().map(str::to_string),
+        content_disposition: head.content_disposition().map(str::to_string),
+        content_encoding: head.content_encoding().map(str::to_string),
+        content_language: head.content_language().map(str::to_string),
+        expires: head.expires().and_then(|d| d.fmt(DateTimeFormat::HttpDate).ok()),
+        metadata: head.metadata().cloned().unwrap_or_default().into_iter().collect(),
+        tags: tags.tag_set().unwrap_or_default().iter()
+            .filter_map(|tag| Some((tag.key()?.to_string(), tag.value()?.to_string())))
+            .collect(),
+    })
+}
+
+/// writes the headers, metadata and tags of every object under prefix to output as JSON lines,
+/// in key order
+pub async fn export(client: &Client, bucket_name: &str, prefix: &str, output: &Path, concurrency: usize) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(output)?);
+    let objects = object_pages(client, bucket_name, prefix)
+        .map_ok(|page| stream::iter(page.into_iter().filter_map(|o| o.key).map(Ok::<_, Box<dyn Error>>)))
+        .try_flatten();
+    let mut results = pin!(objects
+        .map_ok(|key| async move {
+            let result = capture(client, bucket_name, &key).await;
+            Ok((key, result))
+        })
+        .try_buffered(concurrency.max(1)));
+    let mut progress = Progress::counter("exporting");
+    let mut summary = Summary::start("meta-export", "exported");
+    while let Some((key, result)) = stop::within(results.try_next()).await?? {
+        match result {
+            Ok(meta) => {
+                writeln!(out, "{}", meta.to_json())?;
+                summary.transferred += 1;
+            }
+            Err(err) => {
+                println!("failed to export {}: {}", key, err);
+                summary.failed += 1;
+            }
+        }
+        progress.add(1);
+    }
+    out.flush()?;
+    progress.finish();
+    summary.print();
+    if summary.failed > 0 {
+        return Err(format!("{} objects failed", summary.failed).into());
+    }
+    Ok(())
+}
+
+/// copies key onto itself with meta's headers, metadata and tags in place of its own. the
+/// storage class is kept, since a copy would otherwise move it to STANDARD
+async fn apply(client: &Client, bucket_name: &str, meta: &ObjectMeta) -> Result<(), Box<dyn Error>> {
+    key::validate(&meta.key)?;
+    let head = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(&meta.key)
+        .send()
+        .await?;
+    if head.content_length() > key::MAX_COPY_SIZE {
+        return Err(format!("{} is over 5 GiB, which needs a multipart copy", meta.key).into());
+    }
+    let expires = match &meta.expires {
+        Some(expires) => Some(DateTime::from_str(expires, DateTimeFormat::HttpDate)?),
+        None => None,
+    };
+    let mut request = client.copy_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .copy_source(key::copy_source(bucket_name, &meta.key, None))
+        .key(&meta.key)
+        .metadata_directive(MetadataDirective::Replace)
+        .set_content_type(meta.content_type.clone())
+        .set_cache_control(meta.cache_control.clone())
+        .set_content_disposition(meta.content_disposition.clone())
+        .set_content_encoding(meta.content_encoding.clone())
+        .set_content_language(meta.content_language.clone())
+        .set_expires(expires)
+        .set_storage_class(head.storage_class().cloned())
+        .tagging_directive(TaggingDirective::Replace)
+        .tagging(tagging(&meta.tags.clone().into_iter().collect::<Vec<_>>()));
+    for (key, value) in &meta.metadata {
+        request = request.metadata(key, value);
+    }
+    request.send().await?;
+    Ok(())
+}
+
+/// re-applies the headers, metadata and tags in a file written by export to the objects with
+/// the same keys in bucket_name, which must already exist
+pub async fn import(client: &Client, bucket_name: &str, input: &Path, concurrency: usize) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(input)?;
+    let mut records = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(line).map_err(|err| format!("{}:{}: {}", input.display(), idx + 1, err))?;
+        records.push(ObjectMeta::from_json(&value).map_err(|err| format!("{}:{}: {}", input.display(), idx + 1, err))?);
+    }
+    let mut results = stream::iter(&records)
+        .map(|meta| async move { (meta, apply(client, bucket_name, meta).await) })
+        .buffer_unordered(concurrency.max(1));
+    let mut progress = Progress::counter("importing");
+    let mut summary = Summary::start("meta-import", "updated");
+    while let Some((meta, result)) = stop::within(results.next()).await? {
+        match result {
+            Ok(()) => summary.transferred += 1,
+            Err(err) => {
+                println!("failed to import {}: {}", meta.key, err);
+                summary.failed += 1;
+            }
+        }
+        progress.add(1);
+    }
+    progress.finish();
+    summary.print();
+    if summary.failed > 0 {
+        return Err(format!("{} objects failed", summary.failed).into());
+    }
+    Ok(())
+}
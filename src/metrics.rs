@@ -0,0 +1,259 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{AnalyticsAndOperator, AnalyticsConfiguration, AnalyticsExportDestination, AnalyticsFilter,
+                        AnalyticsS3BucketDestination, AnalyticsS3ExportFileFormat, MetricsAndOperator, MetricsConfiguration,
+                        MetricsFilter, StorageClassAnalysis, StorageClassAnalysisDataExport, StorageClassAnalysisSchemaVersion, Tag};
+use clap::{Args, Subcommand};
+use crate::copy::parse_pair;
+
+/// which objects a configuration covers. with neither, it covers the whole bucket
+#[derive(Args, Clone, Debug)]
+pub struct FilterArgs {
+    #[arg(long, help = "only objects under this prefix")]
+    prefix: Option<String>,
+    #[arg(long = "tag", value_name = "KEY=VALUE", value_parser = parse_pair, help = "only objects with this tag. may be repeated")]
+    tags: Vec<(String, String)>,
+}
+
+impl FilterArgs {
+    fn tags(&self) -> Vec<Tag> {
+        self.tags.iter().map(|(key, value)| Tag::builder().key(key).value(value).build()).collect()
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum MetricsAction {
+    /// list the bucket's request metrics configurations
+    List,
+    Get {
+        id: String,
+    },
+    /// create or replace a configuration publishing CloudWatch request metrics
+    Put {
+        id: String,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_name = "ARN", help = "only requests through this access point")]
+        access_point: Option<String>,
+    },
+    Delete {
+        id: String,
+    },
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum AnalyticsAction {
+    /// list the bucket's storage class analysis configurations
+    List,
+    Get {
+        id: String,
+    },
+    /// create or replace a storage class analysis configuration
+    Put {
+        id: String,
+        #[command(flatten)]
+        filter: FilterArgs,
+        #[arg(long, value_name = "BUCKET", help = "bucket, or bucket ARN, to export daily analysis CSVs to")]
+        export_bucket: Option<String>,
+        #[arg(long, requires = "export_bucket", help = "prefix for the exported files")]
+        export_prefix: Option<String>,
+    },
+    Delete {
+        id: String,
+    },
+}
+
+fn describe_tags(tags: &[Tag]) -> impl Iterator<Item = String> + '_ {
+    tags.iter().map(|tag| format!("tag {}={}", tag.key().unwrap_or_default(), tag.value().unwrap_or_default()))
+}
+
+fn describe_metrics_filter(filter: Option<&MetricsFilter>) -> String {
+    let parts: Vec<String> = match filter {
+        None => Vec::new(),
+        Some(MetricsFilter::Prefix(prefix)) => vec![format!("prefix {}", prefix)],
+        Some(MetricsFilter::Tag(tag)) => describe_tags(std::slice::from_ref(tag)).collect(),
+        Some(MetricsFilter::AccessPointArn(arn)) => vec![format!("access point {}", arn)],
+        Some(MetricsFilter::And(and)) => and.prefix().map(|prefix| format!("prefix {}", prefix)).into_iter()
+            .chain(describe_tags(and.tags().unwrap_or_default()))
+            .chain(and.access_point_arn().map(|arn| format!("access point {}", arn)))
+            .collect(),
+        Some(_) => vec!["a filter this tool doesn't know".to_string()],
+    };
+    if parts.is_empty() { "the whole bucket".to_string() } else { parts.join(", ") }
+}
+
+fn describe_analytics_filter(filter: Option<&AnalyticsFilter>) -> String {
+    let parts: Vec<String> = match filter {
+        None => Vec::new(),
+        Some(AnalyticsFilter::Prefix(prefix)) => vec![format!("prefix {}", prefix)],
+        Some(AnalyticsFilter::Tag(tag)) => describe_tags(std::slice::from_ref(tag)).collect(),
+        Some(AnalyticsFilter::And(and)) => and.prefix().map(|prefix| format!("prefix {}", prefix)).into_iter()
+            .chain(describe_tags(and.tags().unwrap_or_default()))
+            .collect(),
+        Some(_) => vec!["a filter this tool doesn't know".to_string()],
+    };
+    if parts.is_empty() { "the whole bucket".to_string() } else { parts.join(", ") }
+}
+
+fn print_metrics(configuration: &MetricsConfiguration) {
+    println!("{}: {}", configuration.id().unwrap_or("<none>"), describe_metrics_filter(configuration.filter()));
+}
+
+fn print_analytics(configuration: &AnalyticsConfiguration) {
+    println!("{}: {}", configuration.id().unwrap_or("<none>"), describe_analytics_filter(configuration.filter()));
+    let destination = configuration.storage_class_analysis()
+        .and_then(|analysis| analysis.data_export())
+        .and_then(|export| export.destination())
+        .and_then(|destination| destination.s3_bucket_destination());
+    if let Some(destination) = destination {
+        println!("  exported to {}/{}", destination.bucket().unwrap_or_default(), destination.prefix().unwrap_or_default());
+    }
+}
+
+/// a filter with one condition is that condition; more have to be combined with And
+fn metrics_filter(filter: &FilterArgs, access_point: Option<&str>) -> Option<MetricsFilter> {
+    let mut tags = filter.tags();
+    match (filter.prefix.as_deref(), tags.len(), access_point) {
+        (None, 0, None) => None,
+        (Some(prefix), 0, None) => Some(MetricsFilter::Prefix(prefix.to_string())),
+        (None, 1, None) => Some(MetricsFilter::Tag(tags.remove(0))),
+        (None, 0, Some(arn)) => Some(MetricsFilter::AccessPointArn(arn.to_string())),
+        (prefix, _, arn) => Some(MetricsFilter::And(MetricsAndOperator::builder()
+            .set_prefix(prefix.map(str::to_string))
+            .set_tags(Some(tags))
+            .set_access_point_arn(arn.map(str::to_string))
+            .build())),
+    }
+}
+
+fn analytics_filter(filter: &FilterArgs) -> Option<AnalyticsFilter> {
+    let mut tags = filter.tags();
+    match (filter.prefix.as_deref(), tags.len()) {
+        (None, 0) => None,
+        (Some(prefix), 0) => Some(AnalyticsFilter::Prefix(prefix.to_string())),
+        (None, 1) => Some(AnalyticsFilter::Tag(tags.remove(0))),
+        (prefix, _) => Some(AnalyticsFilter::And(AnalyticsAndOperator::builder()
+            .set_prefix(prefix.map(str::to_string))
+            .set_tags(Some(tags))
+            .build())),
+    }
+}
+
+pub async fn run_metrics(client: &Client, bucket_name: &str, action: &MetricsAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        MetricsAction::List => {
+            let mut token = None;
+            let mut count = 0;
+            loop {
+                let result = client.list_bucket_metrics_configurations()
+                    .bucket(bucket_name)
+                    .set_continuation_token(token)
+                    .send()
+                    .await?;
+                for configuration in result.metrics_configuration_list().unwrap_or_default() {
+                    print_metrics(configuration);
+                    count += 1;
+                }
+                token = result.next_continuation_token().filter(|_| result.is_truncated()).map(str::to_string);
+                if token.is_none() {
+                    break;
+                }
+            }
+            if count == 0 {
+                println!("no metrics configurations");
+            }
+        }
+        MetricsAction::Get { id } => {
+            let result = client.get_bucket_metrics_configuration().bucket(bucket_name).id(id).send().await?;
+            match result.metrics_configuration() {
+                Some(configuration) => print_metrics(configuration),
+                None => println!("no configuration {}", id),
+            }
+        }
+        MetricsAction::Put { id, filter, access_point } => {
+            let configuration = MetricsConfiguration::builder()
+                .id(id)
+                .set_filter(metrics_filter(filter, access_point.as_deref()))
+                .build();
+            client.put_bucket_metrics_configuration()
+                .bucket(bucket_name)
+                .id(id)
+                .metrics_configuration(configuration.clone())
+                .send()
+                .await?;
+            print_metrics(&configuration);
+        }
+        MetricsAction::Delete { id } => {
+            client.delete_bucket_metrics_configuration().bucket(bucket_name).id(id).send().await?;
+            println!("deleted metrics configuration {}", id);
+        }
+    }
+    Ok(())
+}
+
+pub async fn run_analytics(client: &Client, bucket_name: &str, action: &AnalyticsAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        AnalyticsAction::List => {
+            let mut token = None;
+            let mut count = 0;
+            loop {
+                let result = client.list_bucket_analytics_configurations()
+                    .bucket(bucket_name)
+                    .set_continuation_token(token)
+                    .send()
+                    .await?;
+                for configuration in result.analytics_configuration_list().unwrap_or_default() {
+                    print_analytics(configuration);
+                    count += 1;
+                }
+                token = result.next_continuation_token().filter(|_| result.is_truncated()).map(str::to_string);
+                if token.is_none() {
+                    break;
+                }
+            }
+            if count == 0 {
+                println!("no analytics configurations");
+            }
+        }
+        AnalyticsAction::Get { id } => {
+            let result = client.get_bucket_analytics_configuration().bucket(bucket_name).id(id).send().await?;
+            match result.analytics_configuration() {
+                Some(configuration) => print_analytics(configuration),
+                None => println!("no configuration {}", id),
+            }
+        }
+        AnalyticsAction::Put { id, filter, export_bucket, export_prefix } => {
+            let mut analysis = StorageClassAnalysis::builder();
+            if let Some(bucket) = export_bucket {
+                // the destination has to be given as an ARN
+                let arn = if bucket.starts_with("arn:") { bucket.clone() } else { format!("arn:aws:s3:::{}", bucket) };
+                let destination = AnalyticsS3BucketDestination::builder()
+                    .bucket(arn)
+                    .set_prefix(export_prefix.clone())
+                    .format(AnalyticsS3ExportFileFormat::Csv)
+                    .build();
+                analysis = analysis.data_export(StorageClassAnalysisDataExport::builder()
+                    .output_schema_version(StorageClassAnalysisSchemaVersion::V1)
+                    .destination(AnalyticsExportDestination::builder().s3_bucket_destination(destination).build())
+                    .build());
+            }
+            let configuration = AnalyticsConfiguration::builder()
+                .id(id)
+                .set_filter(analytics_filter(filter))
+                .storage_class_analysis(analysis.build())
+                .build();
+            client.put_bucket_analytics_configuration()
+                .bucket(bucket_name)
+                .id(id)
+                .analytics_configuration(configuration.clone())
+                .send()
+                .await?;
+            print_analytics(&configuration);
+        }
+        AnalyticsAction::Delete { id } => {
+            client.delete_bucket_analytics_configuration().bucket(bucket_name).id(id).send().await?;
+            println!("deleted analytics configuration {}", id);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,442 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use aws_credential_types::provider::SharedCredentialsProvider;
+use aws_sdk_config::config::Credentials;
+use aws_sdk_s3::{Client, Config};
+use aws_sdk_s3::config::Region;
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{BucketVersioningStatus, CompletedMultipartUpload, CompletedPart};
+use clap::Args;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use tokio::io::AsyncReadExt;
+use crate::clock;
+use crate::clone_bucket::{histories, Event};
+use crate::config::Settings;
+use crate::copy::tagging;
+use crate::errors::ErrorDetails;
+use crate::failover;
+use crate::http::{self, HttpArgs};
+use crate::listing::object_pages;
+use crate::lock::LockArgs;
+use crate::payer;
+use crate::progress::Progress;
+use crate::secrets;
+use crate::sigv2::{SignatureVersion, SigV2};
+use crate::stop;
+use crate::summary::Summary;
+use crate::usage;
+
+const HEADER: &str = "# s3test migrate journal";
+
+/// the smallest part S3 accepts, other than the last
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// the most parts a multipart upload can have
+pub const MAX_PARTS: u64 = 10_000;
+
+#[derive(Args, Clone, Debug)]
+pub struct MigrateArgs {
+    #[arg(long, value_name = "PROFILE", help = "profile to read from")]
+    source_profile: String,
+    #[arg(long, value_name = "PROFILE", help = "profile to write to")]
+    dest_profile: String,
+    #[arg(long, help = "bucket to read from. defaults to the source profile's bucket")]
+    source_bucket: Option<String>,
+    #[arg(long, help = "bucket to write to. defaults to the destination profile's bucket")]
+    dest_bucket: Option<String>,
+    #[arg(allow_hyphen_values = true, default_value = "")]
+    prefix: String,
+    #[arg(long, help = "migrate every version and delete marker, oldest first, instead of just current objects. the destination must be versioned")]
+    include_versions: bool,
+    #[arg(long, value_name = "PATH", help = "record each object as it's migrated, and skip the recorded ones when run again")]
+    journal: Option<PathBuf>,
+    #[arg(long, default_value_t = 4, help = "objects to transfer at once")]
+    concurrency: usize,
+    #[arg(long, default_value_t = 64 * 1024 * 1024, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(MIN_PART_SIZE..),
+          help = "objects larger than this are streamed into a multipart upload in parts of this size, holding one part in memory at a time")]
+    part_size: u64,
+}
+
+/// a client for the named profile from the config file, with its keys from the OS keyring if
+/// configure put them there. the region defaults to us-east-1, as configure suggests
+fn profile_client(settings: &Settings, name: &str, http_args: &HttpArgs, signature_version: SignatureVersion) -> Result<(Client, Option<String>), Box<dyn Error>> {
+    let profile = settings.profile(name)?;
+    let (access_key, secret_key) = match secrets::load(name) {
+        Some(keys) => keys,
+        None => profile.access_key.clone().zip(profile.secret_key.clone())
+            .ok_or_else(|| format!("profile {} has no access key and secret key", name))?,
+    };
+    let credentials = SharedCredentialsProvider::new(Credentials::from_keys(access_key, secret_key, None));
+    let mut connector = usage::Metered::wrap(http::connector(http_args)?);
+    // innermost, as for every other command
+    if signature_version == SignatureVersion::V2 {
+        connector = SigV2::wrap(connector, credentials.clone(), &profile.endpoints);
+    }
+    let connector = clock::SkewCorrection::wrap(
+        failover::Failover::wrap(connector, &profile.endpoints, credentials.clone())?,
+        credentials.clone());
+    let mut builder = Config::builder()
+        .credentials_provider(credentials)
+        .region(Region::new(profile.region.clone().unwrap_or_else(|| "us-east-1".to_string())))
+        .http_connector(connector);
+    builder.set_endpoint_url(profile.endpoints.first().cloned());
+    Ok((Client::from_conf(builder.build()), profile.bucket.clone()))
+}
+
+/// the objects a migration has transferred, as key and ETag, or key and version id with versions.
+/// appended to as each one finishes, so an interrupted run loses none of them
+struct Journal {
+    file: Option<BufWriter<File>>,
+}
+
+/// what earlier runs recorded in a journal
+type Done = HashSet<(String, String)>;
+
+impl Journal {
+    /// opens the journal at path with what it already records, creating it if it doesn't exist.
+    /// a journal for a different migration is refused rather than appended to
+    fn open(path: Option<&Path>, header: &str) -> Result<(Journal, Done), Box<dyn Error>> {
+        let mut done = HashSet::new();
+        let Some(path) = path else { return Ok((Journal { file: None }, done)) };
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let mut lines = text.lines();
+                if lines.next() != Some(header) {
+                    return Err(format!("journal {} is for a different migration", path.display()).into());
+                }
+                for (idx, line) in lines.enumerate() {
+                    let Some((key, id)) = line.split_once('\t') else {
+                        return Err(format!("{}:{}: malformed journal entry", path.display(), idx + 2).into());
+                    };
+                    done.insert((urlencoding::decode(key)?.into_owned(), id.to_string()));
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => fs::write(path, format!("{}\n", header))?,
+            Err(err) => return Err(err.into()),
+        }
+        let file = File::options().append(true).open(path)?;
+        Ok((Journal { file: Some(BufWriter::new(file)) }, done))
+    }
+
+    fn record(&mut self, key: &str, id: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}\t{}", urlencoding::encode(key), id)?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// the buckets on each side
+struct Ends<'a> {
+    source: &'a Client,
+    source_bucket: &'a str,
+    dest: &'a Client,
+    dest_bucket: &'a str,
+    part_size: u64,
+}
+
+/// a multipart upload that's aborted unless it completes, including when a transfer is dropped
+/// at the --deadline or on Ctrl-C, so no parts are left behind to be billed for
+pub struct PendingUpload<'a> {
+    pub client: &'a Client,
+    pub bucket_name: &'a str,
+    pub key: &'a str,
+    pub upload_id: String,
+    pub completed: bool,
+}
+
+impl Drop for PendingUpload<'_> {
+    /// the abort has to happen before the process exits, so it blocks this worker thread
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+        let abort = self.client.abort_multipart_upload()
+            .bucket(self.bucket_name)
+            .set_request_payer(payer::get())
+            .key(self.key)
+            .upload_id(&self.upload_id)
+            .send();
+        let result = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async { abort.await.map(|_| ()).map_err(Box::<dyn Error>::from) })
+        });
+        if let Err(err) = result {
+            eprintln!("note: couldn't abort the upload of {}: {}", self.key, err);
+        }
+    }
+}
+
+/// streams one object or version from source to dest, with its content headers, metadata and
+/// tags, and returns its size. the body goes straight from the download to the upload
+async fn transfer(ends: &Ends<'_>, key: &str, version_id: Option<&str>) -> Result<i64, Box<dyn Error>> {
+    let object = ends.source.get_object()
+        .bucket(ends.source_bucket)
+        .set_request_payer(payer::get())
+        .key(key)
+        .set_version_id(version_id.map(str::to_string))
+        .send()
+        .await?;
+    let size = object.content_length();
+    let tags = if object.tag_count() > 0 {
+        let result = ends.source.get_object_tagging()
+            .bucket(ends.source_bucket)
+            .set_request_payer(payer::get())
+            .key(key)
+            .set_version_id(version_id.map(str::to_string))
+            .send()
+            .await?;
+        let tags: Vec<(String, String)> = result.tag_set().unwrap_or_default().iter()
+            .filter_map(|tag| Some((tag.key()?.to_string(), tag.value()?.to_string())))
+            .collect();
+        Some(tagging(&tags))
+    } else {
+        None
+    };
+    if size as u64 > ends.part_size {
+        transfer_multipart(ends, key, object, tags).await?;
+        return Ok(size);
+    }
+    ends.dest.put_object()
+        .bucket(ends.dest_bucket)
+        .set_request_payer(payer::get())
+        .key(key)
+        .content_length(size)
+        .set_metadata(object.metadata().cloned())
+        .set_content_type(object.content_type().map(str::to_string))
+        .set_content_encoding(object.content_encoding().map(str::to_string))
+        .set_content_disposition(object.content_disposition().map(str::to_string))
+        .set_content_language(object.content_language().map(str::to_string))
+        .set_cache_control(object.cache_control().map(str::to_string))
+        .set_expires(object.expires().cloned())
+        .set_tagging(tags)
+        .body(object.body)
+        .send()
+        .await?;
+    Ok(size)
+}
+
+/// reads the GET body a part at a time and uploads each part before reading the next, so memory
+/// stays at one part whatever the object's size. parts grow past part_size for objects that
+/// would otherwise need more than S3 allows
+async fn transfer_multipart(ends: &Ends<'_>, key: &str, object: GetObjectOutput, tags: Option<String>) -> Result<(), Box<dyn Error>> {
+    let size = object.content_length().max(0) as u64;
+    let part_size = ends.part_size.max(size.div_ceil(MAX_PARTS));
+    let created = ends.dest.create_multipart_upload()
+        .bucket(ends.dest_bucket)
+        .set_request_payer(payer::get())
+        .key(key)
+        .set_metadata(object.metadata().cloned())
+        .set_content_type(object.content_type().map(str::to_string))
+        .set_content_encoding(object.content_encoding().map(str::to_string))
+        .set_content_disposition(object.content_disposition().map(str::to_string))
+        .set_content_language(object.content_language().map(str::to_string))
+        .set_cache_control(object.cache_control().map(str::to_string))
+        .set_expires(object.expires().cloned())
+        .set_tagging(tags)
+        .send()
+        .await?;
+    let mut upload = PendingUpload {
+        client: ends.dest,
+        bucket_name: ends.dest_bucket,
+        key,
+        upload_id: created.upload_id().ok_or("no upload id in the response")?.to_string(),
+        completed: false,
+    };
+    let mut body = object.body.into_async_read();
+    let mut parts = Vec::new();
+    let mut sent = 0;
+    while sent < size {
+        let mut buffer = Vec::with_capacity(part_size as usize);
+        (&mut body).take(part_size).read_to_end(&mut buffer).await?;
+        if buffer.is_empty() {
+            return Err(format!("the download of {} ended after {} of {} bytes", key, sent, size).into());
+        }
+        sent += buffer.len() as u64;
+        let number = parts.len() as i32 + 1;
+        let result = ends.dest.upload_part()
+            .bucket(ends.dest_bucket)
+            .set_request_payer(payer::get())
+            .key(key)
+            .upload_id(&upload.upload_id)
+            .part_number(number)
+            .body(ByteStream::from(buffer))
+            .send()
+            .await?;
+        parts.push(CompletedPart::builder().part_number(number).set_e_tag(result.e_tag().map(str::to_string)).build());
+    }
+    ends.dest.complete_multipart_upload()
+        .bucket(ends.dest_bucket)
+        .set_request_payer(payer::get())
+        .key(key)
+        .upload_id(&upload.upload_id)
+        .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+        .send()
+        .await?;
+    upload.completed = true;
+    Ok(())
+}
+
+/// replays the steps of a key's history that are left into dest, oldest first. returns
+/// the version ids done and their bytes, with the error that stopped it, if one did
+async fn transfer_history(ends: &Ends<'_>, key: &str, events: Vec<&Event>) -> (Vec<String>, u64, Option<Box<dyn Error>>) {
+    let mut done = Vec::new();
+    let mut bytes = 0;
+    for event in events {
+        let version_id = event.version_id.as_deref().unwrap_or("null");
+        let result = if event.deleted {
+            ends.dest.delete_object().bucket(ends.dest_bucket).set_request_payer(payer::get()).key(key).send().await
+                .map(|_| 0).map_err(|err| err.into())
+        } else {
+            transfer(ends, key, Some(version_id)).await
+        };
+        match result {
+            Ok(size) => {
+                done.push(version_id.to_string());
+                bytes += size.max(0) as u64;
+            }
+            Err(err) => return (done, bytes, Some(err)),
+        }
+    }
+    (done, bytes, None)
+}
+
+/// copies every object under the prefix from the source profile's endpoint to the destination's,
+/// which can be different providers with different credentials. with a journal, a second run
+/// carries on where an interrupted one stopped
+pub async fn run(settings: &Settings, http_args: &HttpArgs, args: &MigrateArgs, signature_version: SignatureVersion,
+                 locking: &LockArgs) -> Result<(), Box<dyn Error>> {
+    let (source, source_default) = profile_client(settings, &args.source_profile, http_args, signature_version)?;
+    let (dest, dest_default) = profile_client(settings, &args.dest_profile, http_args, signature_version)?;
+    let source_bucket = args.source_bucket.clone().or(source_default)
+        .ok_or_else(|| format!("profile {} has no bucket. use --source-bucket", args.source_profile))?;
+    let dest_bucket = args.dest_bucket.clone().or(dest_default)
+        .ok_or_else(|| format!("profile {} has no bucket. use --dest-bucket", args.dest_profile))?;
+    // in the destination, which is what two migrations mustn't write to at once. released when this returns
+    let _lock = locking.acquire(&dest, &dest_bucket).await?;
+    if args.include_versions {
+        let versioning = dest.get_bucket_versioning().bucket(&dest_bucket).send().await?;
+        if versioning.status() != Some(&BucketVersioningStatus::Enabled) {
+            return Err(format!("--include-versions needs versioning enabled on {}", dest_bucket).into());
+        }
+    }
+    let header = format!("{}\t{}\t{}\t{}\t{}\t{}", HEADER, args.source_profile, source_bucket, args.dest_profile, dest_bucket,
+                         urlencoding::encode(&args.prefix));
+    let (mut journal, done) = Journal::open(args.journal.as_deref(), &header)?;
+    let done = &done;
+    let is_done = |key: &str, id: &str| done.contains(&(key.to_string(), id.to_string()));
+    let ends = Ends { source: &source, source_bucket: &source_bucket, dest: &dest, dest_bucket: &dest_bucket, part_size: args.part_size };
+    let ends = &ends;
+
+    let mut progress = Progress::counter("migrating");
+    let mut summary = Summary::start("migrate", "migrated");
+    if args.include_versions {
+        let histories = histories(&source, &source_bucket, &args.prefix).await?;
+        let mut pending = Vec::new();
+        for (key, events) in &histories {
+            let (done, todo): (Vec<&Event>, Vec<&Event>) = events.iter()
+                .partition(|event| is_done(key, event.version_id.as_deref().unwrap_or("null")));
+            summary.skipped += done.len();
+            if !todo.is_empty() {
+                pending.push((key, todo));
+            }
+        }
+        let mut results = stream::iter(pending)
+            .map(|(key, events)| async move { (key, transfer_history(ends, key, events).await) })
+            .buffer_unordered(args.concurrency.max(1));
+        while let Some((key, (done, bytes, err))) = stop::within(results.next()).await? {
+            for version_id in &done {
+                journal.record(key, version_id)?;
+            }
+            summary.transferred += done.len();
+            summary.bytes += bytes;
+            progress.add(done.len() as u64);
+            if let Some(err) = err {
+                // the rest of the history is left for the next run, so the versions stay in order
+                println!("failed to migrate the history of {}: {}", key, ErrorDetails::from_error(err.as_ref()));
+                summary.failed += 1;
+            }
+        }
+    } else {
+        let objects = object_pages(&source, &source_bucket, &args.prefix)
+            .map_ok(|page| stream::iter(page.into_iter().map(Ok::<_, Box<dyn Error>>)))
+            .try_flatten();
+        let mut results = pin!(objects
+            .map_ok(|object| async move {
+                let key = object.key.clone().unwrap_or_default();
+                let e_tag = object.e_tag.clone().unwrap_or_default();
+                if is_done(&key, &e_tag) {
+                    return Ok((key, e_tag, None));
+                }
+                let result = transfer(ends, &key, None).await;
+                Ok((key, e_tag, Some(result)))
+            })
+            .try_buffer_unordered(args.concurrency.max(1)));
+        while let Some((key, e_tag, result)) = stop::within(results.try_next()).await?? {
+            match result {
+                None => summary.skipped += 1,
+                Some(Ok(size)) => {
+                    journal.record(&key, &e_tag)?;
+                    summary.transferred += 1;
+                    summary.bytes += size.max(0) as u64;
+                    progress.add(1);
+                }
+                Some(Err(err)) => {
+                    println!("failed to migrate {}: {}", key, ErrorDetails::from_error(err.as_ref()));
+                    summary.failed += 1;
+                }
+            }
+        }
+    }
+    progress.finish();
+    summary.print();
+    if summary.failed > 0 {
+        return Err(format!("{} objects failed", summary.failed).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn journal_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("s3test-journal-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn journal_remembers_finished_keys() {
+        let path = journal_path("resume");
+        let (mut journal, done) = Journal::open(Some(&path), "migrate a -> b").unwrap();
+        assert!(done.is_empty());
+        journal.record("plain", "v1").unwrap();
+        journal.record("with\ttab and\nnewline", "null").unwrap();
+        drop(journal);
+        let (_, done) = Journal::open(Some(&path), "migrate a -> b").unwrap();
+        assert_eq!(done.len(), 2);
+        assert!(done.contains(&("plain".to_string(), "v1".to_string())));
+        assert!(done.contains(&("with\ttab and\nnewline".to_string(), "null".to_string())));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn journal_refuses_other_migrations_and_bad_lines() {
+        let path = journal_path("other");
+        Journal::open(Some(&path), "migrate a -> b").unwrap();
+        assert!(Journal::open(Some(&path), "migrate a -> c").is_err());
+        fs::write(&path, "migrate a -> b\nno tab here\n").unwrap();
+        let err = Journal::open(Some(&path), "migrate a -> b").err().unwrap();
+        assert!(err.to_string().contains(":2:"));
+        fs::remove_file(&path).unwrap();
+        let (mut journal, done) = Journal::open(None, "migrate a -> b").unwrap();
+        assert!(done.is_empty() && journal.record("k", "v").is_ok());
+    }
+}
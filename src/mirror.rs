@@ -0,0 +1,138 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use aws_sdk_s3::Client;
+use notify::{Event, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use crate::errors::ErrorDetails;
+use crate::listing::list_all_objects;
+use crate::payer;
+use crate::prometheus;
+use crate::stop;
+use crate::sync::{self, Compare, SyncOptions};
+use crate::tree::{walk, EntryKind, LocalEntry, SymlinkMode};
+use crate::upload::{join_key, put_entry, PutOptions};
+
+/// the options Mirror syncs with before it starts watching, and uploads each change with after
+pub fn sync_options(symlinks: SymlinkMode, put: PutOptions) -> SyncOptions {
+    SyncOptions { compare: Compare::MtimeAndSize, symlinks, put, journal: None, failures_out: None, part_sizes: Vec::new(), chunked_over: None, chunk_size: 0 }
+}
+
+/// syncs local_dir to prefix, then keeps uploading changes until interrupted.
+/// changes are applied once no new events have arrived for the debounce interval
+pub async fn run(client: &Client, bucket_name: &str, local_dir: &str, prefix: &str, options: &SyncOptions, delete: bool, debounce: Duration) -> Result<(), Box<dyn Error>> {
+    let root = fs::canonicalize(local_dir)?;
+    sync::run(client, bucket_name, local_dir, prefix, options).await?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let _ = tx.send(event);
+    })?;
+    watcher.watch(&root, RecursiveMode::Recursive)?;
+    println!("watching {}", root.display());
+
+    let mut pending: BTreeSet<PathBuf> = BTreeSet::new();
+    loop {
+        let event = if pending.is_empty() {
+            stop::within(rx.recv()).await?
+        } else {
+            match stop::within(tokio::time::timeout(debounce, rx.recv())).await? {
+                Ok(event) => event,
+                Err(_) => {
+                    let paths = std::mem::take(&mut pending);
+                    for (done, path) in paths.iter().enumerate() {
+                        prometheus::set_queue_depth(paths.len() - done);
+                        if let Err(err) = apply_change(client, bucket_name, prefix, &root, path, options, delete).await {
+                            eprintln!("failed to mirror {}: {}", path.display(), ErrorDetails::from_error(err.as_ref()));
+                            prometheus::count_error();
+                        }
+                    }
+                    prometheus::set_queue_depth(0);
+                    continue;
+                }
+            }
+        };
+        match event {
+            Some(Ok(event)) if !event.kind.is_access() => {
+                pending.extend(event.paths);
+                prometheus::set_queue_depth(pending.len());
+            }
+            Some(Ok(_)) => {}
+            Some(Err(err)) => eprintln!("watch error: {}", err),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+async fn apply_change(client: &Client, bucket_name: &str, prefix: &str, root: &Path, path: &Path, options: &SyncOptions, delete: bool) -> Result<(), Box<dyn Error>> {
+    let (symlinks, put) = (options.symlinks, &options.put);
+    let Ok(relative) = path.strip_prefix(root) else { return Ok(()) };
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    if relative.is_empty() {
+        return Ok(());
+    }
+    let key = join_key(prefix, &relative);
+    let info = match fs::symlink_metadata(path) {
+        Ok(info) => info,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            if delete {
+                delete_path(client, bucket_name, &key).await?;
+            }
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut file_type = info.file_type();
+    if file_type.is_symlink() {
+        match symlinks {
+            SymlinkMode::Skip => return Ok(()),
+            SymlinkMode::Preserve => {
+                let entry = LocalEntry { kind: EntryKind::Symlink(fs::read_link(path)?), path: path.to_path_buf(), relative };
+                return upload(client, bucket_name, &key, &entry, put).await;
+            }
+            SymlinkMode::Follow => file_type = fs::metadata(path)?.file_type(),
+        }
+    }
+    if file_type.is_dir() {
+        // a directory moved into the tree only produces one event, so upload everything in it
+        for entry in walk(path, symlinks)? {
+            upload(client, bucket_name, &join_key(&key, &entry.relative), &entry, put).await?;
+        }
+    } else if file_type.is_file() {
+        let entry = LocalEntry { kind: EntryKind::File, path: path.to_path_buf(), relative };
+        upload(client, bucket_name, &key, &entry, put).await?;
+    }
+    Ok(())
+}
+
+async fn upload(client: &Client, bucket_name: &str, key: &str, entry: &LocalEntry, options: &PutOptions) -> Result<(), Box<dyn Error>> {
+    let result = put_entry(client, bucket_name, key, entry, options).await?;
+    println!("put {}: {}", key, result.version_id.as_deref().unwrap_or("null"));
+    Ok(())
+}
+
+/// deletes key, and everything under it in case the removed path was a directory. only keys the
+/// listing returns are deleted, so a versioned bucket doesn't get delete markers for keys it never had
+async fn delete_path(client: &Client, bucket_name: &str, key: &str) -> Result<(), Box<dyn Error>> {
+    let dir = format!("{}/", key);
+    let keys: Vec<String> = list_all_objects(client, bucket_name, key).await?
+        .into_iter()
+        .filter_map(|object| object.key)
+        .filter(|found| found == key || found.starts_with(&dir))
+        .collect();
+    for key in keys {
+        client.delete_object()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .key(&key)
+            .send()
+            .await?;
+        println!("deleted {}", key);
+    }
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::types::{ObjectOwnership, OwnershipControls, OwnershipControlsRule};
+use clap::{Subcommand, ValueEnum};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Ownership {
+    /// the bucket owner owns every object and ACLs are disabled
+    BucketOwnerEnforced,
+    /// the bucket owner owns objects uploaded with the bucket-owner-full-control ACL
+    BucketOwnerPreferred,
+    /// whoever uploads an object owns it
+    ObjectWriter,
+}
+
+impl Ownership {
+    fn sdk(self) -> ObjectOwnership {
+        match self {
+            Ownership::BucketOwnerEnforced => ObjectOwnership::BucketOwnerEnforced,
+            Ownership::BucketOwnerPreferred => ObjectOwnership::BucketOwnerPreferred,
+            Ownership::ObjectWriter => ObjectOwnership::ObjectWriter,
+        }
+    }
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum OwnershipAction {
+    /// show who owns objects uploaded to the bucket, and whether ACLs are enabled
+    Get,
+    /// change who owns uploaded objects. bucket-owner-enforced disables ACLs
+    Set {
+        #[arg(value_enum)]
+        ownership: Ownership,
+    },
+}
+
+pub async fn run(client: &Client, bucket_name: &str, action: &OwnershipAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        OwnershipAction::Get => {
+            let result = match client.get_bucket_ownership_controls().bucket(bucket_name).send().await {
+                Ok(result) => result,
+                Err(SdkError::ServiceError(err)) if err.err().code() == Some("OwnershipControlsNotFoundError") => {
+                    println!("{} has no ownership controls, so whoever uploads an object owns it and ACLs are enabled", bucket_name);
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let rules = result.ownership_controls().and_then(|controls| controls.rules()).unwrap_or_default();
+            for ownership in rules.iter().filter_map(|rule| rule.object_ownership()) {
+                let acls = if *ownership == ObjectOwnership::BucketOwnerEnforced { "disabled" } else { "enabled" };
+                println!("object ownership: {} (ACLs {})", ownership.as_str(), acls);
+            }
+        }
+        OwnershipAction::Set { ownership } => {
+            let controls = OwnershipControls::builder()
+                .rules(OwnershipControlsRule::builder().object_ownership(ownership.sdk()).build())
+                .build();
+            client.put_bucket_ownership_controls()
+                .bucket(bucket_name)
+                .ownership_controls(controls)
+                .send()
+                .await?;
+            println!("set object ownership of {} to {}", bucket_name, ownership.sdk().as_str());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use std::sync::OnceLock;
+use aws_sdk_s3::types::RequestPayer;
+
+static REQUEST_PAYER: OnceLock<RequestPayer> = OnceLock::new();
+
+/// sets the payer sent with every request that accepts one. only the first call has any effect
+pub fn set(payer: RequestPayer) {
+    let _ = REQUEST_PAYER.set(payer);
+}
+
+/// the payer to pass to set_request_payer, if --request-payer was given
+pub fn get() -> Option<RequestPayer> {
+    REQUEST_PAYER.get().cloned()
+}
@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::pin::pin;
+use aws_sdk_s3::Client;
+use futures_util::future::LocalBoxFuture;
+use futures_util::{FutureExt, TryStreamExt};
+use crate::listing::object_pages;
+use crate::payer;
+use crate::report::format_bytes;
+
+/// a directory or object in the tree, with the count and bytes of everything under it
+struct Node {
+    name: String,
+    is_dir: bool,
+    count: usize,
+    bytes: i64,
+    children: Vec<Node>,
+}
+
+/// the common prefixes and objects directly under prefix
+async fn list_level(client: &Client, bucket_name: &str, prefix: &str) -> Result<(Vec<String>, Vec<(String, i64)>), Box<dyn Error>> {
+    let mut dirs = Vec::new();
+    let mut objects = Vec::new();
+    let mut token = None;
+    loop {
+        let result = client.list_objects_v2()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .prefix(prefix)
+            .delimiter("/")
+            .set_continuation_token(token)
+            .send()
+            .await?;
+        for common in result.common_prefixes().unwrap_or_default() {
+            dirs.extend(common.prefix().map(str::to_string));
+        }
+        for object in result.contents().unwrap_or_default() {
+            // a directory marker is the prefix itself
+            match object.key() {
+                Some(key) if key != prefix => objects.push((key.to_string(), object.size())),
+                _ => {}
+            }
+        }
+        token = match (result.is_truncated, result.next_continuation_token) {
+            (true, Some(token)) => Some(token),
+            _ => return Ok((dirs, objects)),
+        };
+    }
+}
+
+/// the count and bytes of every object under prefix, for directories past the depth limit
+async fn totals(client: &Client, bucket_name: &str, prefix: &str) -> Result<(usize, i64), Box<dyn Error>> {
+    let mut count = 0;
+    let mut bytes = 0;
+    let mut pages = pin!(object_pages(client, bucket_name, prefix));
+    while let Some(page) = pages.try_next().await? {
+        for object in page.iter().filter(|object| object.key() != Some(prefix)) {
+            count += 1;
+            bytes += object.size();
+        }
+    }
+    Ok((count, bytes))
+}
+
+/// the directory node for prefix, listing depth more levels below it with one delimiter listing each
+fn walk<'a>(client: &'a Client, bucket_name: &'a str, prefix: String, name: String, depth: Option<usize>) -> LocalBoxFuture<'a, Result<Node, Box<dyn Error>>> {
+    async move {
+        if depth == Some(0) {
+            let (count, bytes) = totals(client, bucket_name, &prefix).await?;
+            return Ok(Node { name, is_dir: true, count, bytes, children: Vec::new() });
+        }
+        let (dirs, objects) = list_level(client, bucket_name, &prefix).await?;
+        let mut children = Vec::new();
+        for dir in dirs {
+            let child_name = dir[prefix.len()..].to_string();
+            children.push(walk(client, bucket_name, dir, child_name, depth.map(|depth| depth - 1)).await?);
+        }
+        for (key, size) in objects {
+            children.push(Node { name: key[prefix.len()..].to_string(), is_dir: false, count: 1, bytes: size, children: Vec::new() });
+        }
+        let count = children.iter().map(|child| child.count).sum();
+        let bytes = children.iter().map(|child| child.bytes).sum();
+        Ok(Node { name, is_dir: true, count, bytes, children })
+    }.boxed_local()
+}
+
+fn label(node: &Node) -> String {
+    if node.is_dir {
+        format!("{} ({} objects, {})", node.name, node.count, format_bytes(node.bytes))
+    } else {
+        format!("{} ({})", node.name, format_bytes(node.bytes))
+    }
+}
+
+fn print_children(node: &Node, indent: &str) {
+    for (idx, child) in node.children.iter().enumerate() {
+        let last = idx + 1 == node.children.len();
+        println!("{}{}{}", indent, if last { "└── " } else { "├── " }, label(child));
+        print_children(child, &format!("{}{}", indent, if last { "    " } else { "│   " }));
+    }
+}
+
+/// prints the prefixes and objects under prefix as an indented tree, with the object count and
+/// size of each directory. directories deeper than depth are summarized without their contents
+pub async fn run(client: &Client, bucket_name: &str, prefix: &str, depth: Option<usize>) -> Result<(), Box<dyn Error>> {
+    let name = if prefix.is_empty() { ".".to_string() } else { prefix.to_string() };
+    let root = walk(client, bucket_name, prefix.to_string(), name, depth).await?;
+    println!("{}", label(&root));
+    print_children(&root, "");
+    Ok(())
+}
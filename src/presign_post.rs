@@ -0,0 +1,89 @@
+use std::error::Error;
+use std::time::Duration;
+use aws_credential_types::cache::ProvideCachedCredentials;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::{json, Map, Value};
+use crate::clock;
+use crate::sigv4;
+
+/// the longest a SigV4 signature can be valid for
+pub const MAX_EXPIRES: u64 = 7 * 24 * 60 * 60;
+
+/// the URL a form posts to, which is a presigned URL for any key with the key and query taken off.
+/// presigning goes through the same endpoint resolution as requests, so path-style endpoints,
+/// access points and --accelerate all come out right
+async fn form_action(client: &Client, bucket_name: &str) -> Result<String, Box<dyn Error>> {
+    let presigned = client.put_object()
+        .bucket(bucket_name)
+        .key("k")
+        .presigned(PresigningConfig::expires_in(Duration::from_secs(60))?)
+        .await?;
+    let uri = presigned.uri();
+    let path = uri.path().strip_suffix("k").unwrap_or(uri.path());
+    Ok(format!("{}://{}{}", uri.scheme_str().unwrap_or("https"), uri.authority().map(|a| a.as_str()).unwrap_or_default(), path))
+}
+
+/// prints the form action, fields and policy document for browser uploads under prefix of at most
+/// max_size bytes, valid for expires seconds. a content_type ending in / allows any type that starts with it
+pub async fn run(client: &Client, bucket_name: &str, prefix: &str, max_size: u64, expires: u64, content_type: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let credentials = client.conf().credentials_cache().provide_cached_credentials().await?;
+    let region = client.conf().region().ok_or("no region is set")?;
+    let now = clock::signing_time();
+    let date = sigv4::amz_date(now)?;
+    let scope = format!("{}/{}/s3/aws4_request", &date[..8], region);
+    let credential = format!("{}/{}", credentials.access_key_id(), scope);
+    let expiration = DateTime::from_secs(now + expires as i64).fmt(DateTimeFormat::DateTime)?;
+
+    // the form fields, in the order a form should send them. the file has to come last
+    let mut fields = vec![("key".to_string(), format!("{}${{filename}}", prefix))];
+    let mut conditions = vec![
+        json!({"bucket": bucket_name}),
+        json!(["starts-with", "$key", prefix]),
+        json!(["content-length-range", 0, max_size]),
+    ];
+    match content_type {
+        Some(content_type) if content_type.ends_with('/') => {
+            conditions.push(json!(["starts-with", "$Content-Type", content_type]));
+            fields.push(("Content-Type".to_string(), format!("{}...", content_type)));
+        }
+        Some(content_type) => {
+            conditions.push(json!({"Content-Type": content_type}));
+            fields.push(("Content-Type".to_string(), content_type.to_string()));
+        }
+        None => {}
+    }
+    let mut signed = vec![
+        ("x-amz-algorithm", sigv4::ALGORITHM.to_string()),
+        ("x-amz-credential", credential),
+        ("x-amz-date", date.clone()),
+    ];
+    if let Some(token) = credentials.session_token() {
+        signed.push(("x-amz-security-token", token.to_string()));
+    }
+    for (name, value) in signed {
+        conditions.push(Value::Object(Map::from_iter([(name.to_string(), Value::from(value.as_str()))])));
+        fields.push((name.to_string(), value));
+    }
+    let policy = json!({"expiration": expiration, "conditions": conditions});
+    let encoded = STANDARD.encode(policy.to_string());
+    // the whole base64 policy is the string to sign
+    let signature = sigv4::signature(credentials.secret_access_key(), &scope, &encoded)?;
+    fields.push(("policy".to_string(), encoded));
+    fields.push(("x-amz-signature".to_string(), signature));
+
+    println!("POST {}\n", form_action(client, bucket_name).await?);
+    println!("form fields:");
+    for (name, value) in &fields {
+        println!("{}: {}", name, value);
+    }
+    println!("file: <the file, as the last field>\n");
+    println!("policy document:\n{}", serde_json::to_string_pretty(&policy)?);
+    if content_type.is_some_and(|content_type| content_type.ends_with('/')) {
+        println!("\nthe form's Content-Type must be set to the file's type, which has to start with {}", content_type.unwrap_or_default());
+    }
+    Ok(())
+}
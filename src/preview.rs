@@ -0,0 +1,86 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use crate::download;
+use crate::key;
+use crate::lines::fetch;
+use crate::payer;
+use crate::report::format_bytes;
+
+/// bytes in each hexdump row
+const ROW: usize = 16;
+
+/// whether content_type says the object is text, binary, or doesn't say
+fn declared_text(content_type: &str) -> Option<bool> {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    if essence.starts_with("text/") || essence.ends_with("+json") || essence.ends_with("+xml") {
+        return Some(true);
+    }
+    match essence.as_str() {
+        "application/json" | "application/xml" | "application/javascript" | "application/x-yaml" | "application/yaml"
+        | "application/toml" | "application/x-sh" | "application/sql" => Some(true),
+        // what uploads get when nothing better was known, so it says nothing either way
+        "" | "application/octet-stream" | "binary/octet-stream" => None,
+        _ => Some(false),
+    }
+}
+
+/// UTF-8 without NULs, allowing for a character cut off at the end of the sample
+fn looks_like_text(bytes: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    };
+    valid && !bytes.contains(&0)
+}
+
+/// offset, hex bytes and the printable ones, as hexdump -C shows them
+fn hexdump(bytes: &[u8]) {
+    for (row, chunk) in bytes.chunks(ROW).enumerate() {
+        let hex: Vec<String> = (0..ROW).map(|idx| chunk.get(idx).map_or("  ".to_string(), |b| format!("{:02x}", b))).collect();
+        let text: String = chunk.iter().map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' }).collect();
+        println!("{:08x}  {}  {}  |{}|", row * ROW, hex[..8].join(" "), hex[8..].join(" "), text);
+    }
+}
+
+/// prints the first limit bytes of name as text if it's text, going by its Content-Type and then
+/// by the bytes themselves, or as a hexdump if not. compressed and encrypted objects are shown as
+/// stored, which is always binary
+pub async fn preview(client: &Client, bucket_name: &str, name: &str, version_id: Option<&str>, limit: u64, hex: bool) -> Result<(), Box<dyn Error>> {
+    key::validate(name)?;
+    let head = client.head_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(name)
+        .set_version_id(version_id.map(str::to_string))
+        .send()
+        .await?;
+    let size = head.content_length().max(0) as u64;
+    let content_type = head.content_type().unwrap_or_default();
+    let shown = limit.min(size);
+    println!("{}: {}, {}{}", name, if content_type.is_empty() { "no content type" } else { content_type }, format_bytes(size as i64),
+             if shown < size { format!(", first {}", format_bytes(shown as i64)) } else { String::new() });
+    if let Some(encoding) = head.content_encoding() {
+        println!("stored with Content-Encoding {}", encoding);
+    }
+    if shown == 0 {
+        return Ok(());
+    }
+    let bytes = fetch(client, bucket_name, name, &head, (0, shown - 1)).await?;
+    let text = !hex && download::is_plain(&head, false) && match declared_text(content_type) {
+        // NULs mean a wrong content type rather than text in another encoding
+        Some(true) => !bytes.contains(&0),
+        Some(false) => false,
+        None => looks_like_text(&bytes),
+    };
+    println!();
+    if text {
+        let text = String::from_utf8_lossy(&bytes);
+        println!("{}", text.strip_suffix('\n').unwrap_or(&text));
+    } else {
+        hexdump(&bytes);
+    }
+    if shown < size {
+        println!("...");
+    }
+    Ok(())
+}
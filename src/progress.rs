@@ -0,0 +1,161 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use clap::ValueEnum;
+use serde_json::json;
+
+const BAR_WIDTH: usize = 30;
+
+/// the least time between JSON events for byte counts, which change with every chunk
+const EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum ProgressMode {
+    /// a progress bar, when stderr is a terminal
+    Bar,
+    /// one JSON object per line on stderr, for CI systems and GUIs to render
+    Json,
+    /// no progress at all
+    Off,
+}
+
+static MODE: OnceLock<ProgressMode> = OnceLock::new();
+
+/// sets how every progress display is shown. only the first call has any effect
+pub fn set_mode(mode: ProgressMode) {
+    let _ = MODE.set(mode);
+}
+
+fn mode() -> ProgressMode {
+    let mode = MODE.get().copied().unwrap_or(ProgressMode::Bar);
+    if mode == ProgressMode::Bar && !io::stderr().is_terminal() {
+        return ProgressMode::Off;
+    }
+    mode
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Unit {
+    Items,
+    Bytes,
+}
+
+impl Unit {
+    fn as_str(self) -> &'static str {
+        match self {
+            Unit::Items => "items",
+            Unit::Bytes => "bytes",
+        }
+    }
+}
+
+/// a single-line progress bar on stderr, or JSON lines with --progress json. draws nothing when
+/// stderr isn't a terminal, unless it's JSON
+pub struct Progress {
+    label: String,
+    /// the key being transferred, or the last one finished for counts of objects
+    key: Option<String>,
+    unit: Unit,
+    /// None when the total isn't known ahead of time, which draws a plain count
+    total: Option<u64>,
+    done: u64,
+    mode: ProgressMode,
+    started: Instant,
+    last_event: Option<Instant>,
+}
+
+impl Progress {
+    fn start(label: &str, key: Option<&str>, unit: Unit, total: Option<u64>, mode: ProgressMode) -> Progress {
+        let mut progress = Progress {
+            label: label.to_string(),
+            key: key.map(|key| key.to_string()),
+            unit,
+            total,
+            done: 0,
+            mode,
+            started: Instant::now(),
+            last_event: None,
+        };
+        progress.draw(false);
+        progress
+    }
+
+    /// progress towards a total that isn't known, as when working through a listing as it arrives
+    pub fn counter(label: &str) -> Progress {
+        Progress::start(label, None, Unit::Items, None, mode())
+    }
+
+    /// progress through objects for commands that print a line for each one, which would break up
+    /// a bar, so only JSON events are shown
+    pub fn objects(label: &str, total: Option<u64>) -> Progress {
+        let mode = match mode() {
+            ProgressMode::Bar => ProgressMode::Off,
+            mode => mode,
+        };
+        Progress::start(label, None, Unit::Items, total, mode)
+    }
+
+    /// progress through the total bytes of one object
+    pub fn bytes(label: &str, key: &str, total: u64) -> Progress {
+        Progress::start(label, Some(key), Unit::Bytes, Some(total), mode())
+    }
+
+    pub fn add(&mut self, count: u64) {
+        self.done += count;
+        self.draw(false);
+    }
+
+    /// counts key as done, so JSON events can say which one it was
+    pub fn add_key(&mut self, key: &str, count: u64) {
+        self.key = Some(key.to_string());
+        self.add(count);
+    }
+
+    fn draw(&mut self, finished: bool) {
+        match self.mode {
+            ProgressMode::Off => return,
+            ProgressMode::Json => return self.event(finished),
+            ProgressMode::Bar => {}
+        }
+        match self.total {
+            Some(total) => {
+                let filled = (self.done.min(total) * BAR_WIDTH as u64).checked_div(total).map_or(BAR_WIDTH, |f| f as usize);
+                eprint!("\r{} [{}{}] {}/{}", self.label, "#".repeat(filled), " ".repeat(BAR_WIDTH - filled), self.done, total);
+            }
+            None => eprint!("\r{} {}", self.label, self.done),
+        }
+        let _ = io::stderr().flush();
+    }
+
+    /// writes a JSON line with the counts so far and the rate per second since the start
+    fn event(&mut self, finished: bool) {
+        let now = Instant::now();
+        let recent = self.last_event.is_some_and(|last| now - last < EVENT_INTERVAL);
+        if self.unit == Unit::Bytes && recent && !finished {
+            return;
+        }
+        self.last_event = Some(now);
+        let elapsed = (now - self.started).as_secs_f64();
+        let rate = if elapsed > 0.0 { self.done as f64 / elapsed } else { 0.0 };
+        let event = json!({
+            "operation": self.label,
+            "key": self.key,
+            "unit": self.unit.as_str(),
+            "done": self.done,
+            "total": self.total,
+            "rate": rate,
+            "finished": finished,
+        });
+        eprintln!("{}", event);
+    }
+
+    /// ends the progress line so later output starts on a fresh line. in JSON, sends a last
+    /// event with finished set
+    pub fn finish(&mut self) {
+        match self.mode {
+            ProgressMode::Bar => eprintln!(),
+            ProgressMode::Json => self.event(true),
+            ProgressMode::Off => {}
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::future::{poll_fn, Future};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use http::{Method, Request, Response};
+use hyper::service::Service;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use crate::usage::content_length;
+
+/// the longest request head a scrape may send
+const MAX_HEAD: usize = 8192;
+
+/// requests by method and status, which is "error" when no response came back
+static REQUESTS: Mutex<BTreeMap<(String, String), u64>> = Mutex::new(BTreeMap::new());
+static IN_FLIGHT: AtomicI64 = AtomicI64::new(0);
+static SENT: AtomicU64 = AtomicU64::new(0);
+static RECEIVED: AtomicU64 = AtomicU64::new(0);
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+static QUEUE_DEPTH: AtomicI64 = AtomicI64::new(0);
+
+/// counts a request sent again, to another endpoint or after correcting for clock skew. the SDK
+/// itself is built without retries
+pub fn count_retry() {
+    RETRIES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// counts an operation the command gave up on, such as a file Mirror couldn't upload
+pub fn count_error() {
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// how much work the command has waiting, such as the changed paths Mirror hasn't uploaded yet
+pub fn set_queue_depth(depth: usize) {
+    QUEUE_DEPTH.store(depth as i64, Ordering::Relaxed);
+}
+
+/// counts every request and its bytes. it sits next to Metered, so each attempt is counted
+#[derive(Clone)]
+pub struct Counted {
+    inner: DynConnector,
+}
+
+impl Counted {
+    pub fn wrap(inner: DynConnector) -> DynConnector {
+        DynConnector::new(Counted { inner })
+    }
+}
+
+/// takes a request out of the in-flight gauge even if it's dropped half way
+struct InFlight;
+
+impl InFlight {
+    fn start() -> InFlight {
+        IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Service<Request<SdkBody>> for Counted {
+    type Response = Response<SdkBody>;
+    type Error = ConnectorError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<SdkBody>, ConnectorError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ConnectorError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<SdkBody>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        Box::pin(async move {
+            let method = request.method().to_string();
+            let head = request.method() == Method::HEAD;
+            SENT.fetch_add(content_length(request.headers()).or(request.body().content_length()).unwrap_or(0), Ordering::Relaxed);
+            let _in_flight = InFlight::start();
+            poll_fn(|cx| inner.poll_ready(cx)).await?;
+            let result = inner.call(request).await;
+            let status = match &result {
+                Ok(response) => {
+                    if !head {
+                        RECEIVED.fetch_add(content_length(response.headers()).unwrap_or(0), Ordering::Relaxed);
+                    }
+                    response.status().as_u16().to_string()
+                }
+                Err(_) => "error".to_string(),
+            };
+            *REQUESTS.lock().unwrap().entry((method, status)).or_default() += 1;
+            result
+        })
+    }
+}
+
+fn metric(text: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, String)]) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        let _ = writeln!(text, "{}{} {}", name, labels, value);
+    }
+}
+
+/// every metric, in the Prometheus text format
+fn render() -> String {
+    let requests: Vec<(String, String)> = REQUESTS.lock().unwrap().iter()
+        .map(|((method, status), count)| (format!("{{method=\"{}\",status=\"{}\"}}", method, status), count.to_string()))
+        .collect();
+    let bytes = vec![
+        ("{direction=\"sent\"}".to_string(), SENT.load(Ordering::Relaxed).to_string()),
+        ("{direction=\"received\"}".to_string(), RECEIVED.load(Ordering::Relaxed).to_string()),
+    ];
+    let one = |value: String| vec![(String::new(), value)];
+    let mut text = String::new();
+    metric(&mut text, "s3test_requests_total", "counter", "S3 requests by method and response status, or error when none came back", &requests);
+    metric(&mut text, "s3test_bytes_total", "counter", "request and response body bytes", &bytes);
+    metric(&mut text, "s3test_retries_total", "counter", "requests sent again after an unreachable endpoint or clock skew", &one(RETRIES.load(Ordering::Relaxed).to_string()));
+    metric(&mut text, "s3test_errors_total", "counter", "operations that failed and were given up on", &one(ERRORS.load(Ordering::Relaxed).to_string()));
+    metric(&mut text, "s3test_requests_in_flight", "gauge", "requests waiting on a response", &one(IN_FLIGHT.load(Ordering::Relaxed).to_string()));
+    metric(&mut text, "s3test_queue_depth", "gauge", "work waiting to be done", &one(QUEUE_DEPTH.load(Ordering::Relaxed).to_string()));
+    text
+}
+
+/// answers one scrape. anything but GET /metrics is a 404
+async fn answer(mut stream: TcpStream) -> Result<(), Box<dyn Error>> {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD {
+            return Err("oversized request".into());
+        }
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render()),
+        _ => ("404 Not Found", "only /metrics is served\n".to_string()),
+    };
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                           status, body.len(), body);
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// serves the metrics at http://addr/metrics for as long as the command runs
+pub async fn serve(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(addr).await.map_err(|err| format!("can't serve metrics on {}: {}", addr, err))?;
+    println!("serving metrics at http://{}/metrics", listener.local_addr()?);
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(async move {
+                        if let Err(err) = answer(stream).await {
+                            eprintln!("metrics: {}", err);
+                        }
+                    });
+                }
+                Err(err) => eprintln!("metrics: {}", err),
+            }
+        }
+    });
+    Ok(())
+}
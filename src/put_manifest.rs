@@ -0,0 +1,188 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::ChecksumAlgorithm;
+use futures_util::{stream, StreamExt};
+use serde_json::Value;
+use crate::errors::ErrorDetails;
+use crate::failures::{FailedOp, FailureLog, Operation};
+use crate::inventory::csv_field;
+use crate::key;
+use crate::payer;
+use crate::stop;
+use crate::upload::PutOptions;
+
+/// one file to upload, from line (or array index) `line` of the manifest
+struct Row {
+    line: usize,
+    path: PathBuf,
+    key: String,
+    content_type: Option<String>,
+    metadata: Vec<(String, String)>,
+}
+
+/// the fields of one CSV line. quoted fields may hold commas and doubled quotes, but not newlines
+fn csv_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            (true, '"') => quoted = false,
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted field".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// metadata written as key=value pairs separated by ';'
+fn parse_metadata(value: &str) -> Result<Vec<(String, String)>, String> {
+    value.split(';')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => Ok((key.trim().to_string(), value.to_string())),
+            _ => Err(format!("metadata {:?} isn't key=value", pair)),
+        })
+        .collect()
+}
+
+/// a CSV manifest with a header naming its columns: path and key, and optionally content_type and metadata
+fn parse_csv(text: &str) -> Result<Vec<Row>, Box<dyn Error>> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or("the manifest is empty")?;
+    let columns = csv_fields(header)?;
+    let column = |name: &str| columns.iter().position(|c| c.trim() == name);
+    let (Some(path_col), Some(key_col)) = (column("path"), column("key")) else {
+        return Err("the manifest header needs path and key columns".into());
+    };
+    let (type_col, metadata_col) = (column("content_type"), column("metadata"));
+    let mut rows = Vec::new();
+    for (idx, line) in lines {
+        let line_number = idx + 1;
+        let fields = csv_fields(line).map_err(|err| format!("line {}: {}", line_number, err))?;
+        let field = |col: Option<usize>| col.and_then(|col| fields.get(col)).filter(|value| !value.is_empty());
+        rows.push(Row {
+            line: line_number,
+            path: PathBuf::from(field(Some(path_col)).ok_or_else(|| format!("line {}: no path", line_number))?),
+            key: field(Some(key_col)).ok_or_else(|| format!("line {}: no key", line_number))?.clone(),
+            content_type: field(type_col).cloned(),
+            metadata: field(metadata_col).map(|m| parse_metadata(m)).transpose().map_err(|err| format!("line {}: {}", line_number, err))?.unwrap_or_default(),
+        });
+    }
+    Ok(rows)
+}
+
+/// a JSON array, or JSON lines, of {"path", "key", "content_type", "metadata": {..}} objects
+fn parse_json(text: &str) -> Result<Vec<Row>, Box<dyn Error>> {
+    let values: Vec<Value> = if text.trim_start().starts_with('[') {
+        serde_json::from_str(text)?
+    } else {
+        text.lines().filter(|line| !line.trim().is_empty()).map(serde_json::from_str).collect::<Result<_, _>>()?
+    };
+    let mut rows = Vec::new();
+    for (idx, value) in values.iter().enumerate() {
+        let line = idx + 1;
+        let string = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+        let mut metadata = Vec::new();
+        if let Some(entries) = value.get("metadata").and_then(Value::as_object) {
+            for (key, value) in entries {
+                let value = value.as_str().ok_or_else(|| format!("entry {}: metadata values must be strings", line))?;
+                metadata.push((key.clone(), value.to_string()));
+            }
+        }
+        rows.push(Row {
+            line,
+            path: PathBuf::from(string("path").ok_or_else(|| format!("entry {}: no path", line))?),
+            key: string("key").ok_or_else(|| format!("entry {}: no key", line))?,
+            content_type: string("content_type"),
+            metadata,
+        });
+    }
+    Ok(rows)
+}
+
+async fn put_row(client: &Client, bucket_name: &str, row: &Row) -> Result<Option<String>, Box<dyn Error>> {
+    key::validate(&row.key)?;
+    let bytes = tokio::fs::read(&row.path).await?;
+    let content_type = match &row.content_type {
+        Some(content_type) => content_type.clone(),
+        None => mime_guess::from_path(&row.path).first_or_octet_stream().to_string(),
+    };
+    let mut request = client.put_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(&row.key)
+        .content_type(content_type)
+        .checksum_algorithm(ChecksumAlgorithm::Sha256)
+        .body(ByteStream::from(bytes));
+    for (key, value) in &row.metadata {
+        request = request.metadata(key, value);
+    }
+    let result = request.send().await?;
+    Ok(result.version_id().map(str::to_string))
+}
+
+/// uploads every row of the manifest at manifest_path, concurrency at a time, printing each
+/// row's result. .json manifests are read as JSON, anything else as CSV. report gets a CSV of
+/// every row's outcome in manifest order
+pub async fn run(client: &Client, bucket_name: &str, manifest_path: &Path, concurrency: usize, report: Option<&Path>, failures_out: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let text = fs::read_to_string(manifest_path)?;
+    let rows = if manifest_path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json") || ext.eq_ignore_ascii_case("jsonl")) {
+        parse_json(&text)?
+    } else {
+        parse_csv(&text)?
+    };
+    let mut failures = FailureLog::new(failures_out)?;
+    let mut results = stream::iter(&rows)
+        .map(|row| async move { (row, put_row(client, bucket_name, row).await) })
+        .buffer_unordered(concurrency.max(1));
+    let mut outcomes = Vec::new();
+    while let Some((row, result)) = stop::within(results.next()).await? {
+        match &result {
+            Ok(version_id) => println!("row {}: put {}: {}", row.line, row.key, version_id.as_deref().unwrap_or("null")),
+            // the failure log prints the failure when it isn't writing them to a file
+            Err(err) => {
+                failures.record(FailedOp {
+                    operation: Operation::Put,
+                    bucket: bucket_name.to_string(),
+                    key: row.key.clone(),
+                    version_id: None,
+                    local_path: Some(row.path.clone()),
+                    symlink_target: None,
+                    put_options: Some(PutOptions { content_type: row.content_type.clone(), metadata: row.metadata.clone(), ..Default::default() }),
+                    error: ErrorDetails::from_error(err.as_ref()),
+                })?;
+            }
+        }
+        outcomes.push((row, result.map_err(|err| err.to_string())));
+    }
+    if let Some(report) = report {
+        outcomes.sort_by_key(|(row, _)| row.line);
+        let mut out = BufWriter::new(File::create(report)?);
+        writeln!(out, "row,path,key,status,version_id,error")?;
+        for (row, outcome) in &outcomes {
+            let (status, version_id, error) = match outcome {
+                Ok(version_id) => ("ok", version_id.clone().unwrap_or_default(), String::new()),
+                Err(err) => ("failed", String::new(), err.clone()),
+            };
+            writeln!(out, "{},{},{},{},{},{}", row.line, csv_field(&row.path.to_string_lossy()), csv_field(&row.key), status, csv_field(&version_id), csv_field(&error))?;
+        }
+        out.flush()?;
+    }
+    println!("uploaded {} of {} rows, failed {}", rows.len() - failures.count(), rows.len(), failures.count());
+    failures.finish(rows.len())
+}
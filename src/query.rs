@@ -0,0 +1,137 @@
+use std::error::Error;
+use serde_json::Value;
+
+/// one step of a path
+#[derive(Clone, Debug, PartialEq)]
+enum Step {
+    Field(String),
+    /// counts back from the end when negative
+    Index(i64),
+    /// every element of an array, or every value of an object
+    Each,
+}
+
+/// the paths jq writes, such as .db.hosts[0].name, .items[].id or .["odd key"]. steps may be
+/// split up with |, which does the same as writing them one after the other
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+/// a quoted name and what follows its closing quote. backslash escapes the next character
+fn quoted(rest: &str) -> Result<(String, &str), String> {
+    let mut name = String::new();
+    let mut chars = rest.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Ok((name, &rest[idx + 1..])),
+            '\\' => name.push(chars.next().ok_or("unterminated string")?.1),
+            _ => name.push(c),
+        }
+    }
+    Err("unterminated string".to_string())
+}
+
+/// parses a query, for --jq
+pub fn parse(text: &str) -> Result<Query, String> {
+    let mut steps = Vec::new();
+    let mut rest = text.trim();
+    if !rest.starts_with('.') {
+        return Err(format!("{} doesn't start with '.'", text));
+    }
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('|') {
+            rest = after.trim_start();
+            if !rest.starts_with('.') {
+                return Err(format!("expected '.' after '|' in {}", text));
+            }
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix('[') {
+            let (inside, after) = after.split_once(']').ok_or_else(|| format!("unclosed '[' in {}", text))?;
+            let inside = inside.trim();
+            let step = if inside.is_empty() {
+                Step::Each
+            } else if let Some(name) = inside.strip_prefix('"') {
+                let (name, trailing) = quoted(name)?;
+                if !trailing.trim().is_empty() {
+                    return Err(format!("unexpected {} in {}", trailing, text));
+                }
+                Step::Field(name)
+            } else {
+                Step::Index(inside.parse().map_err(|_| format!("{} isn't an index", inside))?)
+            };
+            steps.push(step);
+            rest = after.trim_start();
+            continue;
+        }
+        let Some(after) = rest.strip_prefix('.') else {
+            return Err(format!("unexpected {} in {}", rest, text));
+        };
+        if let Some(name) = after.strip_prefix('"') {
+            let (name, after) = quoted(name)?;
+            steps.push(Step::Field(name));
+            rest = after.trim_start();
+            continue;
+        }
+        let end = after.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-')).unwrap_or(after.len());
+        if end > 0 {
+            steps.push(Step::Field(after[..end].to_string()));
+        }
+        rest = after[end..].trim_start();
+    }
+    Ok(Query { steps })
+}
+
+impl Query {
+    /// the values the query selects from value. as in jq, a missing field or index is null, but
+    /// asking for a field of something that isn't an object is an error
+    pub fn run(&self, value: &Value) -> Result<Vec<Value>, Box<dyn Error>> {
+        let mut values = vec![value.clone()];
+        for step in &self.steps {
+            let mut next = Vec::new();
+            for value in values {
+                match (step, value) {
+                    (Step::Field(_) | Step::Index(_), Value::Null) => next.push(Value::Null),
+                    (Step::Field(name), Value::Object(mut map)) => next.push(map.remove(name).unwrap_or(Value::Null)),
+                    (Step::Index(idx), Value::Array(items)) => {
+                        let idx = if *idx < 0 { items.len() as i64 + idx } else { *idx };
+                        next.push(usize::try_from(idx).ok().and_then(|idx| items.get(idx)).cloned().unwrap_or(Value::Null));
+                    }
+                    (Step::Each, Value::Array(items)) => next.extend(items),
+                    (Step::Each, Value::Object(map)) => next.extend(map.into_iter().map(|(_, value)| value)),
+                    (Step::Field(name), other) => return Err(format!("can't get field {} of {}", name, kind(&other)).into()),
+                    (Step::Index(idx), other) => return Err(format!("can't get index {} of {}", idx, kind(&other)).into()),
+                    (Step::Each, other) => return Err(format!("can't iterate over {}", kind(&other)).into()),
+                }
+            }
+            values = next;
+        }
+        Ok(values)
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// each result on its own line, pretty-printed as jq prints them. raw writes strings without
+/// quotes, as jq -r does
+pub fn render(results: &[Value], raw: bool) -> Result<String, Box<dyn Error>> {
+    let mut text = String::new();
+    for result in results {
+        match result {
+            Value::String(s) if raw => text.push_str(s),
+            _ => text.push_str(&serde_json::to_string_pretty(result)?),
+        }
+        text.push('\n');
+    }
+    Ok(text)
+}
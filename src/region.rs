@@ -0,0 +1,41 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::config::Region;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::types::BucketLocationConstraint;
+
+/// the region S3 says the bucket is in, when a request failed because it was signed for another one
+pub fn redirect_hint<E>(err: &SdkError<E>, configured: Option<&Region>) -> Option<Region> {
+    let response = err.raw_response()?.http();
+    // 301 for requests to the wrong regional endpoint, 400 for the wrong region in the signature
+    if !matches!(response.status().as_u16(), 301 | 400) {
+        return None;
+    }
+    let region = response.headers().get("x-amz-bucket-region")?.to_str().ok()?;
+    if configured.is_some_and(|configured| configured.as_ref() == region) {
+        return None;
+    }
+    Some(Region::new(region.to_string()))
+}
+
+/// the region name for a GetBucketLocation result, which uses an empty constraint for
+/// us-east-1 and EU for eu-west-1
+pub fn location_name(constraint: Option<&BucketLocationConstraint>) -> &str {
+    match constraint.map(|c| c.as_str()) {
+        None | Some("") => "us-east-1",
+        Some("EU") => "eu-west-1",
+        Some(region) => region,
+    }
+}
+
+/// the region bucket_name is in, from GetBucketLocation, or from the redirect when S3 wants that
+/// asked of the bucket's own region
+pub async fn lookup(client: &Client, bucket_name: &str) -> Result<String, Box<dyn Error>> {
+    match client.get_bucket_location().bucket(bucket_name).send().await {
+        Ok(result) => Ok(location_name(result.location_constraint()).to_string()),
+        Err(err) => match redirect_hint(&err, client.conf().region()) {
+            Some(region) => Ok(region.to_string()),
+            None => Err(err.into()),
+        },
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use crate::config;
+
+/// the regions buckets were found in, kept next to the config file so later runs can sign for
+/// the right region from the start. entries are per endpoint, since bucket names are only
+/// unique within one
+pub struct RegionCache {
+    path: Option<PathBuf>,
+    endpoint: String,
+    /// (endpoint, bucket) to region, for every endpoint so saving keeps the others
+    entries: BTreeMap<(String, String), String>,
+}
+
+impl RegionCache {
+    /// the cache for buckets at endpoint, or AWS's own endpoints when there's none. a missing or
+    /// unreadable file is an empty cache
+    pub fn load(endpoint: Option<&str>) -> RegionCache {
+        let path = config::path().and_then(|config| Some(config.parent()?.join("regions")));
+        let text = path.as_ref().and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+        let entries = text.lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let (endpoint, bucket, region) = (fields.next()?, fields.next()?, fields.next()?);
+                Some(((endpoint.to_string(), bucket.to_string()), region.to_string()))
+            })
+            .collect();
+        RegionCache { path, endpoint: endpoint.unwrap_or("aws").to_string(), entries }
+    }
+
+    pub fn get(&self, bucket_name: &str) -> Option<&str> {
+        self.entries.get(&(self.endpoint.clone(), bucket_name.to_string())).map(|region| region.as_str())
+    }
+
+    /// records where bucket_name is and rewrites the file, atomically so concurrent runs don't see
+    /// half of it
+    pub fn save(&mut self, bucket_name: &str, region: &str) -> Result<(), Box<dyn Error>> {
+        if self.get(bucket_name) == Some(region) {
+            return Ok(());
+        }
+        self.entries.insert((self.endpoint.clone(), bucket_name.to_string()), region.to_string());
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        for ((endpoint, bucket), region) in &self.entries {
+            writeln!(file, "{}\t{}\t{}", endpoint, bucket, region)?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use aws_sdk_s3::Client;
+use crate::listing::{list_all_objects, list_all_versions};
+
+/// prints a storage report for the whole bucket
+pub async fn run(client: &Client, bucket_name: &str, top: usize, by_prefix: bool, include_versions: bool) -> Result<(), Box<dyn Error>> {
+    // (key, size) of the current version of every object
+    let mut current: Vec<(String, i64)> = Vec::new();
+    let mut noncurrent_bytes: i64 = 0;
+    let mut noncurrent_count = 0;
+    let mut marker_count = 0;
+    if include_versions {
+        let (versions, markers) = list_all_versions(client, bucket_name, "").await?;
+        for version in versions {
+            if version.is_latest() {
+                current.push((version.key().unwrap_or("<none>").to_string(), version.size()));
+            } else {
+                noncurrent_bytes += version.size();
+                noncurrent_count += 1;
+            }
+        }
+        marker_count = markers.len();
+    } else {
+        for object in list_all_objects(client, bucket_name, "").await? {
+            current.push((object.key().unwrap_or("<none>").to_string(), object.size()));
+        }
+    }
+
+    let current_bytes: i64 = current.iter().map(|(_, size)| size).sum();
+    println!("objects: {} ({})", current.len(), format_bytes(current_bytes));
+
+    current.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    println!("top {} largest objects:", top.min(current.len()));
+    for (key, size) in current.iter().take(top) {
+        println!("  {:>12}  {}", format_bytes(*size), key);
+    }
+
+    if by_prefix {
+        let mut prefixes: BTreeMap<&str, (usize, i64)> = BTreeMap::new();
+        for (key, size) in &current {
+            let entry = prefixes.entry(top_level_prefix(key)).or_default();
+            entry.0 += 1;
+            entry.1 += size;
+        }
+        println!("bytes by prefix:");
+        for (prefix, (count, size)) in prefixes {
+            println!("  {:>12}  {} ({} objects)", format_bytes(size), prefix, count);
+        }
+    }
+
+    if include_versions {
+        println!("current versions: {} ({})", current.len(), format_bytes(current_bytes));
+        println!("noncurrent versions: {} ({})", noncurrent_count, format_bytes(noncurrent_bytes));
+        println!("delete markers: {}", marker_count);
+    }
+    Ok(())
+}
+
+/// the part of key up to and including the first '/', or "/" for keys at the root
+fn top_level_prefix(key: &str) -> &str {
+    match key.find('/') {
+        Some(idx) => &key[..=idx],
+        None => "/",
+    }
+}
+
+/// formats a byte count using binary units
+pub fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    format!("{:.1} {}", value, unit)
+}
@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+const HEADER: &str = "# s3test resume token";
+
+/// where a listing of one bucket and prefix got to, kept in a file so a later run can carry on from there
+pub struct ResumeFile<'a> {
+    pub path: &'a Path,
+    bucket_name: &'a str,
+    prefix: &'a str,
+}
+
+impl<'a> ResumeFile<'a> {
+    pub fn new(path: &'a Path, bucket_name: &'a str, prefix: &'a str) -> ResumeFile<'a> {
+        ResumeFile { path, bucket_name, prefix }
+    }
+
+    fn header(&self) -> String {
+        format!("{}\t{}\t{}", HEADER, self.bucket_name, urlencoding::encode(self.prefix))
+    }
+
+    /// the continuation token an earlier run saved. None if there's no file, or it was written for a
+    /// different bucket or prefix
+    pub fn load(&self) -> Result<Option<String>, Box<dyn Error>> {
+        let text = match fs::read_to_string(self.path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut lines = text.lines();
+        if lines.next() != Some(self.header().as_str()) {
+            println!("resume token file {} is for a different bucket or prefix, starting from the beginning", self.path.display());
+            return Ok(None);
+        }
+        match lines.next() {
+            Some(token) if !token.is_empty() => Ok(Some(token.to_string())),
+            _ => Err(format!("{}: no continuation token", self.path.display()).into()),
+        }
+    }
+
+    /// saves token as the place to resume from, replacing the file atomically. a None token means
+    /// the listing is finished, so the file is removed
+    pub fn save(&self, token: Option<&str>) -> Result<(), Box<dyn Error>> {
+        let Some(token) = token else {
+            return match fs::remove_file(self.path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            };
+        };
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        writeln!(file, "{}", self.header())?;
+        writeln!(file, "{}", token)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, self.path)?;
+        Ok(())
+    }
+}
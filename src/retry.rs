@@ -0,0 +1,54 @@
+use std::error::Error;
+use std::path::Path;
+use aws_sdk_s3::Client;
+use crate::download::download_to;
+use crate::errors::ErrorDetails;
+use crate::failures::{self, FailedOp, FailureLog, Operation};
+use crate::payer;
+use crate::stop;
+use crate::tree::{EntryKind, LocalEntry};
+use crate::upload::put_entry;
+
+/// replays every operation in a failures file, recording the ones that fail again
+pub async fn run(client: &Client, failures_file: &Path, failures_out: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let ops = failures::read(failures_file)?;
+    let mut failures = FailureLog::new(failures_out)?;
+    for op in &ops {
+        match stop::within(replay(client, op)).await? {
+            Ok(()) => println!("{} {}: ok", op.operation.as_str(), op.key),
+            Err(err) => failures.record(FailedOp { error: ErrorDetails::from_error(err.as_ref()), ..op.clone() })?,
+        }
+    }
+    println!("retried {}, failed again {}", ops.len(), failures.count());
+    failures.finish(ops.len())
+}
+
+async fn replay(client: &Client, op: &FailedOp) -> Result<(), Box<dyn Error>> {
+    match op.operation {
+        Operation::Put => {
+            let path = op.local_path.clone().ok_or("put without local_path")?;
+            // uploading it without the ACL, headers or metadata it was meant to have would store a different object
+            let options = op.put_options.as_ref().ok_or("the failure doesn't record how the put was made, so upload it again instead")?;
+            let kind = match &op.symlink_target {
+                Some(target) => EntryKind::Symlink(target.clone()),
+                None => EntryKind::File,
+            };
+            let entry = LocalEntry { path, relative: String::new(), kind };
+            put_entry(client, &op.bucket, &op.key, &entry, options).await?;
+        }
+        Operation::Get => {
+            let path = op.local_path.as_ref().ok_or("get without local_path")?;
+            download_to(client, &op.bucket, &op.key, op.version_id.as_deref(), path).await?;
+        }
+        Operation::Delete => {
+            client.delete_object()
+                .bucket(&op.bucket)
+                .set_request_payer(payer::get())
+                .key(&op.key)
+                .set_version_id(op.version_id.clone())
+                .send()
+                .await?;
+        }
+    }
+    Ok(())
+}
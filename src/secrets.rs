@@ -0,0 +1,29 @@
+use std::error::Error;
+use keyring::Entry;
+
+/// the keyring service entries are stored under. the user is the profile name
+const SERVICE: &str = "s3test";
+
+/// saves a profile's keys in the OS keyring, as one entry holding both
+pub fn store(profile: &str, access_key: &str, secret_key: &str) -> Result<(), Box<dyn Error>> {
+    let entry = Entry::new(SERVICE, profile)?;
+    entry.set_password(&format!("{}\n{}", access_key, secret_key))
+        .map_err(|err| format!("couldn't save to the keyring: {}", err))?;
+    Ok(())
+}
+
+/// the access and secret key stored for a profile. a keyring that's missing or can't be
+/// reached, as on headless machines, is treated as holding nothing
+pub fn load(profile: &str) -> Option<(String, String)> {
+    let password = Entry::new(SERVICE, profile).ok()?.get_password().ok()?;
+    let (access_key, secret_key) = password.split_once('\n')?;
+    Some((access_key.to_string(), secret_key.to_string()))
+}
+
+/// removes a profile's keys from the keyring. a profile with nothing stored is left as it is
+pub fn delete(profile: &str) -> Result<(), Box<dyn Error>> {
+    match Entry::new(SERVICE, profile)?.delete_password() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(err) => Err(format!("couldn't remove the keys from the keyring: {}", err).into()),
+    }
+}
@@ -0,0 +1,216 @@
+use std::error::Error;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use aws_credential_types::provider::{ProvideCredentials, SharedCredentialsProvider};
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use clap::ValueEnum;
+use http::{HeaderMap, HeaderValue, Method, Request, Response, Uri};
+use hyper::service::Service;
+use ring::hmac;
+use crate::clock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SignatureVersion {
+    /// the legacy HMAC-SHA1 scheme, for old Ceph/RGW and appliances
+    V2,
+    /// what the SDK signs with
+    V4,
+}
+
+/// query parameters that are part of the signed resource. everything else is left out
+const SUBRESOURCES: &[&str] = &[
+    "acl", "cors", "delete", "lifecycle", "location", "logging", "notification", "partNumber", "policy",
+    "requestPayment", "response-cache-control", "response-content-disposition", "response-content-encoding",
+    "response-content-language", "response-content-type", "response-expires", "restore", "tagging", "torrent",
+    "uploadId", "uploads", "versionId", "versioning", "versions", "website",
+];
+
+/// headers only SigV4 uses, which V2 endpoints can choke on
+const V4_HEADERS: &[&str] = &["x-amz-date", "x-amz-content-sha256"];
+
+/// the plain headers in the string to sign. --header adds its headers after signing, so it can't
+/// set these with V2
+pub const SIGNED_HEADERS: &[&str] = &["content-md5", "content-type", "date"];
+
+/// /bucket/key?subresources for the string to sign. virtual-hosted requests get the bucket
+/// from the part of the host in front of one of endpoint_hosts
+pub fn canonical_resource(uri: &Uri, endpoint_hosts: &[String]) -> String {
+    let host = uri.host().unwrap_or_default();
+    let bucket = endpoint_hosts.iter()
+        .find_map(|endpoint| host.strip_suffix(endpoint.as_str())?.strip_suffix('.'))
+        // AWS's own endpoints look like bucket.s3.region.amazonaws.com
+        .or_else(|| if endpoint_hosts.is_empty() { host.split_once(".s3").map(|(bucket, _)| bucket) } else { None });
+    let mut resource = match bucket {
+        Some(bucket) if !bucket.is_empty() => format!("/{}{}", bucket, uri.path()),
+        _ => uri.path().to_string(),
+    };
+    let mut params: Vec<&str> = uri.query().unwrap_or_default()
+        .split('&')
+        .filter(|param| SUBRESOURCES.contains(&param.split('=').next().unwrap_or_default()))
+        .collect();
+    params.sort();
+    if !params.is_empty() {
+        resource.push('?');
+        resource.push_str(&params.join("&"));
+    }
+    resource
+}
+
+pub fn string_to_sign(method: &Method, headers: &HeaderMap, resource: &str) -> String {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default().trim().to_string();
+    let mut amz_headers: Vec<(String, String)> = Vec::new();
+    for (name, value) in headers {
+        if !name.as_str().starts_with("x-amz-") {
+            continue;
+        }
+        let value = value.to_str().unwrap_or_default().trim();
+        match amz_headers.iter_mut().find(|(existing, _)| existing == name.as_str()) {
+            Some((_, values)) => {
+                values.push(',');
+                values.push_str(value);
+            }
+            None => amz_headers.push((name.to_string(), value.to_string())),
+        }
+    }
+    amz_headers.sort();
+    let mut sts = format!("{}\n{}\n{}\n{}\n", method, header("content-md5"), header("content-type"), header("date"));
+    for (name, value) in amz_headers {
+        sts.push_str(&format!("{}:{}\n", name, value));
+    }
+    sts.push_str(resource);
+    sts
+}
+
+pub fn signature(secret_key: &str, string_to_sign: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret_key.as_bytes());
+    STANDARD.encode(hmac::sign(&key, string_to_sign.as_bytes()))
+}
+
+/// replaces a request's SigV4 signature with a V2 one, dated secs
+pub fn sign(request: &mut Request<SdkBody>, access_key: &str, secret_key: &str, secs: i64, endpoint_hosts: &[String]) -> Result<(), Box<dyn Error>> {
+    let headers = request.headers_mut();
+    for name in V4_HEADERS {
+        headers.remove(*name);
+    }
+    headers.insert("date", HeaderValue::from_str(&DateTime::from_secs(secs).fmt(DateTimeFormat::HttpDate)?)?);
+    let resource = canonical_resource(request.uri(), endpoint_hosts);
+    let sts = string_to_sign(request.method(), request.headers(), &resource);
+    let value = format!("AWS {}:{}", access_key, signature(secret_key, &sts));
+    request.headers_mut().insert("authorization", HeaderValue::from_str(&value)?);
+    Ok(())
+}
+
+/// re-signs every signed request with SigV2 just before it's sent, so retargeting and skew
+/// correction above it have already happened. streaming uploads with trailing checksums are V4 only
+#[derive(Clone)]
+pub struct SigV2 {
+    inner: DynConnector,
+    credentials: SharedCredentialsProvider,
+    endpoint_hosts: Arc<Vec<String>>,
+}
+
+impl SigV2 {
+    pub fn wrap(inner: DynConnector, credentials: SharedCredentialsProvider, endpoints: &[String]) -> DynConnector {
+        let endpoint_hosts = endpoints.iter()
+            .filter_map(|endpoint| endpoint.parse::<Uri>().ok()?.host().map(|host| host.to_string()))
+            .collect();
+        DynConnector::new(SigV2 { inner, credentials, endpoint_hosts: Arc::new(endpoint_hosts) })
+    }
+}
+
+impl Service<Request<SdkBody>> for SigV2 {
+    type Response = Response<SdkBody>;
+    type Error = ConnectorError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<SdkBody>, ConnectorError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ConnectorError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<SdkBody>) -> Self::Future {
+        if !request.headers().contains_key("authorization") {
+            return Box::pin(self.inner.call(request));
+        }
+        let mut inner = self.inner.clone();
+        let credentials = self.credentials.clone();
+        let endpoint_hosts = self.endpoint_hosts.clone();
+        Box::pin(async move {
+            let credentials = credentials.provide_credentials().await
+                .map_err(|err| ConnectorError::other(err.into(), None))?;
+            sign(&mut request, credentials.access_key_id(), credentials.secret_access_key(), clock::signing_time(), &endpoint_hosts)
+                .map_err(|err| ConnectorError::other(err.to_string().into(), None))?;
+            poll_fn(|cx| inner.poll_ready(cx)).await?;
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(uri: &str, endpoint_hosts: &[&str]) -> String {
+        let hosts: Vec<String> = endpoint_hosts.iter().map(|h| h.to_string()).collect();
+        canonical_resource(&uri.parse().unwrap(), &hosts)
+    }
+
+    #[test]
+    fn resource_keeps_only_sorted_subresources() {
+        assert_eq!(resource("http://minio:9000/bkt/a%20b?uploadId=U&partNumber=2&x-id=UploadPart", &["minio"]),
+                   "/bkt/a%20b?partNumber=2&uploadId=U");
+        assert_eq!(resource("http://minio:9000/bkt?versioning", &["minio"]), "/bkt?versioning");
+        assert_eq!(resource("http://minio:9000/bkt/k?list-type=2&prefix=p", &["minio"]), "/bkt/k");
+    }
+
+    #[test]
+    fn resource_finds_virtual_hosted_buckets() {
+        assert_eq!(resource("http://bkt.rgw.local/k", &["rgw.local"]), "/bkt/k");
+        assert_eq!(resource("http://rgw.local/bkt/k", &["rgw.local"]), "/bkt/k");
+        assert_eq!(resource("https://bkt.s3.us-east-1.amazonaws.com/k", &[]), "/bkt/k");
+    }
+
+    #[test]
+    fn string_to_sign_folds_and_sorts_amz_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        headers.insert("date", HeaderValue::from_static("Tue, 27 Mar 2007 21:15:45 +0000"));
+        headers.insert("x-amz-meta-b", HeaderValue::from_static(" two "));
+        headers.append("x-amz-meta-a", HeaderValue::from_static("1"));
+        headers.append("x-amz-meta-a", HeaderValue::from_static("2"));
+        headers.insert("cache-control", HeaderValue::from_static("no-cache"));
+        assert_eq!(string_to_sign(&Method::PUT, &headers, "/bkt/k"),
+                   "PUT\n\ntext/plain\nTue, 27 Mar 2007 21:15:45 +0000\nx-amz-meta-a:1,2\nx-amz-meta-b:two\n/bkt/k");
+    }
+
+    #[test]
+    fn signs_the_aws_example() {
+        // the GET object example from the S3 REST authentication documentation
+        let sts = "GET\n\n\nTue, 27 Mar 2007 19:36:42 +0000\n/awsexamplebucket1/photos/puppy.jpg";
+        assert_eq!(signature("wJalrXUtnFEMI/K7MDENG/bPxRfiCYEXAMPLEKEY", sts), "qgk2+6Sv9/oM7G3qLEjTH1a1l1g=");
+    }
+
+    #[test]
+    fn sign_replaces_the_v4_headers() {
+        let mut request = Request::builder()
+            .method("GET")
+            .uri("http://minio:9000/bkt/k")
+            .header("x-amz-date", "20070327T193642Z")
+            .header("x-amz-content-sha256", "UNSIGNED-PAYLOAD")
+            .header("authorization", "AWS4-HMAC-SHA256 Credential=AK/x")
+            .body(SdkBody::empty())
+            .unwrap();
+        sign(&mut request, "AK", "secret", 1175024202, &["minio".to_string()]).unwrap();
+        let headers = request.headers();
+        assert!(!headers.contains_key("x-amz-date") && !headers.contains_key("x-amz-content-sha256"));
+        assert_eq!(headers["date"], "Tue, 27 Mar 2007 19:36:42 GMT");
+        let sts = "GET\n\n\nTue, 27 Mar 2007 19:36:42 GMT\n/bkt/k";
+        assert_eq!(headers["authorization"].to_str().unwrap(), format!("AWS AK:{}", signature("secret", sts)));
+    }
+}
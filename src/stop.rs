@@ -0,0 +1,139 @@
+use std::error::Error;
+use std::future::Future;
+use std::process;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use crate::usage;
+
+/// how long after the deadline a run that hasn't stopped on its own is killed. long enough for
+/// an in-flight request to give up and journals and failure logs to be written
+const GRACE: Duration = Duration::from_secs(30);
+
+/// the same after Ctrl-C, which someone is waiting on. commands other than bulk ones only stop this way
+const INTERRUPT_GRACE: Duration = Duration::from_secs(5);
+
+/// the exit status for a run ended by Ctrl-C, as shells report SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INTERRUPT: Notify = Notify::const_new();
+
+/// parses a duration such as 90s, 30m, 2h or 1d. a bare number is seconds
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => value.split_at(idx),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("{} isn't a duration like 90s, 30m or 2h", value))?;
+    let unit_secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("unknown unit {} in {}. use s, m, h or d", unit, value)),
+    };
+    let secs = number.checked_mul(unit_secs).ok_or_else(|| format!("{} is too long", value))?;
+    if secs == 0 {
+        return Err("the duration must be more than zero".to_string());
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+/// exits once grace has passed since now, for runs that don't stop on their own
+fn exit_after(grace: Duration, reason: &'static str, status: i32) {
+    tokio::spawn(async move {
+        tokio::time::sleep(grace).await;
+        eprintln!("error: still running {}s after {}, exiting", grace.as_secs(), reason);
+        usage::record();
+        process::exit(status);
+    });
+}
+
+/// starts the clock on the whole run. bulk commands stop cleanly once it passes, and anything
+/// still running GRACE later exits. only the first call has any effect
+pub fn set_deadline(after: Duration) {
+    // a deadline past what an Instant can hold never comes
+    let Some(deadline) = Instant::now().checked_add(after) else {
+        return;
+    };
+    if DEADLINE.set(deadline).is_err() {
+        return;
+    }
+    tokio::spawn(async move {
+        tokio::time::sleep_until(deadline.into()).await;
+        exit_after(GRACE, "the deadline", 1);
+    });
+}
+
+/// has the first Ctrl-C stop bulk commands like the deadline does, so they write their journals,
+/// failure logs and summaries. a second Ctrl-C exits at once
+pub fn handle_interrupts() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        eprintln!("interrupted, stopping. press Ctrl-C again to quit now");
+        INTERRUPTED.store(true, Ordering::SeqCst);
+        INTERRUPT.notify_waiters();
+        exit_after(INTERRUPT_GRACE, "Ctrl-C", EXIT_INTERRUPTED);
+        if tokio::signal::ctrl_c().await.is_ok() {
+            usage::record();
+            process::exit(EXIT_INTERRUPTED);
+        }
+    });
+}
+
+/// the exit status for a run that failed, which says so when it was interrupted
+pub fn error_status() -> i32 {
+    if INTERRUPTED.load(Ordering::SeqCst) { EXIT_INTERRUPTED } else { 1 }
+}
+
+async fn interrupted() {
+    loop {
+        let notified = INTERRUPT.notified();
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// waits for future, or returns an error once the deadline passes or Ctrl-C is pressed, dropping
+/// it along with the requests it was waiting on. callers clean up as they would after any other error
+pub async fn within<T>(future: impl Future<Output = T>) -> Result<T, Box<dyn Error>> {
+    let deadline = async {
+        match DEADLINE.get() {
+            Some(deadline) => tokio::time::sleep_until((*deadline).into()).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        result = future => Ok(result),
+        _ = deadline => Err("stopped at the --deadline".into()),
+        _ = interrupted() => Err("interrupted".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(2 * 60 * 60));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(24 * 60 * 60));
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        for value in ["", "0", "0m", "m", "1.5h", "-5s", "10 m", "3w", "1hm", "99999999999999999999", "300000000000000d"] {
+            assert!(parse_duration(value).is_err(), "{:?}", value);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use crate::report::format_bytes;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SummaryFormat {
+    Text,
+    /// one JSON object, for pipelines that collect metrics
+    Json,
+}
+
+static FORMAT: OnceLock<SummaryFormat> = OnceLock::new();
+/// the last bulk summary printed, as text and JSON, for the hooks run at the end
+static LAST: Mutex<Option<(String, Value)>> = Mutex::new(None);
+
+/// sets how bulk commands print their summary. only the first call has any effect
+pub fn set_format(format: SummaryFormat) {
+    let _ = FORMAT.set(format);
+}
+
+fn format() -> SummaryFormat {
+    FORMAT.get().copied().unwrap_or(SummaryFormat::Text)
+}
+
+/// whether a mutating command changed anything. running one again once it has done its work is a no-op
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Changed,
+    NoOp,
+}
+
+impl Outcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Changed => "changed",
+            Outcome::NoOp => "no-op",
+        }
+    }
+}
+
+/// says what a single mutating command did to target, once it has printed its own lines. text
+/// only mentions no-ops
+pub fn report(operation: &str, target: &str, outcome: Outcome) {
+    match format() {
+        SummaryFormat::Text if outcome == Outcome::NoOp => println!("no-op: {} was already done for {}", operation, target),
+        SummaryFormat::Text => {}
+        SummaryFormat::Json => println!("{}", json!({
+            "operation": operation,
+            "target": target,
+            "outcome": outcome.as_str(),
+        })),
+    }
+}
+
+/// the last summary a bulk command printed this run, as text and JSON. it's forgotten once taken,
+/// so the next command the daemon runs doesn't report it too
+pub fn take_last() -> Option<(String, Value)> {
+    LAST.lock().unwrap().take()
+}
+
+/// what a bulk command did, printed once it's finished
+pub struct Summary {
+    operation: &'static str,
+    /// what the text form calls transferred objects, such as uploaded
+    verb: &'static str,
+    started: Instant,
+    pub transferred: usize,
+    /// unchanged files, or keys there was nothing to do for
+    pub skipped: usize,
+    pub failed: usize,
+    /// bytes of the objects transferred, or deleted
+    pub bytes: u64,
+    printed: bool,
+}
+
+impl Summary {
+    pub fn start(operation: &'static str, verb: &'static str) -> Summary {
+        Summary { operation, verb, started: Instant::now(), transferred: 0, skipped: 0, failed: 0, bytes: 0, printed: false }
+    }
+
+    /// nothing transferred and nothing failed, as when running again after a run that finished
+    pub fn outcome(&self) -> Outcome {
+        if self.transferred == 0 && self.failed == 0 { Outcome::NoOp } else { Outcome::Changed }
+    }
+
+    pub fn print(&mut self) {
+        self.printed = true;
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { self.bytes as f64 / elapsed } else { 0.0 };
+        let text = format!("{}: {} {}, {} skipped, {} failed, {} in {:.1}s ({}/s)",
+                           self.operation, self.transferred, self.verb, self.skipped, self.failed,
+                           format_bytes(self.bytes as i64), elapsed, format_bytes(rate as i64));
+        let value = json!({
+            "operation": self.operation,
+            "transferred": self.transferred,
+            "skipped": self.skipped,
+            "failed": self.failed,
+            "bytes": self.bytes,
+            "elapsed_secs": elapsed,
+            "bytes_per_sec": rate,
+            "outcome": self.outcome().as_str(),
+        });
+        match format() {
+            SummaryFormat::Text => println!("{}", text),
+            SummaryFormat::Json => println!("{}", value),
+        }
+        *LAST.lock().unwrap() = Some((text, value));
+    }
+}
+
+/// a command stopped by an error, the deadline or Ctrl-C still says how far it got
+impl Drop for Summary {
+    fn drop(&mut self) {
+        if !self.printed {
+            eprintln!("stopped early. so far:");
+            self.print();
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::Object;
+use clap::ValueEnum;
+use md5::{Digest, Md5};
+use tokio::io::AsyncReadExt;
+use crate::chunked::{self, Chunking};
+use crate::etag::{self, EtagMatch};
+use crate::failures::{FailedOp, FailureLog};
+use crate::journal::{Journal, JournalEntry};
+use crate::listing::list_all_objects;
+use crate::progress::Progress;
+use crate::stop;
+use crate::summary::Summary;
+use crate::tree::{walk, EntryKind, LocalEntry, SymlinkMode};
+use crate::upload::{join_key, local_stat, put_entry, put_failure, PutOptions};
+
+/// chunk uploads to run at once for each file synced as chunks
+const CHUNK_CONCURRENCY: usize = 4;
+
+/// how Sync decides a local file differs from the remote object
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Compare {
+    /// md5 of the local file against the object's ETag
+    Checksum,
+    SizeOnly,
+    /// sizes differ, or the local file was modified after the object was uploaded
+    MtimeAndSize,
+}
+
+pub struct SyncOptions {
+    pub compare: Compare,
+    pub symlinks: SymlinkMode,
+    pub put: PutOptions,
+    /// records what was synced, so later runs can skip the remote listing and unchanged files
+    pub journal: Option<PathBuf>,
+    /// JSON lines file recording files that failed to upload, for Retry
+    pub failures_out: Option<PathBuf>,
+    /// part sizes to try first when comparing against multipart ETags
+    pub part_sizes: Vec<u64>,
+    /// files larger than this are stored as content-defined chunks and a manifest, as ChunkedPut
+    /// stores them, so a changed file only uploads the chunks that changed
+    pub chunked_over: Option<u64>,
+    /// the average chunk size for those files
+    pub chunk_size: u64,
+}
+
+/// uploads files under local_dir that are missing or changed under prefix
+pub async fn run(client: &Client, bucket_name: &str, local_dir: &str, prefix: &str, options: &SyncOptions) -> Result<(), Box<dyn Error>> {
+    let mut summary = Summary::start("sync", "uploaded");
+    let entries = walk(Path::new(local_dir), options.symlinks)?;
+    let previous = match &options.journal {
+        Some(path) => Journal::load(path, bucket_name, prefix)?,
+        None => None,
+    };
+    // a journal from an earlier run stands in for the remote listing. files it doesn't know are uploaded
+    let remote: HashMap<String, Object> = if previous.is_some() {
+        HashMap::new()
+    } else {
+        list_all_objects(client, bucket_name, prefix).await?
+            .into_iter()
+            .filter_map(|object| Some((object.key.clone()?, object)))
+            .collect()
+    };
+    let mut journal = previous.unwrap_or_else(|| Journal::new(bucket_name, prefix));
+    let mut failures = FailureLog::new(options.failures_out.as_deref())?;
+
+    let outcome = sync_entries(client, bucket_name, prefix, &entries, &remote, options, &mut journal, &mut failures, &mut summary).await;
+    if let Some(path) = &options.journal {
+        // entries not reached after a failure keep their previous state
+        if outcome.is_ok() {
+            let present: HashSet<&str> = entries.iter().map(|e| e.relative.as_str()).collect();
+            journal.entries.retain(|relative, _| present.contains(relative.as_str()));
+        }
+        journal.save(path)?;
+    }
+    outcome?;
+    summary.failed = failures.count();
+    summary.print();
+    failures.finish(summary.transferred + summary.failed)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn sync_entries(client: &Client, bucket_name: &str, prefix: &str, entries: &[LocalEntry], remote: &HashMap<String, Object>,
+                      options: &SyncOptions, journal: &mut Journal, failures: &mut FailureLog, summary: &mut Summary) -> Result<(), Box<dyn Error>> {
+    let mut progress = Progress::objects("syncing", Some(entries.len() as u64));
+    for entry in entries {
+        let key = join_key(prefix, &entry.relative);
+        let (mtime, size) = local_stat(entry).await?;
+        let known = journal.entries.get(&entry.relative).cloned();
+        let chunked = options.chunked_over.is_some_and(|over| size > over) && matches!(entry.kind, EntryKind::File);
+        let current = match (&known, remote.get(&key)) {
+            (Some(known), _) => journal_match(entry, known, mtime, size, options).await?,
+            // the object at a chunked file's key is its manifest, which can't be compared with the
+            // file. storing it again only uploads the chunks that changed
+            (None, Some(object)) if !chunked && !is_changed(entry, object, options).await? => Some(JournalEntry {
+                mtime,
+                size,
+                checksum: etag_checksum(object.e_tag()),
+                version_id: String::new(),
+            }),
+            _ => None,
+        };
+        if let Some(current) = current {
+            journal.entries.insert(entry.relative.clone(), current);
+            summary.skipped += 1;
+            progress.add_key(&key, 1);
+            continue;
+        }
+        if chunked {
+            let chunking = Chunking::ContentDefined(options.chunk_size);
+            let stored = match stop::within(chunked::store(client, bucket_name, &entry.path, &key, chunking, CHUNK_CONCURRENCY)).await? {
+                Ok(stored) => stored,
+                Err(err) => {
+                    // chunks aren't something Retry can replay, so it's left to the next sync
+                    failures.record(FailedOp { put_options: None, ..put_failure(bucket_name, &key, entry, &options.put, err.as_ref()) })?;
+                    progress.add_key(&key, 1);
+                    continue;
+                }
+            };
+            if stored.changed {
+                println!("put {}: {} ({} of {} chunks uploaded)", key, stored.version_id.as_deref().unwrap_or("null"), stored.uploaded_chunks, stored.total_chunks);
+                summary.transferred += 1;
+                summary.bytes += stored.uploaded_bytes;
+            } else {
+                summary.skipped += 1;
+            }
+            journal.entries.insert(entry.relative.clone(), JournalEntry {
+                mtime,
+                size,
+                checksum: etag_checksum(stored.e_tag.as_deref()),
+                version_id: stored.version_id.unwrap_or_default(),
+            });
+            progress.add_key(&key, 1);
+            continue;
+        }
+        let result = match stop::within(put_entry(client, bucket_name, &key, entry, &options.put)).await? {
+            Ok(result) => result,
+            Err(err) => {
+                failures.record(put_failure(bucket_name, &key, entry, &options.put, err.as_ref()))?;
+                progress.add_key(&key, 1);
+                continue;
+            }
+        };
+        println!("put {}: {}", key, result.version_id.as_deref().unwrap_or("null"));
+        journal.entries.insert(entry.relative.clone(), JournalEntry {
+            mtime,
+            size,
+            checksum: etag_checksum(result.e_tag.as_deref()),
+            version_id: result.version_id.unwrap_or_default(),
+        });
+        summary.transferred += 1;
+        summary.bytes += size;
+        progress.add_key(&key, 1);
+    }
+    progress.finish();
+    Ok(())
+}
+
+/// the updated journal entry if the file still matches what was synced, None if it needs uploading
+async fn journal_match(entry: &LocalEntry, known: &JournalEntry, mtime: i64, size: u64, options: &SyncOptions) -> Result<Option<JournalEntry>, Box<dyn Error>> {
+    let same_stat = known.mtime == mtime && known.size == size;
+    let unchanged = match options.compare {
+        Compare::SizeOnly => known.size == size,
+        Compare::MtimeAndSize => same_stat,
+        // the journal keeps the ETag, which is a multipart one for files synced that way
+        Compare::Checksum => same_stat
+            || (known.size == size && !known.checksum.is_empty()
+                && etag::matches_file(&entry.path, &known.checksum, &options.part_sizes).await? != EtagMatch::NoMatch),
+    };
+    Ok(unchanged.then(|| JournalEntry { mtime, size, ..known.clone() }))
+}
+
+fn etag_checksum(etag: Option<&str>) -> String {
+    etag.unwrap_or_default().trim_matches('"').to_ascii_lowercase()
+}
+
+async fn is_changed(entry: &LocalEntry, object: &Object, options: &SyncOptions) -> Result<bool, Box<dyn Error>> {
+    if let EntryKind::Symlink(_) = entry.kind {
+        // the target lives in metadata we'd have to fetch, so existing links count as unchanged
+        return Ok(false);
+    }
+    let (local_secs, size) = local_stat(entry).await?;
+    let size_differs = size as i64 != object.size();
+    Ok(match options.compare {
+        Compare::SizeOnly => size_differs,
+        Compare::MtimeAndSize => {
+            let remote_secs = object.last_modified().map(|d| d.secs()).unwrap_or(0);
+            size_differs || local_secs > remote_secs
+        }
+        Compare::Checksum => size_differs
+            || etag::matches_file(&entry.path, object.e_tag().unwrap_or_default(), &options.part_sizes).await? == EtagMatch::NoMatch,
+    })
+}
+
+/// hex md5 of the file at path, read in chunks
+pub async fn md5_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let count = file.read(&mut buffer).await?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
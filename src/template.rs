@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::SystemTime;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// a random version 4 UUID
+fn uuid() -> Result<String, Box<dyn Error>> {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new().fill(&mut bytes).map_err(|_| "couldn't generate a uuid")?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = base16ct::lower::encode_string(&bytes);
+    Ok(format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+}
+
+/// expands the placeholders in a destination key for uploading file_path:
+///
+/// - `{date}`: today's UTC date, as YYYY-MM-DD
+/// - `{uuid}`: a random UUID
+/// - `{filename}`: the file's name without its directory
+/// - `{sha256}` or `{sha256:N}`: the hex SHA-256 of the file's contents, or its first N characters
+///
+/// `{{` and `}}` stand for literal braces. sha256 is only called if the key uses it
+pub fn expand(key: &str, file_path: &str, sha256: impl FnOnce() -> Result<String, Box<dyn Error>>) -> Result<String, Box<dyn Error>> {
+    let mut sha256 = Some(sha256);
+    let mut digest: Option<String> = None;
+    let mut expanded = String::new();
+    let mut rest = key;
+    while let Some(idx) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..idx]);
+        let after = &rest[idx..];
+        if after.starts_with("{{") || after.starts_with("}}") {
+            expanded.push_str(&after[..1]);
+            rest = &after[2..];
+            continue;
+        }
+        if after.starts_with('}') {
+            return Err(format!("unmatched '}}' in key {:?}", key).into());
+        }
+        let end = after.find('}').ok_or_else(|| format!("unclosed '{{' in key {:?}", key))?;
+        let placeholder = &after[1..end];
+        match placeholder.split_once(':').unwrap_or((placeholder, "")) {
+            ("date", "") => {
+                let now = DateTime::from(SystemTime::now()).fmt(DateTimeFormat::DateTime)?;
+                expanded.push_str(&now[..10]);
+            }
+            ("uuid", "") => expanded.push_str(&uuid()?),
+            ("filename", "") => {
+                let name = Path::new(file_path).file_name().ok_or_else(|| format!("{} has no file name", file_path))?;
+                expanded.push_str(&name.to_string_lossy());
+            }
+            ("sha256", length) => {
+                if digest.is_none() {
+                    digest = Some((sha256.take().expect("sha256 is only taken once"))()?);
+                }
+                let digest = digest.as_deref().unwrap_or_default();
+                let length = if length.is_empty() { digest.len() } else { length.parse().map_err(|_| format!("bad length in {{{}}}", placeholder))? };
+                expanded.push_str(&digest[..length.min(digest.len())]);
+            }
+            _ => return Err(format!("unknown placeholder {{{}}} in key {:?}", placeholder, key).into()),
+        }
+        rest = &after[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn expand_key(key: &str) -> Result<String, String> {
+        expand(key, "dir/sub/report.csv", || Ok(DIGEST.to_string())).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!(expand_key("plain/key").unwrap(), "plain/key");
+        assert_eq!(expand_key("in/{filename}").unwrap(), "in/report.csv");
+        assert_eq!(expand_key("cas/{sha256}").unwrap(), format!("cas/{}", DIGEST));
+        assert_eq!(expand_key("{sha256:8}/{sha256:2}").unwrap(), "9f86d081/9f");
+        assert_eq!(expand_key("{sha256:100}").unwrap(), DIGEST);
+        assert_eq!(expand_key("{{literal}}/{filename}").unwrap(), "{literal}/report.csv");
+    }
+
+    #[test]
+    fn date_and_uuid_have_their_shapes() {
+        let date = expand_key("{date}").unwrap();
+        assert_eq!(date.len(), 10);
+        assert_eq!(date.matches('-').count(), 2);
+        let uuid = expand_key("{uuid}").unwrap();
+        let groups: Vec<usize> = uuid.split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(uuid, expand_key("{uuid}").unwrap());
+    }
+
+    #[test]
+    fn hashes_only_when_needed_and_once() {
+        assert_eq!(expand("{filename}", "a.txt", || Err("hashed".into())).unwrap(), "a.txt");
+        let mut calls = 0;
+        expand("{sha256}{sha256:4}", "a.txt", || { calls += 1; Ok(DIGEST.to_string()) }).unwrap();
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn rejects_malformed_keys() {
+        assert!(expand_key("a}b").unwrap_err().contains("unmatched"));
+        assert!(expand_key("a{b").unwrap_err().contains("unclosed"));
+        assert!(expand_key("{nope}").unwrap_err().contains("unknown placeholder"));
+        assert!(expand_key("{date:5}").unwrap_err().contains("unknown placeholder"));
+        assert!(expand_key("{sha256:x}").unwrap_err().contains("bad length"));
+        assert!(expand("{filename}", "/", || Ok(String::new())).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::types::{IntelligentTieringAccessTier, IntelligentTieringAndOperator, IntelligentTieringConfiguration,
+                        IntelligentTieringFilter, IntelligentTieringStatus, Tag, Tiering};
+use clap::{ArgGroup, Subcommand};
+use crate::copy::parse_pair;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum TieringAction {
+    /// list the bucket's Intelligent-Tiering configurations
+    List,
+    /// show one configuration
+    Get {
+        id: String,
+    },
+    /// create or replace a configuration moving INTELLIGENT_TIERING objects to the archive tiers
+    #[command(group(ArgGroup::new("tiers").required(true).multiple(true).args(["archive_days", "deep_archive_days"])))]
+    Put {
+        id: String,
+        #[arg(long, help = "only objects under this prefix")]
+        prefix: Option<String>,
+        #[arg(long = "tag", value_name = "KEY=VALUE", value_parser = parse_pair, help = "only objects with this tag. may be repeated")]
+        tags: Vec<(String, String)>,
+        #[arg(long, value_name = "DAYS", help = "days without access before moving to Archive Access. at least 90")]
+        archive_days: Option<i32>,
+        #[arg(long, value_name = "DAYS", help = "days without access before moving to Deep Archive Access. at least 180")]
+        deep_archive_days: Option<i32>,
+        #[arg(long, help = "save the configuration without applying it")]
+        disabled: bool,
+    },
+    Delete {
+        id: String,
+    },
+}
+
+fn filter(prefix: Option<&str>, tags: &[(String, String)]) -> Option<IntelligentTieringFilter> {
+    let tags: Vec<Tag> = tags.iter().map(|(key, value)| Tag::builder().key(key).value(value).build()).collect();
+    // a prefix and a tag, or several tags, have to be combined with And
+    let builder = IntelligentTieringFilter::builder();
+    let builder = match (prefix, tags.len()) {
+        (None, 0) => return None,
+        (Some(prefix), 0) => builder.prefix(prefix),
+        (None, 1) => builder.tag(tags[0].clone()),
+        _ => builder.and(IntelligentTieringAndOperator::builder().set_prefix(prefix.map(str::to_string)).set_tags(Some(tags)).build()),
+    };
+    Some(builder.build())
+}
+
+fn print_configuration(configuration: &IntelligentTieringConfiguration) {
+    let status = configuration.status().map(|s| s.as_str()).unwrap_or("<unknown>");
+    println!("{} ({})", configuration.id().unwrap_or("<none>"), status);
+    if let Some(filter) = configuration.filter() {
+        let and = filter.and();
+        if let Some(prefix) = filter.prefix().or(and.and_then(|and| and.prefix())) {
+            println!("  prefix: {}", prefix);
+        }
+        for tag in filter.tag().into_iter().chain(and.and_then(|and| and.tags()).unwrap_or_default()) {
+            println!("  tag: {}={}", tag.key().unwrap_or_default(), tag.value().unwrap_or_default());
+        }
+    }
+    for tiering in configuration.tierings().unwrap_or_default() {
+        println!("  {} after {} days", tiering.access_tier().map(|t| t.as_str()).unwrap_or("<unknown>"), tiering.days());
+    }
+}
+
+pub async fn run(client: &Client, bucket_name: &str, action: &TieringAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        TieringAction::List => {
+            let mut token = None;
+            let mut count = 0;
+            loop {
+                let result = client.list_bucket_intelligent_tiering_configurations()
+                    .bucket(bucket_name)
+                    .set_continuation_token(token)
+                    .send()
+                    .await?;
+                for configuration in result.intelligent_tiering_configuration_list().unwrap_or_default() {
+                    print_configuration(configuration);
+                    count += 1;
+                }
+                token = result.next_continuation_token().filter(|_| result.is_truncated()).map(str::to_string);
+                if token.is_none() {
+                    break;
+                }
+            }
+            if count == 0 {
+                println!("no Intelligent-Tiering configurations");
+            }
+        }
+        TieringAction::Get { id } => {
+            let result = client.get_bucket_intelligent_tiering_configuration()
+                .bucket(bucket_name)
+                .id(id)
+                .send()
+                .await?;
+            match result.intelligent_tiering_configuration() {
+                Some(configuration) => print_configuration(configuration),
+                None => println!("no configuration {}", id),
+            }
+        }
+        TieringAction::Put { id, prefix, tags, archive_days, deep_archive_days, disabled } => {
+            let tierings: Vec<Tiering> = [(IntelligentTieringAccessTier::ArchiveAccess, archive_days), (IntelligentTieringAccessTier::DeepArchiveAccess, deep_archive_days)]
+                .into_iter()
+                .filter_map(|(tier, days)| Some(Tiering::builder().access_tier(tier).days((*days)?).build()))
+                .collect();
+            let status = if *disabled { IntelligentTieringStatus::Disabled } else { IntelligentTieringStatus::Enabled };
+            let configuration = IntelligentTieringConfiguration::builder()
+                .id(id)
+                .set_filter(filter(prefix.as_deref(), tags))
+                .status(status)
+                .set_tierings(Some(tierings))
+                .build();
+            client.put_bucket_intelligent_tiering_configuration()
+                .bucket(bucket_name)
+                .id(id)
+                .intelligent_tiering_configuration(configuration.clone())
+                .send()
+                .await?;
+            print_configuration(&configuration);
+        }
+        TieringAction::Delete { id } => {
+            client.delete_bucket_intelligent_tiering_configuration()
+                .bucket(bucket_name)
+                .id(id)
+                .send()
+                .await?;
+            println!("deleted Intelligent-Tiering configuration {}", id);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use clap::Args;
+
+/// how directory walks treat symbolic links
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymlinkMode {
+    /// upload what the link points to
+    Follow,
+    /// ignore links entirely
+    Skip,
+    /// upload an empty object recording the link target in metadata
+    Preserve,
+}
+
+#[derive(Args, Clone, Debug)]
+#[group(multiple = false)]
+pub struct SymlinkArgs {
+    #[arg(long, help = "upload the files symlinks point to (default)")]
+    follow_symlinks: bool,
+    #[arg(long, help = "ignore symlinks")]
+    skip_symlinks: bool,
+    #[arg(long, help = "store symlinks as empty objects with the target in metadata")]
+    preserve_symlinks: bool,
+}
+
+impl SymlinkArgs {
+    pub fn mode(&self) -> SymlinkMode {
+        if self.skip_symlinks {
+            SymlinkMode::Skip
+        } else if self.preserve_symlinks {
+            SymlinkMode::Preserve
+        } else {
+            SymlinkMode::Follow
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum EntryKind {
+    File,
+    Symlink(PathBuf),
+}
+
+#[derive(Clone, Debug)]
+pub struct LocalEntry {
+    pub path: PathBuf,
+    /// path relative to the walk root, always using '/' separators
+    pub relative: String,
+    pub kind: EntryKind,
+}
+
+/// returns every file under root, sorted by relative path
+pub fn walk(root: &Path, symlinks: SymlinkMode) -> io::Result<Vec<LocalEntry>> {
+    let mut entries = Vec::new();
+    let mut visited = HashSet::new();
+    visited.insert(fs::canonicalize(root)?);
+    walk_dir(root, "", symlinks, &mut visited, &mut entries)?;
+    entries.sort_by(|a, b| a.relative.cmp(&b.relative));
+    Ok(entries)
+}
+
+fn walk_dir(dir: &Path, relative: &str, symlinks: SymlinkMode, visited: &mut HashSet<PathBuf>, entries: &mut Vec<LocalEntry>) -> io::Result<()> {
+    for dir_entry in fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        let path = dir_entry.path();
+        let name = dir_entry.file_name().to_string_lossy().to_string();
+        let child_relative = format!("{}{}", relative, name);
+        let mut file_type = dir_entry.file_type()?;
+        if file_type.is_symlink() {
+            match symlinks {
+                SymlinkMode::Skip => continue,
+                SymlinkMode::Preserve => {
+                    entries.push(LocalEntry { kind: EntryKind::Symlink(fs::read_link(&path)?), path, relative: child_relative });
+                    continue;
+                }
+                SymlinkMode::Follow => match fs::metadata(&path) {
+                    Ok(target) => file_type = target.file_type(),
+                    Err(err) => {
+                        eprintln!("skipping broken symlink {}: {}", path.display(), err);
+                        continue;
+                    }
+                },
+            }
+        }
+        if file_type.is_dir() {
+            // following links can revisit a directory, so only descend into each real directory once
+            if visited.insert(fs::canonicalize(&path)?) {
+                walk_dir(&path, &format!("{}/", child_relative), symlinks, visited, entries)?;
+            }
+        } else if file_type.is_file() {
+            entries.push(LocalEntry { path, relative: child_relative, kind: EntryKind::File });
+        }
+    }
+    Ok(())
+}
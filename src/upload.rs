@@ -0,0 +1,326 @@
+use std::error::Error;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::operation::create_multipart_upload::builders::CreateMultipartUploadFluentBuilder;
+use aws_sdk_s3::operation::put_object::builders::PutObjectFluentBuilder;
+use aws_sdk_s3::primitives::{ByteStream, DateTime, DateTimeFormat};
+use aws_sdk_s3::types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart, ObjectCannedAcl, StorageClass};
+use clap::builder::PossibleValuesParser;
+use clap::Args;
+use serde_json::{json, Value};
+use crate::acl;
+use crate::checksum::Sha256;
+use crate::errors::ErrorDetails;
+use crate::failures::{FailedOp, FailureLog, Operation};
+use crate::file_meta;
+use crate::manifest;
+use crate::mapped::{self, Mapped};
+use crate::migrate::{PendingUpload, MAX_PARTS};
+use crate::payer;
+use crate::progress::Progress;
+use crate::stop;
+use crate::summary::Summary;
+use crate::tree::{walk, EntryKind, LocalEntry, SymlinkMode};
+use crate::website;
+
+/// user metadata key holding the target of a preserved symlink
+pub const SYMLINK_KEY: &str = "symlink-target";
+
+/// files larger than this are uploaded in parts of this size. it's one of the sizes
+/// etag::matches_file tries, so Sync --compare checksum still recognises them
+pub const PART_SIZE: u64 = 64 * 1024 * 1024;
+
+/// the key for a file at relative under prefix, treating a non-empty prefix as a directory
+pub fn join_key(prefix: &str, relative: &str) -> String {
+    if prefix.is_empty() || prefix.ends_with('/') {
+        format!("{}{}", prefix, relative)
+    } else {
+        format!("{}/{}", prefix, relative)
+    }
+}
+
+/// how uploaded objects are stored: the headers they're served to browsers with, their ACL and storage class
+#[derive(Args, Clone, Debug, Default)]
+pub struct HeaderArgs {
+    #[arg(long, value_name = "VALUE", help = "Cache-Control header to store, e.g. 'public, max-age=86400'")]
+    pub cache_control: Option<String>,
+    #[arg(long, value_name = "RFC3339", value_parser = manifest::parse_time, help = "Expires header to store")]
+    pub expires: Option<DateTime>,
+    #[arg(long, value_name = "ENCODING", help = "Content-Encoding header to store, for files that are already compressed")]
+    pub content_encoding: Option<String>,
+    #[arg(long, value_parser = acl::canned_acl_parser(), help = "canned ACL to apply")]
+    pub acl: Option<String>,
+    #[arg(long, value_parser = PossibleValuesParser::new(StorageClass::values().iter().copied()), help = "storage class to upload to, e.g. INTELLIGENT_TIERING")]
+    pub storage_class: Option<String>,
+}
+
+impl HeaderArgs {
+    /// sets the headers that were given, replacing any already set on request
+    pub fn apply(&self, mut request: PutObjectFluentBuilder) -> PutObjectFluentBuilder {
+        if let Some(cache_control) = &self.cache_control {
+            request = request.cache_control(cache_control);
+        }
+        if let Some(content_encoding) = &self.content_encoding {
+            request = request.content_encoding(content_encoding);
+        }
+        request
+            .set_expires(self.expires)
+            .set_acl(self.acl.as_deref().map(ObjectCannedAcl::from))
+            .set_storage_class(self.storage_class.as_deref().map(StorageClass::from))
+    }
+
+    /// the same, for an upload in parts
+    pub fn apply_multipart(&self, mut request: CreateMultipartUploadFluentBuilder) -> CreateMultipartUploadFluentBuilder {
+        if let Some(cache_control) = &self.cache_control {
+            request = request.cache_control(cache_control);
+        }
+        if let Some(content_encoding) = &self.content_encoding {
+            request = request.content_encoding(content_encoding);
+        }
+        request
+            .set_expires(self.expires)
+            .set_acl(self.acl.as_deref().map(ObjectCannedAcl::from))
+            .set_storage_class(self.storage_class.as_deref().map(StorageClass::from))
+    }
+}
+
+/// how files are uploaded beyond their contents
+#[derive(Clone, Debug, Default)]
+pub struct PutOptions {
+    /// record each file's mtime and permission bits in object metadata
+    pub preserve: bool,
+    /// set the headers a static site wants: text types with a charset, and Cache-Control
+    pub web: bool,
+    /// stored on every file, overriding the headers web sets
+    pub headers: HeaderArgs,
+    /// stored instead of the type guessed from the file name
+    pub content_type: Option<String>,
+    /// user metadata stored on every file
+    pub metadata: Vec<(String, String)>,
+}
+
+impl PutOptions {
+    /// the options as a failure record stores them, so a retry uploads the same object
+    pub fn to_json(&self) -> Value {
+        let headers = &self.headers;
+        json!({
+            "preserve": self.preserve,
+            "web": self.web,
+            "cache_control": headers.cache_control,
+            "expires": headers.expires.and_then(|expires| expires.fmt(DateTimeFormat::DateTime).ok()),
+            "content_encoding": headers.content_encoding,
+            "acl": headers.acl,
+            "storage_class": headers.storage_class,
+            "content_type": self.content_type,
+            "metadata": self.metadata.iter().map(|(key, value)| json!([key, value])).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn from_json(value: &Value) -> Result<PutOptions, String> {
+        let string = |name: &str| value[name].as_str().map(|s| s.to_string());
+        let metadata = match &value["metadata"] {
+            Value::Null => Vec::new(),
+            Value::Array(pairs) => pairs.iter()
+                .map(|pair| match (pair[0].as_str(), pair[1].as_str()) {
+                    (Some(key), Some(value)) => Ok((key.to_string(), value.to_string())),
+                    _ => Err("metadata must be [key, value] pairs".to_string()),
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err("metadata must be [key, value] pairs".to_string()),
+        };
+        Ok(PutOptions {
+            preserve: value["preserve"].as_bool().unwrap_or(false),
+            web: value["web"].as_bool().unwrap_or(false),
+            headers: HeaderArgs {
+                cache_control: string("cache_control"),
+                expires: string("expires").map(|expires| manifest::parse_time(&expires)).transpose()?,
+                content_encoding: string("content_encoding"),
+                acl: string("acl"),
+                storage_class: string("storage_class"),
+            },
+            content_type: string("content_type"),
+            metadata,
+        })
+    }
+}
+
+/// what S3 said about an uploaded entry
+pub struct Uploaded {
+    pub version_id: Option<String>,
+    pub e_tag: Option<String>,
+}
+
+/// uploads one walked entry to key. files are hashed from their mapping and streamed from disk,
+/// in parts once they're larger than PART_SIZE, so memory doesn't grow with the file
+pub async fn put_entry(client: &Client, bucket_name: &str, key: &str, entry: &LocalEntry, options: &PutOptions) -> Result<Uploaded, Box<dyn Error>> {
+    let target = match &entry.kind {
+        EntryKind::File => return put_file(client, bucket_name, key, &entry.path, options).await,
+        EntryKind::Symlink(target) => target,
+    };
+    let request = client.put_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .checksum_algorithm(ChecksumAlgorithm::Sha256)
+        .metadata(SYMLINK_KEY, target.to_string_lossy())
+        .body(ByteStream::from(Vec::new()));
+    let result = options.headers.apply(request).send().await?;
+    Ok(Uploaded { version_id: result.version_id().map(str::to_string), e_tag: result.e_tag().map(str::to_string) })
+}
+
+/// what a file is stored with, before options.headers
+struct FileHeaders {
+    content_type: String,
+    cache_control: Option<String>,
+    metadata: Vec<(String, String)>,
+}
+
+fn file_headers(path: &Path, options: &PutOptions) -> Result<FileHeaders, Box<dyn Error>> {
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    let (mut content_type, cache_control) = if options.web {
+        (website::content_type(&mime), Some(website::cache_control(&mime)))
+    } else {
+        (mime.to_string(), None)
+    };
+    if let Some(overridden) = &options.content_type {
+        content_type = overridden.clone();
+    }
+    let mut metadata: Vec<(String, String)> = Vec::new();
+    if options.preserve {
+        metadata.extend(file_meta::capture(path)?.into_iter().map(|(key, value)| (key.to_string(), value)));
+    }
+    metadata.extend(options.metadata.iter().cloned());
+    Ok(FileHeaders { content_type, cache_control, metadata })
+}
+
+async fn put_file(client: &Client, bucket_name: &str, key: &str, path: &Path, options: &PutOptions) -> Result<Uploaded, Box<dyn Error>> {
+    let contents = mapped::read(path).await?;
+    let headers = file_headers(path, options)?;
+    if contents.len() as u64 > PART_SIZE {
+        let mut request = client.create_multipart_upload()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .key(key)
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .content_type(headers.content_type)
+            .set_cache_control(headers.cache_control);
+        for (key, value) in headers.metadata {
+            request = request.metadata(key, value);
+        }
+        return put_parts(client, bucket_name, key, &contents, options.headers.apply_multipart(request)).await;
+    }
+    let checksum = Sha256::of(&contents);
+    let mut request = client.put_object()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .content_type(headers.content_type)
+        .set_cache_control(headers.cache_control)
+        .checksum_sha256(checksum.base64())
+        .body(contents.body(0..contents.len()).await?);
+    for (key, value) in headers.metadata {
+        request = request.metadata(key, value);
+    }
+    let result = checksum.sign_with(options.headers.apply(request).customize().await?).send().await?;
+    Ok(Uploaded { version_id: result.version_id().map(str::to_string), e_tag: result.e_tag().map(str::to_string) })
+}
+
+/// uploads contents in parts of PART_SIZE, or larger ones for files that would otherwise need
+/// more than S3 allows, one part at a time. the upload is aborted if a part fails
+async fn put_parts(client: &Client, bucket_name: &str, key: &str, contents: &Mapped, create: CreateMultipartUploadFluentBuilder) -> Result<Uploaded, Box<dyn Error>> {
+    let size = contents.len() as u64;
+    let part_size = PART_SIZE.max(size.div_ceil(MAX_PARTS));
+    let created = create.send().await?;
+    let mut upload = PendingUpload {
+        client,
+        bucket_name,
+        key,
+        upload_id: created.upload_id().ok_or("no upload id in the response")?.to_string(),
+        completed: false,
+    };
+    let mut parts = Vec::new();
+    for (idx, start) in (0..size).step_by(part_size as usize).enumerate() {
+        let range = start as usize..(start + part_size).min(size) as usize;
+        let number = idx as i32 + 1;
+        let checksum = Sha256::of(&contents[range.clone()]);
+        let request = client.upload_part()
+            .bucket(bucket_name)
+            .set_request_payer(payer::get())
+            .key(key)
+            .upload_id(&upload.upload_id)
+            .part_number(number)
+            .checksum_sha256(checksum.base64())
+            .body(contents.body(range).await?);
+        let result = checksum.sign_with(request.customize().await?).send().await?;
+        parts.push(CompletedPart::builder()
+            .part_number(number)
+            .set_e_tag(result.e_tag().map(str::to_string))
+            .checksum_sha256(checksum.base64())
+            .build());
+    }
+    let result = client.complete_multipart_upload()
+        .bucket(bucket_name)
+        .set_request_payer(payer::get())
+        .key(key)
+        .upload_id(&upload.upload_id)
+        .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+        .send()
+        .await?;
+    upload.completed = true;
+    Ok(Uploaded { version_id: result.version_id().map(str::to_string), e_tag: result.e_tag().map(str::to_string) })
+}
+
+/// mtime in seconds and size of a walked entry. symlinks being preserved report zero for both
+pub async fn local_stat(entry: &LocalEntry) -> Result<(i64, u64), Box<dyn Error>> {
+    if let EntryKind::Symlink(_) = entry.kind {
+        return Ok((0, 0));
+    }
+    let info = tokio::fs::metadata(&entry.path).await?;
+    let mtime = info.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    Ok((mtime, info.len()))
+}
+
+/// the failure record for uploading entry to key with options
+pub fn put_failure(bucket_name: &str, key: &str, entry: &LocalEntry, options: &PutOptions, err: &(dyn Error + 'static)) -> FailedOp {
+    FailedOp {
+        operation: Operation::Put,
+        bucket: bucket_name.to_string(),
+        key: key.to_string(),
+        version_id: None,
+        local_path: Some(entry.path.clone()),
+        symlink_target: match &entry.kind {
+            EntryKind::File => None,
+            EntryKind::Symlink(target) => Some(target.clone()),
+        },
+        put_options: Some(options.clone()),
+        error: ErrorDetails::from_error(err),
+    }
+}
+
+/// uploads every file under local_dir to prefix, continuing past files that fail
+pub async fn upload_dir(client: &Client, bucket_name: &str, local_dir: &str, prefix: &str, symlinks: SymlinkMode, options: &PutOptions,
+                        failures_out: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let entries = walk(Path::new(local_dir), symlinks)?;
+    let mut failures = FailureLog::new(failures_out)?;
+    let mut progress = Progress::objects("uploading", Some(entries.len() as u64));
+    let mut summary = Summary::start("upload", "uploaded");
+    for entry in &entries {
+        let key = join_key(prefix, &entry.relative);
+        // a file that can't be read fails to upload, so its size doesn't matter
+        let size = local_stat(entry).await.map(|(_, size)| size).unwrap_or(0);
+        match stop::within(put_entry(client, bucket_name, &key, entry, options)).await? {
+            Ok(result) => {
+                println!("put {}: {}", key, result.version_id.as_deref().unwrap_or("null"));
+                summary.transferred += 1;
+                summary.bytes += size;
+            }
+            Err(err) => failures.record(put_failure(bucket_name, &key, entry, options, err.as_ref()))?,
+        }
+        progress.add_key(&key, 1);
+    }
+    progress.finish();
+    summary.failed = failures.count();
+    summary.print();
+    failures.finish(entries.len())
+}
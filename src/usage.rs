@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::future::{poll_fn, Future};
+use std::io::Write;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::mem;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_smithy_client::erase::DynConnector;
+use aws_smithy_http::body::SdkBody;
+use aws_smithy_http::result::ConnectorError;
+use http::{HeaderMap, Method, Request, Response};
+use hyper::service::Service;
+use crate::config;
+use crate::manifest;
+use crate::report::format_bytes;
+use crate::stop;
+
+/// bytes sent and received this run, per host
+static COUNTS: Mutex<BTreeMap<String, (u64, u64)>> = Mutex::new(BTreeMap::new());
+static COMMAND: Mutex<String> = Mutex::new(String::new());
+
+/// names the run in the ledger by a command's variant name, written as on the command line:
+/// MetaExport is meta-export. the daemon names each command it runs, then itself again
+pub fn set_command(variant: &str) {
+    let mut command = String::new();
+    for (idx, c) in variant.chars().enumerate() {
+        if c.is_uppercase() && idx > 0 {
+            command.push('-');
+        }
+        command.extend(c.to_lowercase());
+    }
+    *COMMAND.lock().unwrap() = command;
+}
+
+/// the command's name as set_command wrote it, or - before then
+pub fn command() -> String {
+    let command = COMMAND.lock().unwrap();
+    if command.is_empty() { "-".to_string() } else { command.clone() }
+}
+
+pub fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers.get("content-length")?.to_str().ok()?.parse().ok()
+}
+
+/// counts the bytes of every request and response body by their Content-Length, for the usage
+/// ledger. it sits next to the network, so retries and failovers are counted as sent
+#[derive(Clone)]
+pub struct Metered {
+    inner: DynConnector,
+}
+
+impl Metered {
+    pub fn wrap(inner: DynConnector) -> DynConnector {
+        DynConnector::new(Metered { inner })
+    }
+}
+
+impl Service<Request<SdkBody>> for Metered {
+    type Response = Response<SdkBody>;
+    type Error = ConnectorError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<SdkBody>, ConnectorError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ConnectorError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<SdkBody>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        Box::pin(async move {
+            let host = request.uri().authority().map(|a| a.to_string()).unwrap_or_default();
+            let sent = content_length(request.headers()).or(request.body().content_length()).unwrap_or(0);
+            // a HEAD response says how long the object is without sending it
+            let head = request.method() == Method::HEAD;
+            poll_fn(|cx| inner.poll_ready(cx)).await?;
+            let result = inner.call(request).await;
+            let received = match &result {
+                Ok(response) if !head => content_length(response.headers()).unwrap_or(0),
+                _ => 0,
+            };
+            let mut counts = COUNTS.lock().unwrap();
+            let entry = counts.entry(host).or_default();
+            entry.0 += sent;
+            entry.1 += received;
+            result
+        })
+    }
+}
+
+/// the ledger, next to the config file
+fn ledger_path() -> Option<PathBuf> {
+    config::path().and_then(|config| Some(config.parent()?.join("usage")))
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
+
+/// appends a line per host this run talked to: time, command, host, bytes sent and received.
+/// appends are small enough that concurrent runs don't interleave them. the counts start again
+/// from zero, so each command the daemon runs gets lines of its own
+pub fn record() {
+    let counts = mem::take(&mut *COUNTS.lock().unwrap());
+    if counts.is_empty() {
+        return;
+    }
+    let Some(path) = ledger_path() else { return };
+    let command = command();
+    let mut text = String::new();
+    for (host, (sent, received)) in counts.iter() {
+        text.push_str(&format!("{}\t{}\t{}\t{}\t{}\n", now(), command, host, sent, received));
+    }
+    let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::OpenOptions::new().create(true).append(true).open(&path))
+        .and_then(|mut file| file.write_all(text.as_bytes()));
+    if let Err(err) = result {
+        eprintln!("note: couldn't record usage in {}: {}", path.display(), err);
+    }
+}
+
+/// a start time for Usage: a duration back from now such as 7d, or an RFC 3339 time
+pub fn parse_since(value: &str) -> Result<i64, String> {
+    if let Ok(duration) = stop::parse_duration(value) {
+        return Ok(now() - duration.as_secs() as i64);
+    }
+    manifest::parse_time(value).map(|time| time.secs())
+        .map_err(|_| format!("{} isn't a duration like 7d or a time like 2024-06-01T00:00:00Z", value))
+}
+
+/// one line of the ledger
+struct Entry {
+    time: i64,
+    command: String,
+    host: String,
+    sent: u64,
+    received: u64,
+}
+
+fn day(time: i64) -> String {
+    let formatted = DateTime::from_secs(time).fmt(DateTimeFormat::DateTime).unwrap_or_default();
+    formatted.get(..10).unwrap_or_default().to_string()
+}
+
+/// prints the bytes sent and received per day since since, then the total. runs lists every run
+/// instead of daily totals, and by_host splits them by the host they went to
+pub fn report(since: Option<i64>, runs: bool, by_host: bool) -> Result<(), Box<dyn Error>> {
+    let path = ledger_path().ok_or("no ledger location. set S3TEST_CONFIG or HOME")?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(format!("{}: {}", path.display(), err).into()),
+    };
+    let entries: Vec<Entry> = text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some(Entry {
+                time: fields.next()?.parse().ok()?,
+                command: fields.next()?.to_string(),
+                host: fields.next()?.to_string(),
+                sent: fields.next()?.parse().ok()?,
+                received: fields.next()?.parse().ok()?,
+            })
+        })
+        .filter(|entry| since.is_none_or(|since| entry.time >= since))
+        .collect();
+    if entries.is_empty() {
+        println!("no usage recorded");
+        return Ok(());
+    }
+    let mut totals: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for entry in &entries {
+        let mut label = if runs {
+            let time = DateTime::from_secs(entry.time).fmt(DateTimeFormat::DateTime).unwrap_or_default();
+            format!("{}  {}", time, entry.command)
+        } else {
+            day(entry.time)
+        };
+        if by_host {
+            label = format!("{}  {}", label, entry.host);
+        }
+        let total = totals.entry(label).or_default();
+        total.0 += entry.sent;
+        total.1 += entry.received;
+    }
+    for (label, (sent, received)) in &totals {
+        println!("{}  uploaded {}, downloaded {}", label, format_bytes(*sent as i64), format_bytes(*received as i64));
+    }
+    let sent: u64 = entries.iter().map(|entry| entry.sent).sum();
+    let received: u64 = entries.iter().map(|entry| entry.received).sum();
+    println!("total  uploaded {}, downloaded {}", format_bytes(sent as i64), format_bytes(received as i64));
+    Ok(())
+}
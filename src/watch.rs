@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::Duration;
+use aws_sdk_s3::Client;
+use tokio::process::Command;
+use crate::errors::ErrorDetails;
+use crate::listing::list_all_objects;
+use crate::prometheus;
+use crate::stop;
+
+/// what a listing says about an object
+#[derive(Clone, Debug, PartialEq)]
+struct Seen {
+    size: i64,
+    e_tag: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+impl Change {
+    fn name(self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Modified => "modified",
+        }
+    }
+}
+
+async fn snapshot(client: &Client, bucket_name: &str, prefix: &str) -> Result<BTreeMap<String, Seen>, Box<dyn Error>> {
+    let mut seen = BTreeMap::new();
+    for object in list_all_objects(client, bucket_name, prefix).await? {
+        if let Some(key) = object.key {
+            seen.insert(key, Seen { size: object.size, e_tag: object.e_tag.unwrap_or_default() });
+        }
+    }
+    Ok(seen)
+}
+
+/// the keys that were added, removed or changed size or ETag between before and after, in key order
+fn diff<'a>(before: &'a BTreeMap<String, Seen>, after: &'a BTreeMap<String, Seen>) -> Vec<(Change, &'a str, &'a Seen)> {
+    let mut changes: Vec<_> = after.iter()
+        .filter_map(|(key, seen)| match before.get(key) {
+            None => Some((Change::Added, key.as_str(), seen)),
+            Some(old) if old != seen => Some((Change::Modified, key.as_str(), seen)),
+            Some(_) => None,
+        })
+        .chain(before.iter().filter(|(key, _)| !after.contains_key(*key)).map(|(key, seen)| (Change::Removed, key.as_str(), seen)))
+        .collect();
+    changes.sort_by(|a, b| a.1.cmp(b.1));
+    changes
+}
+
+/// runs command with sh, telling it about the change through S3TEST_* environment variables.
+/// a removed key's size and ETag are the last ones seen
+async fn run_hook(command: &str, bucket_name: &str, change: Change, key: &str, seen: &Seen) -> Result<(), Box<dyn Error>> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("S3TEST_CHANGE", change.name())
+        .env("S3TEST_BUCKET", bucket_name)
+        .env("S3TEST_KEY", key)
+        .env("S3TEST_SIZE", seen.size.to_string())
+        .env("S3TEST_ETAG", &seen.e_tag)
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(format!("--exec exited with {}", status).into());
+    }
+    Ok(())
+}
+
+/// lists prefix every interval and prints a line for each key added, removed or modified since
+/// the last listing, until interrupted. listings that fail are reported and tried again at the
+/// next interval, so a brief outage doesn't end the watch. changes made and undone between two
+/// listings aren't seen
+pub async fn run(client: &Client, bucket_name: &str, prefix: &str, interval: Duration, exec: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let mut last = snapshot(client, bucket_name, prefix).await?;
+    println!("watching {}/{}: {} objects", bucket_name, prefix, last.len());
+    loop {
+        stop::within(tokio::time::sleep(interval)).await?;
+        let current = match stop::within(snapshot(client, bucket_name, prefix)).await? {
+            Ok(current) => current,
+            Err(err) => {
+                eprintln!("failed to list {}: {}", prefix, ErrorDetails::from_error(err.as_ref()));
+                prometheus::count_error();
+                continue;
+            }
+        };
+        let changes = diff(&last, &current);
+        for (done, (change, key, seen)) in changes.iter().copied().enumerate() {
+            prometheus::set_queue_depth(changes.len() - done);
+            match change {
+                Change::Removed => println!("removed {}", key),
+                _ => println!("{} {}: {} bytes, etag {}", change.name(), key, seen.size, seen.e_tag),
+            }
+            if let Some(command) = exec {
+                if let Err(err) = stop::within(run_hook(command, bucket_name, change, key, seen)).await? {
+                    eprintln!("hook for {} {}: {}", change.name(), key, err);
+                    prometheus::count_error();
+                }
+            }
+        }
+        prometheus::set_queue_depth(0);
+        last = current;
+    }
+}
@@ -0,0 +1,93 @@
+use std::error::Error;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::types::{ErrorDocument, IndexDocument, WebsiteConfiguration};
+use clap::Subcommand;
+use mime_guess::Mime;
+
+/// types that are text even though they aren't text/*
+const TEXT_TYPES: &[&str] = &["application/javascript", "application/json", "application/xml", "image/svg+xml"];
+
+/// how long browsers may cache anything but pages. pages are revalidated so a publish shows up at once
+const ASSET_MAX_AGE: u32 = 24 * 60 * 60;
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum WebsiteAction {
+    /// show the bucket's website configuration
+    Get,
+    /// serve the bucket as a website
+    Set {
+        #[arg(long, default_value = "index.html", help = "document served for requests for a directory")]
+        index: String,
+        #[arg(long, value_name = "KEY", help = "object served for errors, e.g. 404.html")]
+        error: Option<String>,
+    },
+    /// stop serving the bucket as a website
+    Delete,
+}
+
+/// the Content-Type to publish a file of type mime with, giving text a charset
+pub fn content_type(mime: &Mime) -> String {
+    let essence = mime.essence_str();
+    if essence.starts_with("text/") || TEXT_TYPES.contains(&essence) {
+        format!("{}; charset=utf-8", essence)
+    } else {
+        essence.to_string()
+    }
+}
+
+/// the Cache-Control to publish a file of type mime with
+pub fn cache_control(mime: &Mime) -> String {
+    if mime.essence_str() == "text/html" {
+        "no-cache".to_string()
+    } else {
+        format!("public, max-age={}", ASSET_MAX_AGE)
+    }
+}
+
+pub async fn run(client: &Client, bucket_name: &str, action: &WebsiteAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        WebsiteAction::Get => {
+            let result = match client.get_bucket_website().bucket(bucket_name).send().await {
+                Ok(result) => result,
+                Err(SdkError::ServiceError(err)) if err.err().code() == Some("NoSuchWebsiteConfiguration") => {
+                    println!("{} isn't configured as a website", bucket_name);
+                    return Ok(());
+                }
+                Err(err) => return Err(err.into()),
+            };
+            if let Some(redirect) = result.redirect_all_requests_to() {
+                println!("redirect all requests to: {}{}", redirect.protocol().map(|p| format!("{}://", p.as_str())).unwrap_or_default(),
+                         redirect.host_name().unwrap_or_default());
+            }
+            if let Some(index) = result.index_document().and_then(|index| index.suffix()) {
+                println!("index document: {}", index);
+            }
+            if let Some(error) = result.error_document().and_then(|error| error.key()) {
+                println!("error document: {}", error);
+            }
+            let rules = result.routing_rules().unwrap_or_default();
+            if !rules.is_empty() {
+                println!("{} routing rules", rules.len());
+            }
+        }
+        WebsiteAction::Set { index, error } => {
+            let configuration = WebsiteConfiguration::builder()
+                .index_document(IndexDocument::builder().suffix(index).build())
+                .set_error_document(error.as_ref().map(|key| ErrorDocument::builder().key(key).build()))
+                .build();
+            client.put_bucket_website()
+                .bucket(bucket_name)
+                .website_configuration(configuration)
+                .send()
+                .await?;
+            println!("{} is serving {} as its index{}", bucket_name, index,
+                     error.as_ref().map(|key| format!(" and {} for errors", key)).unwrap_or_default());
+        }
+        WebsiteAction::Delete => {
+            client.delete_bucket_website().bucket(bucket_name).send().await?;
+            println!("removed the website configuration of {}", bucket_name);
+        }
+    }
+    Ok(())
+}